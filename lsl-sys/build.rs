@@ -12,77 +12,102 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use flate2::read::GzDecoder;
-use std::fs::File;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+//! Locates or builds liblsl.
+//!
+//! * `system`: links an installed liblsl, found under `$LSL_DIR` or via pkg-config
+//! * `vendored` (default): builds the bundled `liblsl-1.16.2.tar.gz` with cmake and links it
+//!   statically.  If both features are enabled, an installed liblsl is preferred.
+//! * `bindgen`: regenerates the bindings from the headers instead of using the pre-generated
+//!   `src/lsl_bindings.rs`, which requires libclang
+
 use std::env;
-use tar::Archive;
+#[cfg(feature = "vendored")]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(feature = "vendored")]
+use std::process::Command;
+
+type BuildResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[cfg(feature = "vendored")]
+const LSL_VERSION: &str = "1.16.2";
+
+/// Runs a build tool to completion, turning a failure to launch it or a non-zero exit status into
+/// an error instead of carrying on with a half-built library.
+#[cfg(feature = "vendored")]
+fn run(command: &mut Command) -> BuildResult<()> {
+    let status = command.status().map_err(|e| {
+        format!(
+            "couldn't run {:?} (is it installed and on PATH?): {}",
+            command, e
+        )
+    })?;
+    if !status.success() {
+        return Err(format!("{:?} failed with {}", command, status).into());
+    }
+    Ok(())
+}
 
-fn build_lsl_unix(lsl_dir: PathBuf, lsl_build_dir: PathBuf) {
-    Command::new("cmake")
-        .arg(&lsl_dir)
+#[cfg(feature = "vendored")]
+fn build_lsl_unix(lsl_dir: &Path, lsl_build_dir: &Path) -> BuildResult<()> {
+    run(Command::new("cmake")
+        .arg(lsl_dir)
         .arg("-DLSL_BUILD_STATIC=1")
         .arg("-DBOOST_ALL_NO_LIB=1")
-        .current_dir(&lsl_build_dir)
-        .spawn()
-        .expect("Can't spawn subprocess.")
-        .wait();
-
-    Command::new("make")
-        .current_dir(&lsl_build_dir)
-        //.arg(format!("-j{}", num_cpus::get() - 1))
-        .spawn()
-        .expect("Can't spawn subprocess.")
-        .wait();
+        .current_dir(lsl_build_dir))?;
+
+    let mut make = Command::new("make");
+    make.current_dir(lsl_build_dir);
+    //make.arg(format!("-j{}", num_cpus::get() - 1));
+    run(&mut make)
 }
 
-fn build_lsl_windows(lsl_dir: PathBuf, lsl_lib_dir: PathBuf) {
+#[cfg(feature = "vendored")]
+fn build_lsl_windows(lsl_dir: &Path, lsl_lib_dir: &Path) -> BuildResult<()> {
     println!("cargo:rustc-link-lib=static:-bundle=winmm");
     println!("cargo:rustc-link-lib=static:-bundle=iphlpapi");
     println!("cargo:rustc-link-search={}", lsl_lib_dir.display());
-    Command::new("cmake")
-        .arg(&lsl_dir)
-        .arg("-B build")
+
+    run(Command::new("cmake")
+        .arg(lsl_dir)
+        .args(["-B", "build"])
         .arg("-DLSL_BUILD_STATIC=1")
-        .arg("-G Visual Studio 17 2022")
-        .arg("-A x64")
-        .current_dir(&lsl_dir)
-        .spawn()
-        .expect("Can't spawn subprocess.")
-        .wait()
-        .expect(&("Cannot execute command, path: ".to_owned() + &env::var("PATH").unwrap()));
-
-    Command::new("cmake")
+        .args(["-G", "Visual Studio 17 2022"])
+        .args(["-A", "x64"])
+        .current_dir(lsl_dir))?;
+
+    run(Command::new("cmake")
         .arg("--build")
         .arg("build")
-        .arg("--config Release")
-        .current_dir(&lsl_dir)
-        .spawn()
-        .expect("Can't spawn subprocess.")
-        .wait()
-        .expect(&("Cannot execute command, path: ".to_owned() + &env::var("PATH").unwrap()));
+        .args(["--config", "Release"])
+        .current_dir(lsl_dir))
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let out_dir: PathBuf = std::env::var("OUT_DIR").unwrap().into();
-    let package_dir: PathBuf = std::env::var("CARGO_MANIFEST_DIR").unwrap().into();
+/// Builds the bundled liblsl and returns its include directory
+#[cfg(feature = "vendored")]
+fn build_vendored() -> BuildResult<PathBuf> {
+    use flate2::read::GzDecoder;
+    use std::fs::File;
+    use tar::Archive;
 
-    let lsl_dir = out_dir.join("liblsl-1.16.2");
+    let out_dir: PathBuf = env::var("OUT_DIR")?.into();
+    let package_dir: PathBuf = env::var("CARGO_MANIFEST_DIR")?.into();
+    let tarball = package_dir.join(format!("liblsl-{}.tar.gz", LSL_VERSION));
+    println!("cargo:rerun-if-changed={}", tarball.display());
+
+    let lsl_dir = out_dir.join(format!("liblsl-{}", LSL_VERSION));
     let lsl_build_dir = lsl_dir.join("build");
     let lsl_include_dir = lsl_dir.join("include");
     let lsl_lib_dir = lsl_build_dir.join("Release");
 
     if !lsl_dir.exists() {
-        let tar_gz = File::open(package_dir.join("liblsl-1.16.2.tar.gz"))?;
+        let tar_gz = File::open(&tarball)
+            .map_err(|e| format!("couldn't open {}: {}", tarball.display(), e))?;
         let tar = GzDecoder::new(tar_gz);
         let mut archive = Archive::new(tar);
         archive.unpack(&out_dir)?;
     }
 
-    // Tell cargo to invalidate the built crate whenever the wrapper changes
-    println!("cargo:rerun-if-changed=wrapper.h");
-
     println!("cargo:rustc-link-search={}", lsl_build_dir.display());
     println!("cargo:rustc-link-lib=static=lsl");
 
@@ -92,29 +117,122 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if cfg!(target_os = "linux") {
         println!("cargo:rustc-link-lib=stdc++");
-        build_lsl_unix(lsl_dir, lsl_build_dir);
+        build_lsl_unix(&lsl_dir, &lsl_build_dir)?;
     } else if cfg!(target_os = "macos") {
         println!("cargo:rustc-link-lib=c++");
-        build_lsl_unix(lsl_dir, lsl_build_dir);
+        build_lsl_unix(&lsl_dir, &lsl_build_dir)?;
     } else if cfg!(target_os = "windows") {
-        build_lsl_windows(lsl_dir, lsl_lib_dir);
+        build_lsl_windows(&lsl_dir, &lsl_lib_dir)?;
     } else {
-        println!("cargo:warning=Unsupported operating system.") 
+        return Err(
+            "building the vendored liblsl isn't supported on this OS; use the `system` feature"
+                .into(),
+        );
+    }
+
+    Ok(lsl_include_dir)
+}
+
+/// Looks for an installed liblsl, first under `$LSL_DIR` and then through pkg-config, and
+/// returns its include directories if found
+#[cfg(feature = "system")]
+fn find_system() -> BuildResult<Option<Vec<PathBuf>>> {
+    println!("cargo:rerun-if-env-changed=LSL_DIR");
+
+    if let Some(lsl_dir) = env::var_os("LSL_DIR") {
+        let lsl_dir = PathBuf::from(lsl_dir);
+        let lib_dirs: Vec<PathBuf> = ["lib", "lib64"]
+            .iter()
+            .map(|lib| lsl_dir.join(lib))
+            .filter(|lib_dir| lib_dir.exists())
+            .collect();
+        if lib_dirs.is_empty() {
+            return Err(format!(
+                "LSL_DIR is set, but {} has no lib directory",
+                lsl_dir.display()
+            )
+            .into());
+        }
+        for lib_dir in lib_dirs {
+            println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        }
+        println!("cargo:rustc-link-lib=lsl");
+        return Ok(Some(vec![lsl_dir.join("include")]));
+    }
+
+    // upstream liblsl doesn't install a .pc file, but some distributions add one under either name
+    for name in &["liblsl", "lsl"] {
+        if let Ok(library) = pkg_config::Config::new()
+            .atleast_version("1.14")
+            .probe(name)
+        {
+            return Ok(Some(library.include_paths));
+        }
     }
-     
-    let bindings = bindgen::Builder::default()
-        .clang_arg(format!("-I{}", lsl_include_dir.display()))
-        .header("wrapper.h")
+
+    Ok(None)
+}
+
+#[cfg(feature = "bindgen")]
+fn generate_bindings(include_dirs: &[PathBuf]) -> BuildResult<()> {
+    let out_dir: PathBuf = env::var("OUT_DIR")?.into();
+    let mut builder = bindgen::Builder::default().header("wrapper.h");
+    for include_dir in include_dirs {
+        builder = builder.clang_arg(format!("-I{}", include_dir.display()));
+    }
+
+    let bindings = builder
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
         .generate()
-        .expect("Unable to generate bindings");
+        .map_err(|_| "Unable to generate bindings")?;
+
+    bindings.write_to_file(out_dir.join("lsl_bindings.rs"))?;
+    Ok(())
+}
+
+/// Finds or builds liblsl according to the enabled features, and returns its include directories
+// which of the returns below is the last statement depends on the features
+#[allow(clippy::needless_return)]
+fn locate_lsl() -> BuildResult<Vec<PathBuf>> {
+    #[cfg(feature = "system")]
+    {
+        if let Some(include_dirs) = find_system()? {
+            return Ok(include_dirs);
+        }
+    }
+
+    #[cfg(feature = "vendored")]
+    {
+        return Ok(vec![build_vendored()?]);
+    }
+
+    #[cfg(all(feature = "system", not(feature = "vendored")))]
+    {
+        return Err(
+            "couldn't find liblsl: set LSL_DIR to its install prefix, or make it visible to pkg-config"
+                .into(),
+        );
+    }
+
+    #[cfg(not(any(feature = "system", feature = "vendored")))]
+    {
+        return Err("lsl-sys needs either the `vendored` or the `system` feature enabled".into());
+    }
+}
+
+fn main() -> BuildResult<()> {
+    // Tell cargo to invalidate the built crate whenever the wrapper changes
+    println!("cargo:rerun-if-changed=wrapper.h");
+
+    let include_dirs = locate_lsl()?;
+
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&include_dirs)?;
 
-    let lsl_binding = Path::new("lsl_bindings.rs");
-    bindings
-        .write_to_file(out_dir.join(lsl_binding))
-        .expect("Couldn't write bindings!");
+    #[cfg(not(feature = "bindgen"))]
+    let _ = include_dirs;
 
     Ok(())
 }
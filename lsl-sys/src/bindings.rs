@@ -12,4 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "bindgen")]
 include!(concat!(env!("OUT_DIR"), "/lsl_bindings.rs"));
+
+#[cfg(not(feature = "bindgen"))]
+include!("lsl_bindings.rs");
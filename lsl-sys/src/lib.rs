@@ -47,6 +47,12 @@ impl From<ffi::NulError> for Error {
     }
 }
 
+/// Returns the current time on LSL's local clock, in seconds.  Sample timestamps pushed to an
+/// outlet are expected to be on this clock.
+pub fn local_clock() -> f64 {
+    unsafe { bindings::lsl_local_clock() }
+}
+
 #[derive(Copy, Clone)]
 enum ChannelFormat {
     Undefined = 0,
@@ -97,6 +103,45 @@ impl<Format> StreamInfo<Format> {
     }
 }
 
+impl<Format> StreamInfo<Format> {
+    /// The `<desc>` element of the stream's metadata, where the stream's own fields go
+    pub fn desc(&mut self) -> XmlElement<'_> {
+        XmlElement {
+            handle: unsafe { bindings::lsl_get_desc(self.handle) },
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// An element of a stream's XML metadata, borrowed from its `StreamInfo`
+pub struct XmlElement<'a> {
+    handle: bindings::lsl_xml_ptr,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> XmlElement<'a> {
+    /// Appends an empty child element and returns it
+    pub fn append_child(&self, name: &str) -> Result<XmlElement<'a>> {
+        let name = ffi::CString::new(name)?;
+        Ok(XmlElement {
+            handle: unsafe { bindings::lsl_append_child(self.handle, name.as_ptr()) },
+            phantom: PhantomData,
+        })
+    }
+
+    /// Appends a child element holding `value`, and returns this element
+    pub fn append_child_value(&self, name: &str, value: &str) -> Result<XmlElement<'a>> {
+        let name = ffi::CString::new(name)?;
+        let value = ffi::CString::new(value)?;
+        Ok(XmlElement {
+            handle: unsafe {
+                bindings::lsl_append_child_value(self.handle, name.as_ptr(), value.as_ptr())
+            },
+            phantom: PhantomData,
+        })
+    }
+}
+
 impl StreamInfo<i32> {
     pub fn new(
         name: &str,
@@ -116,16 +161,56 @@ impl StreamInfo<i32> {
     }
 }
 
+impl StreamInfo<f32> {
+    pub fn new(
+        name: &str,
+        stream_type: &str,
+        channel_count: i32,
+        nominal_srate: f64,
+        source_id: &str,
+    ) -> Result<Self> {
+        StreamInfo::real_new(
+            name,
+            stream_type,
+            channel_count,
+            nominal_srate,
+            source_id,
+            ChannelFormat::Float32,
+        )
+    }
+}
+
+impl StreamInfo<String> {
+    pub fn new(
+        name: &str,
+        stream_type: &str,
+        channel_count: i32,
+        nominal_srate: f64,
+        source_id: &str,
+    ) -> Result<Self> {
+        StreamInfo::real_new(
+            name,
+            stream_type,
+            channel_count,
+            nominal_srate,
+            source_id,
+            ChannelFormat::String,
+        )
+    }
+}
+
 #[cfg(all(target_pointer_width = "64", target_os = "windows"))]
 type PtrWidth = u32;
 
-#[cfg(all(target_pointer_width = "64", any(target_os = "linux", target_os = "macos")))]
+#[cfg(all(
+    target_pointer_width = "64",
+    any(target_os = "linux", target_os = "macos")
+))]
 type PtrWidth = u64;
 
 #[cfg(target_pointer_width = "32")]
 type PtrWidth = u32;
 
-
 pub struct Outlet<Format> {
     info: StreamInfo<Format>,
     handle: bindings::lsl_outlet,
@@ -142,6 +227,19 @@ impl Outlet<i32> {
             )
         }
     }
+
+    /// Pushes a multiplexed chunk of samples with one timestamp per sample, so `data.len()` must
+    /// be `timestamps.len()` times the channel count.
+    pub fn push_chunk_with_timestamps(&self, data: &[i32], timestamps: &[f64]) -> i32 {
+        unsafe {
+            bindings::lsl_push_chunk_itn(
+                self.handle,
+                data.as_ptr(),
+                data.len() as PtrWidth,
+                timestamps.as_ptr(),
+            )
+        }
+    }
 }
 
 impl Outlet<f32> {
@@ -155,6 +253,29 @@ impl Outlet<f32> {
             )
         }
     }
+
+    /// Pushes a multiplexed chunk of samples with one timestamp per sample, so `data.len()` must
+    /// be `timestamps.len()` times the channel count.
+    pub fn push_chunk_with_timestamps(&self, data: &[f32], timestamps: &[f64]) -> i32 {
+        unsafe {
+            bindings::lsl_push_chunk_ftn(
+                self.handle,
+                data.as_ptr(),
+                data.len() as PtrWidth,
+                timestamps.as_ptr(),
+            )
+        }
+    }
+}
+
+impl Outlet<String> {
+    /// Pushes a sample of a single-channel string stream, such as a marker.  NUL bytes, which C
+    /// strings can't hold, are left out.
+    pub fn push_sample(&self, text: &str, timestamp: f64) -> i32 {
+        let text = ffi::CString::new(text.replace('\0', "")).unwrap_or_default();
+        let mut data = [text.as_ptr()];
+        unsafe { bindings::lsl_push_sample_strt(self.handle, data.as_mut_ptr(), timestamp) }
+    }
 }
 
 impl<Format> Outlet<Format> {
@@ -170,6 +291,10 @@ impl<Format> Outlet<Format> {
     }
 }
 
+// liblsl outlets may be used from any thread, and nothing here hands out the handle or the
+// stream info, so moving the outlet to another thread is sound
+unsafe impl<Format: Send> Send for Outlet<Format> {}
+
 impl<Format> Drop for Outlet<Format> {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,1047 @@
+/* automatically generated by rust-bindgen */
+
+// Pre-generated from liblsl 1.16.2 `lsl_c.h` so that building this crate does not require
+// libclang. Enable the `bindgen` feature to regenerate these at build time instead.
+
+pub const LSL_IRREGULAR_RATE: f64 = 0.0;
+pub const LSL_DEDUCED_TIMESTAMP: f64 = -1.0;
+pub const LSL_FOREVER: f64 = 32000000.0;
+pub const LSL_NO_PREFERENCE: u32 = 0;
+pub const LIBLSL_COMPILE_HEADER_VERSION: u32 = 114;
+pub type int8_t = ::std::os::raw::c_schar;
+pub type int16_t = ::std::os::raw::c_short;
+pub type int32_t = ::std::os::raw::c_int;
+pub type int64_t = ::std::os::raw::c_longlong;
+pub type uint32_t = ::std::os::raw::c_uint;
+pub const lsl_channel_format_t_cft_float32: lsl_channel_format_t = 1;
+pub const lsl_channel_format_t_cft_double64: lsl_channel_format_t = 2;
+pub const lsl_channel_format_t_cft_string: lsl_channel_format_t = 3;
+pub const lsl_channel_format_t_cft_int32: lsl_channel_format_t = 4;
+pub const lsl_channel_format_t_cft_int16: lsl_channel_format_t = 5;
+pub const lsl_channel_format_t_cft_int8: lsl_channel_format_t = 6;
+pub const lsl_channel_format_t_cft_int64: lsl_channel_format_t = 7;
+pub const lsl_channel_format_t_cft_undefined: lsl_channel_format_t = 0;
+pub const lsl_channel_format_t__cft_maxval: lsl_channel_format_t = 2130706432;
+pub type lsl_channel_format_t = u32;
+pub const lsl_processing_options_t_proc_none: lsl_processing_options_t = 0;
+pub const lsl_processing_options_t_proc_clocksync: lsl_processing_options_t = 1;
+pub const lsl_processing_options_t_proc_dejitter: lsl_processing_options_t = 2;
+pub const lsl_processing_options_t_proc_monotonize: lsl_processing_options_t = 4;
+pub const lsl_processing_options_t_proc_threadsafe: lsl_processing_options_t = 8;
+pub const lsl_processing_options_t_proc_ALL: lsl_processing_options_t = 15;
+pub const lsl_processing_options_t__proc_maxval: lsl_processing_options_t = 2130706432;
+pub type lsl_processing_options_t = u32;
+pub const lsl_error_code_t_lsl_no_error: lsl_error_code_t = 0;
+pub const lsl_error_code_t_lsl_timeout_error: lsl_error_code_t = -1;
+pub const lsl_error_code_t_lsl_lost_error: lsl_error_code_t = -2;
+pub const lsl_error_code_t_lsl_argument_error: lsl_error_code_t = -3;
+pub const lsl_error_code_t_lsl_internal_error: lsl_error_code_t = -4;
+pub const lsl_error_code_t__lsl_error_code_maxval: lsl_error_code_t = 2130706432;
+pub type lsl_error_code_t = i32;
+pub const lsl_transport_options_t_transp_default: lsl_transport_options_t = 0;
+pub const lsl_transport_options_t_transp_bufsize_samples: lsl_transport_options_t = 1;
+pub const lsl_transport_options_t_transp_bufsize_thousandths: lsl_transport_options_t = 2;
+pub const lsl_transport_options_t__lsl_transport_options_maxval: lsl_transport_options_t =
+    2130706432;
+pub type lsl_transport_options_t = u32;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct lsl_streaminfo_struct_ {
+    _unused: [u8; 0],
+}
+pub type lsl_streaminfo = *mut lsl_streaminfo_struct_;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct lsl_outlet_struct_ {
+    _unused: [u8; 0],
+}
+pub type lsl_outlet = *mut lsl_outlet_struct_;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct lsl_inlet_struct_ {
+    _unused: [u8; 0],
+}
+pub type lsl_inlet = *mut lsl_inlet_struct_;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct lsl_xml_ptr_struct_ {
+    _unused: [u8; 0],
+}
+pub type lsl_xml_ptr = *mut lsl_xml_ptr_struct_;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct lsl_continuous_resolver_ {
+    _unused: [u8; 0],
+}
+pub type lsl_continuous_resolver = *mut lsl_continuous_resolver_;
+extern "C" {
+    pub fn lsl_last_error() -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_protocol_version() -> int32_t;
+}
+extern "C" {
+    pub fn lsl_library_version() -> int32_t;
+}
+extern "C" {
+    pub fn lsl_library_info() -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_local_clock() -> f64;
+}
+extern "C" {
+    pub fn lsl_destroy_string(s: *mut ::std::os::raw::c_char);
+}
+extern "C" {
+    pub fn lsl_create_streaminfo(
+        name: *const ::std::os::raw::c_char,
+        type_: *const ::std::os::raw::c_char,
+        channel_count: int32_t,
+        nominal_srate: f64,
+        channel_format: lsl_channel_format_t,
+        source_id: *const ::std::os::raw::c_char,
+    ) -> lsl_streaminfo;
+}
+extern "C" {
+    pub fn lsl_destroy_streaminfo(info: lsl_streaminfo);
+}
+extern "C" {
+    pub fn lsl_copy_streaminfo(info: lsl_streaminfo) -> lsl_streaminfo;
+}
+extern "C" {
+    pub fn lsl_get_name(info: lsl_streaminfo) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_type(info: lsl_streaminfo) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_channel_count(info: lsl_streaminfo) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_get_nominal_srate(info: lsl_streaminfo) -> f64;
+}
+extern "C" {
+    pub fn lsl_get_channel_format(info: lsl_streaminfo) -> lsl_channel_format_t;
+}
+extern "C" {
+    pub fn lsl_get_source_id(info: lsl_streaminfo) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_version(info: lsl_streaminfo) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_get_created_at(info: lsl_streaminfo) -> f64;
+}
+extern "C" {
+    pub fn lsl_get_uid(info: lsl_streaminfo) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_session_id(info: lsl_streaminfo) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_hostname(info: lsl_streaminfo) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_desc(info: lsl_streaminfo) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_get_xml(info: lsl_streaminfo) -> *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_get_channel_bytes(info: lsl_streaminfo) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_get_sample_bytes(info: lsl_streaminfo) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_stream_info_matches_query(
+        info: lsl_streaminfo,
+        query: *const ::std::os::raw::c_char,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_streaminfo_from_xml(xml: *const ::std::os::raw::c_char) -> lsl_streaminfo;
+}
+extern "C" {
+    pub fn lsl_create_outlet(
+        info: lsl_streaminfo,
+        chunk_size: int32_t,
+        max_buffered: int32_t,
+    ) -> lsl_outlet;
+}
+extern "C" {
+    pub fn lsl_create_outlet_ex(
+        info: lsl_streaminfo,
+        chunk_size: int32_t,
+        max_buffered: int32_t,
+        flags: lsl_transport_options_t,
+    ) -> lsl_outlet;
+}
+extern "C" {
+    pub fn lsl_destroy_outlet(out: lsl_outlet);
+}
+extern "C" {
+    pub fn lsl_push_sample_f(out: lsl_outlet, data: *const f32) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_d(out: lsl_outlet, data: *const f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_l(out: lsl_outlet, data: *const int64_t) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_i(out: lsl_outlet, data: *const int32_t) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_s(out: lsl_outlet, data: *const int16_t) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_c(out: lsl_outlet, data: *const ::std::os::raw::c_char) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_str(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_v(out: lsl_outlet, data: *const ::std::os::raw::c_void) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_ft(out: lsl_outlet, data: *const f32, timestamp: f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_dt(out: lsl_outlet, data: *const f64, timestamp: f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_lt(out: lsl_outlet, data: *const int64_t, timestamp: f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_it(out: lsl_outlet, data: *const int32_t, timestamp: f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_st(out: lsl_outlet, data: *const int16_t, timestamp: f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_ct(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_strt(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_vt(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_void,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_ftp(
+        out: lsl_outlet,
+        data: *const f32,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_dtp(
+        out: lsl_outlet,
+        data: *const f64,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_ltp(
+        out: lsl_outlet,
+        data: *const int64_t,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_itp(
+        out: lsl_outlet,
+        data: *const int32_t,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_stp(
+        out: lsl_outlet,
+        data: *const int16_t,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_ctp(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_strtp(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_vtp(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_void,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_buf(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_buft(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_sample_buftp(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_f(
+        out: lsl_outlet,
+        data: *const f32,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_d(
+        out: lsl_outlet,
+        data: *const f64,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_l(
+        out: lsl_outlet,
+        data: *const int64_t,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_i(
+        out: lsl_outlet,
+        data: *const int32_t,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_s(
+        out: lsl_outlet,
+        data: *const int16_t,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_c(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_str(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ft(
+        out: lsl_outlet,
+        data: *const f32,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_dt(
+        out: lsl_outlet,
+        data: *const f64,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_lt(
+        out: lsl_outlet,
+        data: *const int64_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_it(
+        out: lsl_outlet,
+        data: *const int32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_st(
+        out: lsl_outlet,
+        data: *const int16_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ct(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_strt(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ftp(
+        out: lsl_outlet,
+        data: *const f32,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_dtp(
+        out: lsl_outlet,
+        data: *const f64,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ltp(
+        out: lsl_outlet,
+        data: *const int64_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_itp(
+        out: lsl_outlet,
+        data: *const int32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_stp(
+        out: lsl_outlet,
+        data: *const int16_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ctp(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_strtp(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ftn(
+        out: lsl_outlet,
+        data: *const f32,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_dtn(
+        out: lsl_outlet,
+        data: *const f64,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ltn(
+        out: lsl_outlet,
+        data: *const int64_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_itn(
+        out: lsl_outlet,
+        data: *const int32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_stn(
+        out: lsl_outlet,
+        data: *const int16_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ctn(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_strtn(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ftnp(
+        out: lsl_outlet,
+        data: *const f32,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_dtnp(
+        out: lsl_outlet,
+        data: *const f64,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ltnp(
+        out: lsl_outlet,
+        data: *const int64_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_itnp(
+        out: lsl_outlet,
+        data: *const int32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_stnp(
+        out: lsl_outlet,
+        data: *const int16_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_ctnp(
+        out: lsl_outlet,
+        data: *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_strtnp(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_buf(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        data_elements: ::std::os::raw::c_ulong,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_buft(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_buftp(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamp: f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_buftn(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_push_chunk_buftnp(
+        out: lsl_outlet,
+        data: *mut *const ::std::os::raw::c_char,
+        lengths: *const uint32_t,
+        data_elements: ::std::os::raw::c_ulong,
+        timestamps: *const f64,
+        pushthrough: int32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_have_consumers(out: lsl_outlet) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_wait_for_consumers(out: lsl_outlet, timeout: f64) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_get_info(out: lsl_outlet) -> lsl_streaminfo;
+}
+extern "C" {
+    pub fn lsl_create_inlet(
+        info: lsl_streaminfo,
+        max_buflen: int32_t,
+        max_chunklen: int32_t,
+        recover: int32_t,
+    ) -> lsl_inlet;
+}
+extern "C" {
+    pub fn lsl_create_inlet_ex(
+        info: lsl_streaminfo,
+        max_buflen: int32_t,
+        max_chunklen: int32_t,
+        recover: int32_t,
+        flags: lsl_transport_options_t,
+    ) -> lsl_inlet;
+}
+extern "C" {
+    pub fn lsl_destroy_inlet(in_: lsl_inlet);
+}
+extern "C" {
+    pub fn lsl_get_fullinfo(in_: lsl_inlet, timeout: f64, ec: *mut int32_t) -> lsl_streaminfo;
+}
+extern "C" {
+    pub fn lsl_open_stream(in_: lsl_inlet, timeout: f64, ec: *mut int32_t);
+}
+extern "C" {
+    pub fn lsl_close_stream(in_: lsl_inlet);
+}
+extern "C" {
+    pub fn lsl_time_correction(in_: lsl_inlet, timeout: f64, ec: *mut int32_t) -> f64;
+}
+extern "C" {
+    pub fn lsl_time_correction_ex(
+        in_: lsl_inlet,
+        remote_time: *mut f64,
+        uncertainty: *mut f64,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_set_postprocessing(in_: lsl_inlet, flags: uint32_t) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_pull_sample_f(
+        in_: lsl_inlet,
+        buffer: *mut f32,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_d(
+        in_: lsl_inlet,
+        buffer: *mut f64,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_l(
+        in_: lsl_inlet,
+        buffer: *mut int64_t,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_i(
+        in_: lsl_inlet,
+        buffer: *mut int32_t,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_s(
+        in_: lsl_inlet,
+        buffer: *mut int16_t,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_c(
+        in_: lsl_inlet,
+        buffer: *mut ::std::os::raw::c_char,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_str(
+        in_: lsl_inlet,
+        buffer: *mut *mut ::std::os::raw::c_char,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_buf(
+        in_: lsl_inlet,
+        buffer: *mut *mut ::std::os::raw::c_char,
+        buffer_lengths: *mut uint32_t,
+        buffer_elements: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_sample_v(
+        in_: lsl_inlet,
+        buffer: *mut ::std::os::raw::c_void,
+        buffer_bytes: int32_t,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> f64;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_f(
+        in_: lsl_inlet,
+        data_buffer: *mut f32,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_d(
+        in_: lsl_inlet,
+        data_buffer: *mut f64,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_l(
+        in_: lsl_inlet,
+        data_buffer: *mut int64_t,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_i(
+        in_: lsl_inlet,
+        data_buffer: *mut int32_t,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_s(
+        in_: lsl_inlet,
+        data_buffer: *mut int16_t,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_c(
+        in_: lsl_inlet,
+        data_buffer: *mut ::std::os::raw::c_char,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_str(
+        in_: lsl_inlet,
+        data_buffer: *mut *mut ::std::os::raw::c_char,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_pull_chunk_buf(
+        in_: lsl_inlet,
+        data_buffer: *mut *mut ::std::os::raw::c_char,
+        lengths_buffer: *mut uint32_t,
+        timestamp_buffer: *mut f64,
+        data_buffer_elements: ::std::os::raw::c_ulong,
+        timestamp_buffer_elements: ::std::os::raw::c_ulong,
+        timeout: f64,
+        ec: *mut int32_t,
+    ) -> ::std::os::raw::c_ulong;
+}
+extern "C" {
+    pub fn lsl_samples_available(in_: lsl_inlet) -> uint32_t;
+}
+extern "C" {
+    pub fn lsl_inlet_flush(in_: lsl_inlet) -> uint32_t;
+}
+extern "C" {
+    pub fn lsl_was_clock_reset(in_: lsl_inlet) -> uint32_t;
+}
+extern "C" {
+    pub fn lsl_smoothing_halftime(in_: lsl_inlet, value: f32) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_first_child(e: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_last_child(e: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_next_sibling(e: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_previous_sibling(e: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_parent(e: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_child(e: lsl_xml_ptr, name: *const ::std::os::raw::c_char) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_next_sibling_n(e: lsl_xml_ptr, name: *const ::std::os::raw::c_char) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_previous_sibling_n(
+        e: lsl_xml_ptr,
+        name: *const ::std::os::raw::c_char,
+    ) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_empty(e: lsl_xml_ptr) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_is_text(e: lsl_xml_ptr) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_name(e: lsl_xml_ptr) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_value(e: lsl_xml_ptr) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_child_value(e: lsl_xml_ptr) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_child_value_n(
+        e: lsl_xml_ptr,
+        name: *const ::std::os::raw::c_char,
+    ) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn lsl_append_child_value(
+        e: lsl_xml_ptr,
+        name: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+    ) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_prepend_child_value(
+        e: lsl_xml_ptr,
+        name: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+    ) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_set_child_value(
+        e: lsl_xml_ptr,
+        name: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_set_name(e: lsl_xml_ptr, rhs: *const ::std::os::raw::c_char) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_set_value(e: lsl_xml_ptr, rhs: *const ::std::os::raw::c_char) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_append_child(e: lsl_xml_ptr, name: *const ::std::os::raw::c_char) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_prepend_child(e: lsl_xml_ptr, name: *const ::std::os::raw::c_char) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_append_copy(e: lsl_xml_ptr, e2: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_prepend_copy(e: lsl_xml_ptr, e2: lsl_xml_ptr) -> lsl_xml_ptr;
+}
+extern "C" {
+    pub fn lsl_remove_child_n(e: lsl_xml_ptr, name: *const ::std::os::raw::c_char);
+}
+extern "C" {
+    pub fn lsl_remove_child(e: lsl_xml_ptr, e2: lsl_xml_ptr);
+}
+extern "C" {
+    pub fn lsl_create_continuous_resolver(forget_after: f64) -> lsl_continuous_resolver;
+}
+extern "C" {
+    pub fn lsl_create_continuous_resolver_byprop(
+        prop: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+        forget_after: f64,
+    ) -> lsl_continuous_resolver;
+}
+extern "C" {
+    pub fn lsl_create_continuous_resolver_bypred(
+        pred: *const ::std::os::raw::c_char,
+        forget_after: f64,
+    ) -> lsl_continuous_resolver;
+}
+extern "C" {
+    pub fn lsl_resolver_results(
+        res: lsl_continuous_resolver,
+        buffer: *mut lsl_streaminfo,
+        buffer_elements: uint32_t,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_destroy_continuous_resolver(res: lsl_continuous_resolver);
+}
+extern "C" {
+    pub fn lsl_resolve_all(
+        buffer: *mut lsl_streaminfo,
+        buffer_elements: uint32_t,
+        wait_time: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_resolve_byprop(
+        buffer: *mut lsl_streaminfo,
+        buffer_elements: uint32_t,
+        prop: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+        minimum: int32_t,
+        timeout: f64,
+    ) -> int32_t;
+}
+extern "C" {
+    pub fn lsl_resolve_bypred(
+        buffer: *mut lsl_streaminfo,
+        buffer_elements: uint32_t,
+        pred: *const ::std::os::raw::c_char,
+        minimum: int32_t,
+        timeout: f64,
+    ) -> int32_t;
+}
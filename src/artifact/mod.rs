@@ -0,0 +1,433 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detects artifacts such as eye blinks and movement, and marks them as events.
+//!
+//! An `ArtifactDetector` runs any number of detectors, each watching some of the channels in
+//! microvolts: `threshold` flags samples far from the channel's slow drift, `gradient` flags
+//! steps between samples that are too steep for EEG, and `blink` flags the brief, large, slow
+//! deflections that eye blinks leave on frontal and EOG channels.  Flagged samples less than
+//! 100 ms apart are merged into one event, e.g. `Artifact blink: Fp1, Fp2`, which travels with
+//! the stream to be written as an annotation or published as a marker.
+//!
+//! With masking, the channels that caught an artifact are zeroed over it and 100 ms either side.
+//! Samples are then held back long enough to mask the start of a blink once its end is seen.
+
+use log::debug;
+use std::collections::VecDeque;
+
+mod err;
+mod spec;
+
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+use crate::dsp::biquad::{Biquad, BiquadCascade};
+use crate::dsp::Filter;
+use crate::pipeline::{Event, TimedSample};
+pub use err::ArtifactError;
+pub use spec::{DetectorKind, DetectorSpec};
+
+pub type ArtifactResult<T> = Result<T, ArtifactError>;
+
+const ARTIFACT_TAG: &str = "artifact";
+
+// the drift thresholds are measured from, removed by a highpass at this frequency
+const DRIFT_CUTOFF: f64 = 0.1;
+// blinks are looked for below this frequency, where they carry most of their power
+const BLINK_CUTOFF: f64 = 10.0;
+const BLINK_MIN_SECONDS: f64 = 0.05;
+const BLINK_MAX_SECONDS: f64 = 0.5;
+// flags closer together than this are one event
+const MERGE_SECONDS: f64 = 0.1;
+// masked on either side of an artifact
+const MASK_PADDING_SECONDS: f64 = 0.1;
+
+/// Flagged samples, from `start` to `end` inclusive, on one channel
+struct Flag {
+    start: u64,
+    end: u64,
+    chan_idx: usize,
+}
+
+/// Consecutive flags of one detector, which become an event
+struct Episode {
+    kind: &'static str,
+    start: u64,
+    end: u64,
+    channels: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Deflection {
+    start: Option<u64>,
+    peak: f64,
+}
+
+struct Detector {
+    kind: DetectorKind,
+    channels: Vec<usize>,
+    sample_rate: f64,
+    // highpassed for thresholds, bandpassed for blinks
+    filter: Option<BiquadCascade>,
+    settled: bool,
+    frame: Vec<f64>,
+    previous: Option<Vec<f64>>,
+    deflections: Vec<Deflection>,
+    episode: Option<Episode>,
+}
+
+impl Detector {
+    fn new(spec: &DetectorSpec, config: &BoardConfig) -> ArtifactResult<Self> {
+        let channels = if spec.channels.is_empty() {
+            (0..NUM_CHANNELS)
+                .filter(|chan_idx| config.channels[*chan_idx].enabled)
+                .collect()
+        } else {
+            spec.channels
+                .iter()
+                .map(|name| {
+                    config.channel_index(name).ok_or_else(|| {
+                        ArtifactError::BadDetector(format!(
+                            "No channel '{}' for the {} detector; use a channel label or a \
+                             number from 1 to {}",
+                            name,
+                            spec.kind.name(),
+                            NUM_CHANNELS
+                        ))
+                    })
+                })
+                .collect::<ArtifactResult<Vec<_>>>()?
+        };
+
+        let sample_rate = config.sample_rate as f64;
+        let sections = match spec.kind {
+            DetectorKind::Threshold(_) => vec![Biquad::highpass1(DRIFT_CUTOFF, sample_rate)],
+            DetectorKind::Gradient(_) => Vec::new(),
+            DetectorKind::Blink(_) => {
+                let mut sections = vec![Biquad::highpass1(DRIFT_CUTOFF, sample_rate)];
+                sections.extend(Biquad::butterworth(2, BLINK_CUTOFF, sample_rate, false));
+                sections
+            }
+        };
+        let filter = if sections.is_empty() {
+            None
+        } else {
+            Some(BiquadCascade::new(sections, channels.len()))
+        };
+
+        Ok(Self {
+            kind: spec.kind,
+            sample_rate,
+            filter,
+            settled: false,
+            frame: vec![0.0; channels.len()],
+            previous: None,
+            deflections: channels.iter().map(|_| Deflection::default()).collect(),
+            episode: None,
+            channels,
+        })
+    }
+
+    /// Looks at sample `index`, appending what it flags, which for blinks may be earlier samples
+    fn update(&mut self, index: u64, microvolts: &[f64], flags: &mut Vec<Flag>) {
+        for (value, chan_idx) in self.frame.iter_mut().zip(&self.channels) {
+            *value = microvolts[*chan_idx];
+        }
+        if let Some(ref mut filter) = self.filter {
+            if !self.settled {
+                filter.settle(&self.frame);
+                self.settled = true;
+            }
+            filter.process(&mut self.frame);
+        }
+
+        match self.kind {
+            DetectorKind::Threshold(limit) => {
+                for (value, chan_idx) in self.frame.iter().zip(&self.channels) {
+                    if value.abs() > limit {
+                        flags.push(Flag {
+                            start: index,
+                            end: index,
+                            chan_idx: *chan_idx,
+                        });
+                    }
+                }
+            }
+            DetectorKind::Gradient(limit) => {
+                // per millisecond
+                let limit = limit * 1000.0 / self.sample_rate;
+                if let Some(ref previous) = self.previous {
+                    for ((value, previous), chan_idx) in
+                        self.frame.iter().zip(previous).zip(&self.channels)
+                    {
+                        if (value - previous).abs() > limit {
+                            flags.push(Flag {
+                                start: index,
+                                end: index,
+                                chan_idx: *chan_idx,
+                            });
+                        }
+                    }
+                }
+                self.previous = Some(self.frame.clone());
+            }
+            DetectorKind::Blink(limit) => {
+                let min_len = (BLINK_MIN_SECONDS * self.sample_rate).round() as u64;
+                let max_len = (BLINK_MAX_SECONDS * self.sample_rate).round() as u64;
+                // a blink runs from where the deflection passes half its limit to where it
+                // comes back
+                for ((value, deflection), chan_idx) in self
+                    .frame
+                    .iter()
+                    .zip(&mut self.deflections)
+                    .zip(&self.channels)
+                {
+                    let value = value.abs();
+                    match deflection.start {
+                        None if value > limit / 2.0 => {
+                            deflection.start = Some(index);
+                            deflection.peak = value;
+                        }
+                        Some(_) if value > limit / 2.0 => {
+                            deflection.peak = deflection.peak.max(value);
+                        }
+                        Some(start) => {
+                            let len = index - start;
+                            if deflection.peak > limit && len >= min_len && len <= max_len {
+                                flags.push(Flag {
+                                    start,
+                                    end: index - 1,
+                                    chan_idx: *chan_idx,
+                                });
+                            }
+                            deflection.start = None;
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Flags artifacts in a stream of samples, adding an event for each to the samples it passes
+/// on, and optionally masking them.  Samples are held back by one sample, so an artifact that
+/// runs to the end of the stream still has a sample to carry its event, or longer when masking.
+pub struct ArtifactDetector {
+    detectors: Vec<Detector>,
+    labels: Vec<String>,
+    scale: [f64; NUM_CHANNELS],
+    sample_rate: f64,
+    merge: u64,
+    mask: bool,
+    padding: u64,
+    // channels are masked up to but not including these samples
+    mask_end: [u64; NUM_CHANNELS],
+    hold: usize,
+    held: VecDeque<(u64, TimedSample)>,
+    // ended episodes waiting for a sample to carry them
+    ended: Vec<Episode>,
+    index: u64,
+    microvolts: [f64; NUM_CHANNELS],
+    flags: Vec<Flag>,
+    events: u64,
+}
+
+impl ArtifactDetector {
+    /// Detectors for the channels of `config`, which gives their labels and scales.  With
+    /// `mask`, the channels are zeroed where they have artifacts.
+    pub fn new(specs: &[DetectorSpec], config: &BoardConfig, mask: bool) -> ArtifactResult<Self> {
+        let detectors = specs
+            .iter()
+            .map(|spec| Detector::new(spec, config))
+            .collect::<ArtifactResult<Vec<_>>>()?;
+        let sample_rate = config.sample_rate as f64;
+        let mut scale = [0.0; NUM_CHANNELS];
+        for (scale, channel) in scale.iter_mut().zip(&config.channels) {
+            *scale = channel.microvolts_per_count();
+        }
+
+        let padding = (MASK_PADDING_SECONDS * sample_rate).round() as u64;
+        let hold = if mask {
+            // long enough to reach back over the padding to the start of the longest blink
+            let blinks = specs
+                .iter()
+                .any(|spec| matches!(spec.kind, DetectorKind::Blink(_)));
+            let longest = if blinks { BLINK_MAX_SECONDS } else { 0.0 };
+            ((longest + MASK_PADDING_SECONDS) * sample_rate).round() as usize + 1
+        } else {
+            1
+        };
+
+        Ok(Self {
+            detectors,
+            labels: config
+                .channels
+                .iter()
+                .map(|channel| channel.label.clone())
+                .collect(),
+            scale,
+            sample_rate,
+            merge: (MERGE_SECONDS * sample_rate).round() as u64,
+            mask,
+            padding,
+            mask_end: [0; NUM_CHANNELS],
+            hold,
+            held: VecDeque::with_capacity(hold + 1),
+            ended: Vec::new(),
+            index: 0,
+            microvolts: [0.0; NUM_CHANNELS],
+            flags: Vec::new(),
+            events: 0,
+        })
+    }
+
+    /// Seconds samples are held back by
+    pub fn delay(&self) -> f64 {
+        self.hold as f64 / self.sample_rate
+    }
+
+    /// Events added so far
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    fn mask_flag(&mut self, flag: &Flag) {
+        let first = flag.start.saturating_sub(self.padding);
+        let last = flag.end + self.padding;
+        for (index, timed) in &mut self.held {
+            if *index >= first && *index <= last {
+                timed.sample.channels[flag.chan_idx].sample = 0;
+            }
+        }
+        let end = &mut self.mask_end[flag.chan_idx];
+        *end = (*end).max(last + 1);
+    }
+
+    /// Moves ended episodes that `index` is at or past onto the sample as events
+    fn attach_events(&mut self, index: u64, timed: &mut TimedSample) {
+        let mut waiting = Vec::new();
+        for episode in std::mem::take(&mut self.ended) {
+            if episode.end > index {
+                waiting.push(episode);
+                continue;
+            }
+            let labels: Vec<&str> = episode
+                .channels
+                .iter()
+                .map(|chan_idx| self.labels[*chan_idx].as_str())
+                .collect();
+            let text = format!("Artifact {}: {}", episode.kind, labels.join(", "));
+            debug!(
+                target: ARTIFACT_TAG,
+                "{} over samples {}-{}", text, episode.start, episode.end
+            );
+            timed.events.push(Event {
+                onset: -((index - episode.start) as f64) / self.sample_rate,
+                duration: Some((episode.end - episode.start + 1) as f64 / self.sample_rate),
+                text,
+            });
+            self.events += 1;
+        }
+        self.ended = waiting;
+    }
+
+    fn release(&mut self, out: &mut Vec<TimedSample>) {
+        if let Some((index, mut timed)) = self.held.pop_front() {
+            self.attach_events(index, &mut timed);
+            out.push(timed);
+        }
+    }
+
+    /// Takes the next sample, appending any that are no longer held back to `out`
+    pub fn process_sample(&mut self, mut input: TimedSample, out: &mut Vec<TimedSample>) {
+        let index = self.index;
+        self.index += 1;
+        for ((value, channel), scale) in self
+            .microvolts
+            .iter_mut()
+            .zip(&input.sample.channels)
+            .zip(&self.scale)
+        {
+            *value = channel.sample as f64 * scale;
+        }
+
+        let mut flags = std::mem::take(&mut self.flags);
+        for detector in &mut self.detectors {
+            let first = flags.len();
+            detector.update(index, &self.microvolts, &mut flags);
+            let kind = detector.kind.name();
+            for flag in &flags[first..] {
+                match detector.episode {
+                    Some(ref mut episode) if flag.start <= episode.end + self.merge => {
+                        episode.start = episode.start.min(flag.start);
+                        episode.end = episode.end.max(flag.end);
+                        if !episode.channels.contains(&flag.chan_idx) {
+                            episode.channels.push(flag.chan_idx);
+                            episode.channels.sort_unstable();
+                        }
+                    }
+                    _ => {
+                        let episode = Episode {
+                            kind,
+                            start: flag.start,
+                            end: flag.end,
+                            channels: vec![flag.chan_idx],
+                        };
+                        if let Some(ended) = detector.episode.replace(episode) {
+                            self.ended.push(ended);
+                        }
+                    }
+                }
+            }
+            let over = match detector.episode {
+                Some(ref episode) => index > episode.end + self.merge,
+                None => false,
+            };
+            if over {
+                self.ended.extend(detector.episode.take());
+            }
+        }
+        if self.mask {
+            for flag in &flags {
+                self.mask_flag(flag);
+            }
+        }
+        flags.clear();
+        self.flags = flags;
+
+        if self.mask {
+            for (channel, end) in input.sample.channels.iter_mut().zip(&self.mask_end) {
+                if index < *end {
+                    channel.sample = 0;
+                }
+            }
+        }
+        self.held.push_back((index, input));
+        while self.held.len() > self.hold {
+            self.release(out);
+        }
+    }
+
+    /// Ends any artifacts in progress and appends the samples still held back
+    pub fn flush(&mut self, out: &mut Vec<TimedSample>) {
+        for detector in &mut self.detectors {
+            self.ended.extend(detector.episode.take());
+        }
+        // every episode ends by the last sample, which is still held to carry it
+        while !self.held.is_empty() {
+            self.release(out);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{ArtifactError, ArtifactResult};
+
+pub const DEFAULT_THRESHOLD: f64 = 100.0;
+pub const DEFAULT_GRADIENT: f64 = 50.0;
+pub const DEFAULT_BLINK: f64 = 80.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DetectorKind {
+    /// the signal, less its slow drift, beyond ± this many microvolts
+    Threshold(f64),
+    /// a step between consecutive samples steeper than this many microvolts per millisecond
+    Gradient(f64),
+    /// a 50-500 ms deflection below 10 Hz peaking beyond this many microvolts, in either
+    /// direction so an inverted EOG channel works too
+    Blink(f64),
+}
+
+impl DetectorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DetectorKind::Threshold(_) => "threshold",
+            DetectorKind::Gradient(_) => "gradient",
+            DetectorKind::Blink(_) => "blink",
+        }
+    }
+
+    pub fn limit(&self) -> f64 {
+        match self {
+            DetectorKind::Threshold(limit)
+            | DetectorKind::Gradient(limit)
+            | DetectorKind::Blink(limit) => *limit,
+        }
+    }
+}
+
+/// An artifact detector as given on the command line, e.g. `threshold:150`, `gradient` or
+/// `blink:100,channels=Fp1+Fp2`.
+///
+/// Without a limit, thresholds are 100 µV, gradients 50 µV/ms and blinks 80 µV.  Channels are
+/// named by their labels or numbers from 1, joined with `+`; without any, every enabled channel
+/// is watched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectorSpec {
+    pub kind: DetectorKind,
+    pub channels: Vec<String>,
+}
+
+impl FromStr for DetectorSpec {
+    type Err = ArtifactError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let head = parts.next().unwrap_or("");
+        let (kind, limit) = match head.find(':') {
+            Some(colon) => (&head[..colon], Some(&head[colon + 1..])),
+            None => (head, None),
+        };
+        let limit = match limit {
+            Some(limit) => Some(parse_limit(limit, s)?),
+            None => None,
+        };
+
+        let kind = match kind.trim().to_lowercase().as_str() {
+            "threshold" => DetectorKind::Threshold(limit.unwrap_or(DEFAULT_THRESHOLD)),
+            "gradient" => DetectorKind::Gradient(limit.unwrap_or(DEFAULT_GRADIENT)),
+            "blink" | "eog" => DetectorKind::Blink(limit.unwrap_or(DEFAULT_BLINK)),
+            other => {
+                return Err(ArtifactError::BadDetector(format!(
+                    "Unknown artifact detector '{}'; use threshold, gradient or blink",
+                    other
+                )))
+            }
+        };
+
+        let mut channels = Vec::new();
+        for option in parts {
+            let mut key_value = option.splitn(2, '=');
+            let key = key_value.next().unwrap_or("").trim();
+            let value = key_value.next().unwrap_or("");
+            match key {
+                "channels" => channels.extend(
+                    value
+                        .split('+')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string),
+                ),
+                other => {
+                    return Err(ArtifactError::BadDetector(format!(
+                        "Unknown option '{}' in artifact detector '{}'",
+                        other, s
+                    )))
+                }
+            }
+        }
+        Ok(Self { kind, channels })
+    }
+}
+
+fn parse_limit(value: &str, spec: &str) -> ArtifactResult<f64> {
+    match value.trim().parse::<f64>() {
+        Ok(limit) if limit > 0.0 => Ok(limit),
+        _ => Err(ArtifactError::BadDetector(format!(
+            "Bad limit '{}' in artifact detector '{}'; it should be a positive number",
+            value, spec
+        ))),
+    }
+}
+
+impl fmt::Display for DetectorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind.name(), self.kind.limit())?;
+        if !self.channels.is_empty() {
+            write!(f, ",channels={}", self.channels.join("+"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measures how fast samples can be read from the board: reads and decodes frames for a while
+//! without doing anything else with them, and reports the rate against the nominal one along with
+//! any samples lost on the way.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::info;
+
+use hackeeg::client::decode_rdatac_frame;
+use hackeeg::common::constants::ads1299;
+
+use super::{set_sample_rate, Connection, SAMPLE_RATES};
+
+const BENCH_TAG: &str = "bench";
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("bench")
+        .about("Measures the sample rate and throughput the board and connection can sustain")
+        .arg(
+            Arg::with_name("sps")
+                .short("s")
+                .long("sps")
+                .help("Samples per second; rates above 2000 need --mode msgpack")
+                .possible_values(&SAMPLE_RATES)
+                .default_value("16000"),
+        )
+        .arg(
+            Arg::with_name("seconds")
+                .short("t")
+                .long("seconds")
+                .help("Seconds to read for")
+                .default_value("10"),
+        )
+}
+
+pub fn run(matches: &ArgMatches, connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let sps = matches.value_of("sps").unwrap().parse::<u32>()?;
+    let seconds = matches.value_of("seconds").unwrap().parse::<f64>()?;
+
+    let mut client = connection.open()?;
+    set_sample_rate(&client, sps)?;
+    client.enable_all_channels(Some(ads1299::Gain::X1))?;
+    client.ensure_mode(connection.mode)?;
+
+    let sigint = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::SIGINT, Arc::clone(&sigint))?;
+
+    info!(
+        target: BENCH_TAG,
+        "Reading {} SPS in {:?} mode for {} seconds",
+        sps,
+        connection.mode,
+        seconds
+    );
+    let mut samples: u64 = 0;
+    let mut bytes: u64 = 0;
+    let mut read_errors: u64 = 0;
+    let mut decode_errors: u64 = 0;
+    let mut missed: u64 = 0;
+    let mut last_sample_number = None;
+
+    client.start()?;
+    client.rdatac()?;
+    let start = Instant::now();
+    let duration = Duration::from_secs_f64(seconds);
+    while start.elapsed() < duration && !sigint.load(Ordering::Relaxed) {
+        let frame = match client.read_rdatac_frame() {
+            Ok(frame) => frame,
+            Err(_) => {
+                read_errors += 1;
+                continue;
+            }
+        };
+        bytes += frame.len() as u64;
+        let sample = match decode_rdatac_frame(client.mode(), &frame) {
+            Ok(sample) => sample,
+            Err(_) => {
+                decode_errors += 1;
+                continue;
+            }
+        };
+        samples += 1;
+        if let Some(last) = last_sample_number {
+            let missing = sample.sample_number.wrapping_sub(last).wrapping_sub(1);
+            if missing < u32::MAX / 2 {
+                missed += missing as u64;
+            }
+        }
+        last_sample_number = Some(sample.sample_number);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    client.stop_and_sdatac()?;
+
+    let rate = samples as f64 / elapsed;
+    println!("Read for:       {:.2} s", elapsed);
+    println!(
+        "Samples:        {} ({:.1}/s, {:.1}% of {} SPS)",
+        samples,
+        rate,
+        rate / sps as f64 * 100.0,
+        sps
+    );
+    println!(
+        "Throughput:     {:.1} kB/s, {:.1} bytes per frame",
+        bytes as f64 / elapsed / 1e3,
+        if samples > 0 {
+            bytes as f64 / samples as f64
+        } else {
+            0.0
+        }
+    );
+    println!("Missed samples: {}", missed);
+    println!("Read errors:    {}", read_errors);
+    println!("Decode errors:  {}", decode_errors);
+    Ok(())
+}
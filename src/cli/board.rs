@@ -0,0 +1,165 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Commands that query or poke the board: `info`, `regs` and `blink`
+
+use std::error::Error;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use hackeeg::client::commands::responses::Status;
+use hackeeg::common::config::BoardConfig;
+use hackeeg::common::constants::ads1299;
+
+use super::Connection;
+
+pub fn info_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("info")
+        .about("Shows the firmware version, the ADC and how its channels are set up")
+}
+
+pub fn regs_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("regs")
+        .about("Prints the ADS1299's registers, after making any changes given")
+        .arg(
+            Arg::with_name("write")
+                .short("w")
+                .long("write")
+                .help("Write a register first, e.g. CONFIG1=0x96 or 0x05=0x60; may be given more than once")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+}
+
+pub fn blink_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("blink")
+        .about("Blinks the board's LED, to check the connection or find the board")
+        .arg(
+            Arg::with_name("count")
+                .short("n")
+                .long("count")
+                .help("How many times to blink")
+                .default_value("10"),
+        )
+}
+
+/// Describes the ADS1299's ID register, e.g. "ADS1299, 8 channels, revision 1"
+pub fn describe_id(id: u8) -> String {
+    // DEV_ID[1:0] in bits 3:2 is 0b11 for the ADS1299, and NU_CH[1:0] gives the channels
+    if id & 0x1c != 0x1c {
+        return format!("unknown device (ID 0x{:02x})", id);
+    }
+    let channels = match id & 0x03 {
+        0b00 => "4",
+        0b01 => "6",
+        0b10 => "8",
+        _ => "an unknown number of",
+    };
+    format!("ADS1299, {} channels, revision {}", channels, id >> 5)
+}
+
+pub fn info(connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let client = connection.open()?;
+    let version = client.version()?;
+    let registers = client.read_registers()?;
+
+    println!("Port:        {}", client.port_name());
+    println!("Firmware:    {}", version);
+    println!("ADC:         {}", describe_id(registers[0]));
+    let config = match BoardConfig::from_registers(&registers) {
+        Some(config) => config,
+        None => {
+            println!("The registers hold an invalid data rate or gain; see `hackeeg regs`");
+            return Ok(());
+        }
+    };
+    println!("Sample rate: {} SPS", config.sample_rate);
+    println!("Channels:");
+    for channel in &config.channels {
+        if channel.enabled {
+            println!(
+                "  {:<6} on   gain {:<2}  {} input",
+                channel.label,
+                channel.gain,
+                ads1299::input_name(channel.input)
+            );
+        } else {
+            println!("  {:<6} off", channel.label);
+        }
+    }
+    Ok(())
+}
+
+pub fn regs(matches: &ArgMatches, connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let client = connection.open()?;
+    if let Some(writes) = matches.values_of("write") {
+        for write in writes {
+            let (register, value) = parse_write(write)?;
+            client.wreg::<Status>(register, value)?.assert()?;
+        }
+    }
+
+    let registers = client.read_registers()?;
+    for (address, value) in registers.iter().enumerate() {
+        println!(
+            "0x{:02x}  {:<10}  0x{:02x}  {:08b}",
+            address,
+            ads1299::REGISTER_NAMES[address],
+            value,
+            value
+        );
+    }
+    Ok(())
+}
+
+/// Parses `REGISTER=VALUE`, where the register is a name or address
+fn parse_write(write: &str) -> Result<(u8, u8), Box<dyn Error>> {
+    let bad = || format!("Invalid --write '{}'; expected e.g. CONFIG1=0x96", write);
+    let (register, value) = match write.split_once('=') {
+        Some(parts) => parts,
+        None => return Err(bad().into()),
+    };
+    let register = register.trim();
+    let address = match ads1299::REGISTER_NAMES
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(register))
+    {
+        Some(address) => address as u8,
+        None => parse_byte(register).ok_or_else(bad)?,
+    };
+    if address as usize >= ads1299::REGISTER_NAMES.len() {
+        return Err(format!("There's no register at 0x{:02x}", address).into());
+    }
+    let value = parse_byte(value.trim()).ok_or_else(bad)?;
+    Ok((address, value))
+}
+
+/// Parses a byte written in hex (0x..), binary (0b..) or decimal
+fn parse_byte(text: &str) -> Option<u8> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        u8::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+pub fn blink(matches: &ArgMatches, connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let count = matches.value_of("count").unwrap().parse::<u32>()?;
+    let client = connection.open()?;
+    client.blink_test(count)?;
+    Ok(())
+}
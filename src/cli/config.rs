@@ -0,0 +1,168 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The config file, which holds the connection settings and defaults for each command's options,
+//! so they needn't be typed every time:
+//!
+//! ```toml
+//! [connection]
+//! # or "auto" to look for the board
+//! port = "/dev/ttyACM0"
+//! baud = 115200
+//! # read timeout in milliseconds
+//! timeout = 10
+//! mode = "msgpack"
+//!
+//! # options for a command, named as on its command line
+//! [stream]
+//! sps = 1000
+//! gain = 24
+//! filter = ["highpass:0.5", "notch:60"]
+//! tui = true
+//! ```
+//!
+//! It's read from `--config`, or else `$XDG_CONFIG_HOME/hackeeg/config.toml`,
+//! `~/.config/hackeeg/config.toml` or on Windows `%APPDATA%\hackeeg\config.toml`, if there is
+//! one.  Options given on the command line win.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+use toml::value::{Table, Value};
+
+use super::err::CliError;
+use super::CliResult;
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionConfig {
+    pub port: Option<String>,
+    pub baud: Option<u32>,
+    /// milliseconds
+    pub timeout: Option<u64>,
+    pub mode: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default)]
+    connection: ConnectionConfig,
+    #[serde(flatten)]
+    commands: BTreeMap<String, Table>,
+}
+
+#[derive(Default)]
+pub struct Config {
+    /// where it was read from, or `None` if there's no config file
+    pub path: Option<PathBuf>,
+    pub connection: ConnectionConfig,
+    commands: BTreeMap<String, Table>,
+}
+
+impl Config {
+    /// Reads `path`, or the default config file if there is one and `path` is `None`
+    pub fn load(path: Option<&str>) -> CliResult<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let text =
+            std::fs::read_to_string(&path).map_err(|e| CliError::ConfigIOError(path.clone(), e))?;
+        let file: ConfigFile =
+            toml::from_str(&text).map_err(|e| CliError::ConfigParseError(path.clone(), e))?;
+        Ok(Self {
+            path: Some(path),
+            connection: file.connection,
+            commands: file.commands,
+        })
+    }
+
+    /// Fails if the file has a section for something other than the connection or `commands`
+    pub fn check_commands(&self, commands: &[&str]) -> CliResult<()> {
+        match self
+            .commands
+            .keys()
+            .find(|name| !commands.contains(&name.as_str()))
+        {
+            Some(name) => Err(self.error(format!("there's no '{}' command", name))),
+            None => Ok(()),
+        }
+    }
+
+    /// Command line arguments for the defaults the file gives `command`, leaving out the options
+    /// already in `given`
+    pub fn default_args(&self, command: &str, given: &ArgMatches) -> CliResult<Vec<String>> {
+        let mut args = Vec::new();
+        let defaults = match self.commands.get(command) {
+            Some(defaults) => defaults,
+            None => return Ok(args),
+        };
+        for (key, value) in defaults {
+            let long = key.replace('_', "-");
+            if given.occurrences_of(long.replace('-', "_")) > 0 {
+                continue;
+            }
+            self.push_args(&mut args, command, &long, value)?;
+        }
+        Ok(args)
+    }
+
+    fn push_args(
+        &self,
+        args: &mut Vec<String>,
+        command: &str,
+        long: &str,
+        value: &Value,
+    ) -> CliResult<()> {
+        match value {
+            Value::Boolean(true) => args.push(format!("--{}", long)),
+            Value::Boolean(false) => {}
+            Value::String(text) => args.push(format!("--{}={}", long, text)),
+            Value::Integer(number) => args.push(format!("--{}={}", long, number)),
+            Value::Float(number) => args.push(format!("--{}={}", long, number)),
+            Value::Array(values) if values.iter().all(|value| !value.is_array()) => {
+                for value in values {
+                    self.push_args(args, command, long, value)?;
+                }
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "{} in [{}] must be a string, number, boolean or a list of them",
+                    long, command
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn error(&self, message: String) -> CliError {
+        CliError::BadConfig(self.path.clone().unwrap_or_default(), message)
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(".config"),
+            None => PathBuf::from(std::env::var_os("APPDATA")?),
+        },
+    };
+    Some(config_dir.join("hackeeg").join("config.toml"))
+}
@@ -0,0 +1,54 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum CliError {
+    ConfigIOError(PathBuf, std::io::Error),
+    ConfigParseError(PathBuf, toml::de::Error),
+    BadConfig(PathBuf, String),
+    NoPort,
+    NoBoard,
+    ListPortsError(serialport::Error),
+    BadOption(String),
+}
+
+impl std::error::Error for CliError {}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            CliError::ConfigIOError(path, e) => {
+                write!(f, "Couldn't read config file {}: {}", path.display(), e)
+            }
+            CliError::ConfigParseError(path, e) => {
+                write!(f, "Invalid config file {}: {}", path.display(), e)
+            }
+            CliError::BadConfig(path, message) => {
+                write!(f, "Invalid config file {}: {}", path.display(), message)
+            }
+            CliError::NoPort => write!(
+                f,
+                "No serial port given; pass --port, --port auto to look for the board, or set port in the [connection] section of the config file"
+            ),
+            CliError::NoBoard => write!(
+                f,
+                "Couldn't find a board: no Arduino Due port answered; `hackeeg ports` lists the serial ports"
+            ),
+            CliError::ListPortsError(e) => write!(f, "Couldn't list the serial ports: {}", e),
+            CliError::BadOption(message) => write!(f, "{}", message),
+        }
+    }
+}
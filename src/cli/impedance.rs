@@ -0,0 +1,152 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Electrode impedance, measured with the ADS1299's lead-off current sources.
+//!
+//! A 6 nA square wave at 31.25 Hz is driven into each channel's positive input, and the voltage
+//! it develops across the electrode is measured.  The fundamental of a square wave of amplitude
+//! `I·Z` has amplitude `4/π·I·Z`, which a single DFT bin over whole periods measures without
+//! leakage from the electrode's DC offset or other frequencies.
+
+use std::error::Error;
+use std::f64::consts::PI;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::info;
+
+use hackeeg::client::commands::responses::Status;
+use hackeeg::client::HackEEGClient;
+use hackeeg::common::config::{BoardConfig, DIGITAL_MAX, DIGITAL_MIN};
+use hackeeg::common::constants::ads1299;
+
+use super::{acquire, set_sample_rate, Connection};
+
+const IMPEDANCE_TAG: &str = "impedance";
+
+const SAMPLE_RATE: u32 = 250;
+// amps
+const LEAD_OFF_CURRENT: f64 = 6e-9;
+// ohms; below GOOD is good for wet electrodes, above FAIR is poor for any
+const GOOD: f64 = 10e3;
+const FAIR: f64 = 50e3;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("impedance")
+        .about("Measures each electrode's impedance with the ADS1299's lead-off current")
+        .arg(
+            Arg::with_name("seconds")
+                .short("t")
+                .long("seconds")
+                .help("Seconds to measure for; longer is less affected by noise")
+                .default_value("2"),
+        )
+        .arg(
+            Arg::with_name("channel_labels")
+                .long("channel-labels")
+                .help("Comma-separated channel labels, e.g. Fp1,Fp2,C3,C4,P7,P8,O1,O2")
+                .takes_value(true),
+        )
+}
+
+pub fn run(matches: &ArgMatches, connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let seconds = matches.value_of("seconds").unwrap().parse::<f64>()?;
+    let mut config = BoardConfig::new(SAMPLE_RATE, ads1299::Gain::X1);
+    if let Some(labels) = matches.value_of("channel_labels") {
+        let labels: Vec<&str> = labels.split(',').map(str::trim).collect();
+        config.set_labels(&labels);
+    }
+
+    // whole periods of the lead-off current, so the DFT bin doesn't leak
+    let period = (SAMPLE_RATE as f64 / ads1299::LEAD_OFF_AC_HZ).round() as usize;
+    let periods = ((seconds * ads1299::LEAD_OFF_AC_HZ) as usize).max(1);
+    let count = period * periods;
+
+    let client = connection.open()?;
+    set_sample_rate(&client, SAMPLE_RATE)?;
+    client.enable_all_channels(Some(ads1299::Gain::X1))?;
+    set_lead_off(&client, true)?;
+    info!(
+        target: IMPEDANCE_TAG,
+        "Measuring for {:.1} seconds",
+        count as f64 / SAMPLE_RATE as f64
+    );
+    let acquisition = acquire(&client, count);
+    // the current sources are switched off even if reading failed
+    set_lead_off(&client, false)?;
+    let acquisition = acquisition?;
+
+    println!("Channel   Impedance");
+    for (chan_idx, channel) in config.channels.iter().enumerate() {
+        let railed = acquisition.samples.iter().any(|sample| {
+            let counts = sample.channels[chan_idx].sample;
+            counts <= DIGITAL_MIN + 1 || counts >= DIGITAL_MAX - 1
+        });
+        if railed {
+            println!("{:<8}  not connected", channel.label);
+            continue;
+        }
+
+        let microvolts: Vec<f64> = acquisition
+            .samples
+            .iter()
+            .map(|sample| channel.to_microvolts(sample.channels[chan_idx].sample))
+            .collect();
+        let amplitude =
+            dft_amplitude(&microvolts, ads1299::LEAD_OFF_AC_HZ / SAMPLE_RATE as f64) * 1e-6;
+        let ohms = amplitude * PI / 4.0 / LEAD_OFF_CURRENT;
+        let verdict = if ohms < GOOD {
+            "good"
+        } else if ohms < FAIR {
+            "fair"
+        } else {
+            "poor"
+        };
+        println!("{:<8}  {:>7.1} kΩ  {}", channel.label, ohms / 1e3, verdict);
+    }
+    if acquisition.errors > 0 || acquisition.missed > 0 {
+        println!(
+            "({} frames couldn't be read and {} samples were missed, which may affect the results)",
+            acquisition.errors, acquisition.missed
+        );
+    }
+    Ok(())
+}
+
+/// Drives the lead-off current into every channel's positive input, or stops
+fn set_lead_off(client: &HackEEGClient, on: bool) -> Result<(), Box<dyn Error>> {
+    let (loff, sensp) = if on {
+        (ads1299::ILEAD_OFF_6NA | ads1299::FLEAD_OFF_AC_31_2HZ, 0xff)
+    } else {
+        (0x00, 0x00)
+    };
+    client
+        .wreg::<Status>(ads1299::GlobalSettings::LOFF as u8, loff)?
+        .assert()?;
+    client
+        .wreg::<Status>(ads1299::ChannelSettings::LOFF_SENSP as u8, sensp)?
+        .assert()?;
+    Ok(())
+}
+
+/// The amplitude of the component of `values` at `frequency` cycles per sample
+fn dft_amplitude(values: &[f64], frequency: f64) -> f64 {
+    let (re, im) = values
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (n, value)| {
+            let phase = 2.0 * PI * frequency * n as f64;
+            (re + value * phase.cos(), im - value * phase.sin())
+        });
+    2.0 * (re * re + im * im).sqrt() / values.len() as f64
+}
@@ -0,0 +1,251 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `hackeeg` program's subcommands, and what they share: the connection options and the
+//! config file.
+
+use std::error::Error;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches};
+use log::info;
+use serialport::prelude::SerialPortSettings;
+
+use hackeeg::client::commands::responses::Status;
+use hackeeg::client::decode_rdatac_frame;
+use hackeeg::client::modes::Mode;
+use hackeeg::client::ports::find_board;
+use hackeeg::client::sample::Sample;
+use hackeeg::client::HackEEGClient;
+use hackeeg::common::constants::ads1299;
+
+pub mod bench;
+pub mod board;
+pub mod config;
+mod err;
+pub mod impedance;
+pub mod ports;
+pub mod replay;
+pub mod selftest;
+pub mod stream;
+
+use config::ConnectionConfig;
+pub use err::CliError;
+
+pub type CliResult<T> = Result<T, CliError>;
+
+const CLI_TAG: &str = "cli";
+
+const DEFAULT_BAUD: u32 = 115200;
+// milliseconds
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// The rates the board can sample at, for `possible_values`
+pub const SAMPLE_RATES: [&str; 7] = ["250", "500", "1000", "2000", "4000", "8000", "16000"];
+
+/// Adds the options every command has: verbosity, the config file and the connection
+pub fn global_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("verbosity")
+            .short("v")
+            .multiple(true)
+            .global(true)
+            .help("Sets the level of verbosity"),
+    )
+    .arg(
+        Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .global(true)
+            .takes_value(true)
+            .help("Read connection settings and option defaults from this TOML file"),
+    )
+    .arg(
+        Arg::with_name("port")
+            .short("p")
+            .long("port")
+            .global(true)
+            .takes_value(true)
+            .help("The device path to the board's serial port, e.g. /dev/ttyACM0 or COM3, or auto to look for the board"),
+    )
+    .arg(
+        Arg::with_name("baud")
+            .short("b")
+            .long("baud")
+            .global(true)
+            .takes_value(true)
+            .help("The baud rate to connect at [default: 115200]"),
+    )
+    .arg(
+        Arg::with_name("timeout")
+            .long("timeout")
+            .global(true)
+            .takes_value(true)
+            .help("Milliseconds to wait for the board before a read fails [default: 10]"),
+    )
+    .arg(
+        Arg::with_name("mode")
+            .long("mode")
+            .global(true)
+            .takes_value(true)
+            .possible_values(&["jsonlines", "msgpack"])
+            .help("How the board sends samples; MessagePack is needed for high sample rates [default: jsonlines]"),
+    )
+}
+
+/// How to reach the board
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub port: String,
+    pub baud: u32,
+    pub timeout: Duration,
+    /// the mode samples are read in; commands are always sent as JSON Lines
+    pub mode: Mode,
+}
+
+impl Connection {
+    /// The connection options given to a command, falling back on the config file's.  A port of
+    /// `auto` is looked for among the Arduino Due ports.
+    pub fn from_matches(matches: &ArgMatches, config: &ConnectionConfig) -> CliResult<Self> {
+        let settings = port_settings(matches, config)?;
+        let port = match matches.value_of("port").or(config.port.as_deref()) {
+            Some("auto") => {
+                let port = find_board(&settings)
+                    .map_err(CliError::ListPortsError)?
+                    .ok_or(CliError::NoBoard)?;
+                info!(target: CLI_TAG, "Found the board on {}", port);
+                port
+            }
+            Some(port) => port.to_string(),
+            None => return Err(CliError::NoPort),
+        };
+        let mode = match matches.value_of("mode").or(config.mode.as_deref()) {
+            Some("jsonlines") | None => Mode::JsonLines,
+            Some("msgpack") => Mode::MsgPack,
+            Some(other) => {
+                return Err(CliError::BadOption(format!(
+                    "Unknown mode '{}'; use jsonlines or msgpack",
+                    other
+                )))
+            }
+        };
+        Ok(Self {
+            port,
+            baud: settings.baud_rate,
+            timeout: settings.timeout,
+            mode,
+        })
+    }
+
+    /// Opens the port and puts the board in JSON Lines mode, ready for commands
+    pub fn open(&self) -> Result<HackEEGClient, Box<dyn Error>> {
+        let settings = SerialPortSettings {
+            baud_rate: self.baud,
+            timeout: self.timeout,
+            ..SerialPortSettings::default()
+        };
+        HackEEGClient::new(&self.port, &settings)
+    }
+}
+
+/// The serial port settings given to a command, falling back on the config file's
+pub fn port_settings(
+    matches: &ArgMatches,
+    config: &ConnectionConfig,
+) -> CliResult<SerialPortSettings> {
+    let baud = match matches.value_of("baud") {
+        Some(baud) => parse_option("baud", baud)?,
+        None => config.baud.unwrap_or(DEFAULT_BAUD),
+    };
+    let timeout = match matches.value_of("timeout") {
+        Some(timeout) => parse_option("timeout", timeout)?,
+        None => config.timeout.unwrap_or(DEFAULT_TIMEOUT),
+    };
+    Ok(SerialPortSettings {
+        baud_rate: baud,
+        timeout: Duration::from_millis(timeout),
+        ..SerialPortSettings::default()
+    })
+}
+
+fn parse_option<T: std::str::FromStr>(name: &str, value: &str) -> CliResult<T> {
+    value
+        .parse()
+        .map_err(|_| CliError::BadOption(format!("Invalid --{} '{}'", name, value)))
+}
+
+/// Sets the board's data rate, one of `SAMPLE_RATES`
+pub fn set_sample_rate(client: &HackEEGClient, sps: u32) -> Result<(), Box<dyn Error>> {
+    let sample_mode = ads1299::Speed::from(sps) as u8 | ads1299::CONFIG1_const;
+    client
+        .wreg::<Status>(ads1299::GlobalSettings::CONFIG1 as u8, sample_mode)?
+        .assert()?;
+    Ok(())
+}
+
+/// Samples read by `acquire`, and how the reading went
+pub struct Acquisition {
+    pub samples: Vec<Sample>,
+    /// frames that couldn't be read or decoded
+    pub errors: u64,
+    /// gaps in the sample numbers
+    pub missed: u64,
+}
+
+/// Reads `count` samples with the board's current settings, then stops
+pub fn acquire(client: &HackEEGClient, count: usize) -> Result<Acquisition, Box<dyn Error>> {
+    let mut acquisition = Acquisition {
+        samples: Vec::with_capacity(count),
+        errors: 0,
+        missed: 0,
+    };
+    // reads time out quickly, so a board that isn't sending runs up errors fast
+    let max_errors = count as u64 + 100;
+
+    client.start()?;
+    client.rdatac()?;
+    while acquisition.samples.len() < count && acquisition.errors < max_errors {
+        let sample = match client
+            .read_rdatac_frame()
+            .and_then(|frame| decode_rdatac_frame(client.mode(), &frame))
+        {
+            Ok(sample) => sample,
+            Err(_) => {
+                acquisition.errors += 1;
+                continue;
+            }
+        };
+        if let Some(last) = acquisition.samples.last() {
+            let missing = sample
+                .sample_number
+                .wrapping_sub(last.sample_number)
+                .wrapping_sub(1);
+            if missing < u32::MAX / 2 {
+                acquisition.missed += missing as u64;
+            }
+        }
+        acquisition.samples.push(sample);
+    }
+    client.stop_and_sdatac()?;
+
+    if acquisition.samples.len() < count {
+        return Err(format!(
+            "The board sent {} of {} samples before giving up",
+            acquisition.samples.len(),
+            count
+        )
+        .into());
+    }
+    Ok(acquisition)
+}
@@ -0,0 +1,86 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `ports` command, which lists the serial ports a board might be on
+
+use std::error::Error;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serialport::SerialPortType;
+
+use hackeeg::client::ports;
+
+use super::config::ConnectionConfig;
+use super::{port_settings, CliError};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("ports")
+        .about("Lists the serial ports, marking the Arduino Due ones a board could be on")
+        .arg(
+            Arg::with_name("probe").long("probe").help(
+                "Send a nop to each Arduino Due port to see whether HackEEG firmware answers",
+            ),
+        )
+}
+
+pub fn run(matches: &ArgMatches, config: &ConnectionConfig) -> Result<(), Box<dyn Error>> {
+    let settings = port_settings(matches, config)?;
+    let probing = matches.is_present("probe");
+    let available = serialport::available_ports().map_err(CliError::ListPortsError)?;
+    if available.is_empty() {
+        println!("No serial ports found");
+        return Ok(());
+    }
+
+    println!(
+        "{:<20}  {:<9}  {:<9}  {:<20}  Description",
+        "Port", "Type", "VID:PID", "Serial number"
+    );
+    for port in &available {
+        let (kind, ids, serial_number, product) = match &port.port_type {
+            SerialPortType::UsbPort(usb) => (
+                "USB",
+                format!("{:04x}:{:04x}", usb.vid, usb.pid),
+                usb.serial_number.clone().unwrap_or_default(),
+                [usb.manufacturer.as_deref(), usb.product.as_deref()]
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            SerialPortType::PciPort => ("PCI", String::new(), String::new(), String::new()),
+            SerialPortType::BluetoothPort => {
+                ("Bluetooth", String::new(), String::new(), String::new())
+            }
+            SerialPortType::Unknown => ("unknown", String::new(), String::new(), String::new()),
+        };
+        let description = match ports::due_port(port) {
+            Some(due) if probing => {
+                if ports::probe(&port.port_name, &settings) {
+                    format!("{}, HackEEG firmware answered", due)
+                } else {
+                    format!("{}, no answer", due)
+                }
+            }
+            Some(due) => due.to_string(),
+            None => product,
+        };
+        println!(
+            "{:<20}  {:<9}  {:<9}  {:<20}  {}",
+            port.port_name, kind, ids, serial_number, description
+        );
+    }
+    Ok(())
+}
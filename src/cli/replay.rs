@@ -0,0 +1,329 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use log::{info, warn};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+#[cfg(feature = "lsl-sys")]
+use hackeeg::clock::{self, ClockSync};
+use hackeeg::common::config::BoardConfig;
+use hackeeg::common::constants::{ads1299, NUM_CHANNELS};
+#[cfg(feature = "lsl-sys")]
+use hackeeg::common::metadata::StreamMetadata;
+use hackeeg::dsp::FilterChain;
+#[cfg(feature = "lsl-sys")]
+use hackeeg::lsl::ChunkedOutlet;
+#[cfg(feature = "lsl-sys")]
+use hackeeg::pipeline::{LslSink, Transform};
+use hackeeg::pipeline::{
+    Overflow, Pipeline, PrintSink, Source, Stage, TimedSample, ZeroPhaseFilter,
+};
+use hackeeg::record::{RecordError, Replayer};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::stream::{
+    artifact_detector, filter_specs, load_montage, sink_options, warn_dropped, writer_sink,
+};
+
+const REPLAY_TAG: &str = "replay";
+#[cfg(feature = "lsl-sys")]
+const DEFAULT_STREAM_NAME: &str = "HackEEG";
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    let app = SubCommand::with_name("replay")
+        .about("Plays back a recording made with hackeeg record or hackeeg stream --record")
+        .arg(
+            Arg::with_name("recording")
+                .help("The recording file to play back")
+                .required(true),
+        )
+        .arg(Arg::with_name("realtime").short("R").long("realtime").help(
+            "Play back at the speed the samples were recorded, rather than as fast as possible",
+        ))
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Write samples to this file, in the format given by its extension (.edf, .bdf, .csv, .tsv, .xdf, .npy, .npz, .wav, .rf64, OpenBCI GUI .txt, and with the arrow feature .arrow, .parquet)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("units")
+                .long("units")
+                .help("Units for channel values in output files that offer a choice: counts or uv")
+                .default_value("counts"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .help("Comma-separated columns for CSV/TSV output, from sample_number, timestamp, host_time, channels, ch1-ch8, ads_gpio, loff_statp, loff_statn")
+                .default_value("sample_number,timestamp,host_time,channels"),
+        )
+        .arg(
+            Arg::with_name("wav_encoding")
+                .long("wav-encoding")
+                .help("Sample encoding for WAV output: pcm32 or float32")
+                .default_value("pcm32"),
+        )
+        .arg(
+            Arg::with_name("wav_scale")
+                .long("wav-scale")
+                .help("Multiplier for WAV samples, where 1 maps the ADC's full scale to the audio full scale")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("channel_labels")
+                .long("channel-labels")
+                .help("Comma-separated channel labels for output files, e.g. Fp1,Fp2,C3,C4,P7,P8,O1,O2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("montage")
+                .long("montage")
+                .help("Re-reference the channels with the montage in this TOML file, before filtering and output")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .help("Filter the channels, e.g. highpass:0.5, bandpass:1-40,order=2 or notch:60,harmonics=3; may be given more than once")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("zero_phase")
+                .long("zero-phase")
+                .help("Apply the filters forwards and backwards over the whole recording, so they don't shift the signal in time")
+                .requires("filter"),
+        )
+        .arg(
+            Arg::with_name("artifact")
+                .long("artifact")
+                .help("Detect artifacts after filtering and mark them in the --output file, e.g. threshold:100, gradient:50 or blink:80,channels=Fp1+Fp2; may be given more than once")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("artifact_mask")
+                .long("artifact-mask")
+                .requires("artifact")
+                .help("Zero the channels where artifacts are detected"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Quiet mode: do not print sample data"),
+        );
+    #[cfg(feature = "lsl-sys")]
+    let app = app
+        .arg(
+            Arg::with_name("lsl")
+                .short("L")
+                .long("lsl")
+                .help("Send samples to an LSL stream in real time"),
+        )
+        .arg(
+            Arg::with_name("lsl_stream_name")
+                .short("N")
+                .long("lsl-stream-name")
+                .help("Name of LSL stream to create")
+                .default_value(DEFAULT_STREAM_NAME),
+        );
+    app
+}
+
+/// Reads samples from a recording, skipping frames that can't be decoded
+struct RecordingSource {
+    replayer: Replayer<BufReader<File>>,
+    sigint: Arc<AtomicBool>,
+    samples: u64,
+    errors: u64,
+}
+
+impl Stage for RecordingSource {
+    fn name(&self) -> &str {
+        "recording"
+    }
+}
+
+impl Source for RecordingSource {
+    fn next(&mut self) -> Result<Option<TimedSample>, Box<dyn Error>> {
+        loop {
+            if self.sigint.load(Ordering::Relaxed) {
+                info!(target: REPLAY_TAG, "Got SIGINT, stopping replay");
+                return Ok(None);
+            }
+            match self.replayer.next_sample() {
+                Ok(Some((host_time, sample))) => {
+                    self.samples += 1;
+                    return Ok(Some(TimedSample::new(host_time, sample)));
+                }
+                Ok(None) => return Ok(None),
+                // a frame that can't be decoded is skipped, but the file can't be read past an
+                // I/O error
+                Err(RecordError::DecodeError(e)) => {
+                    self.errors += 1;
+                    warn!(target: REPLAY_TAG, "Error replaying frame: {:?}", e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Stamps samples with the host time they're played back at, as LSL consumers expect current
+/// timestamps
+#[cfg(feature = "lsl-sys")]
+struct Restamp;
+
+#[cfg(feature = "lsl-sys")]
+impl Stage for Restamp {
+    fn name(&self) -> &str {
+        "restamp"
+    }
+}
+
+#[cfg(feature = "lsl-sys")]
+impl Transform for Restamp {
+    fn process(&mut self, mut input: TimedSample, out: &mut Vec<TimedSample>) {
+        input.host_time = clock::local_clock();
+        out.push(input);
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let path = matches.value_of("recording").unwrap();
+    let mut replayer = Replayer::open(path)?;
+    let header = replayer.header().clone();
+    info!(
+        target: REPLAY_TAG,
+        "Replaying {} ({:?} frames at {} SPS, recorded {} from {})",
+        path,
+        header.mode,
+        header.sample_rate,
+        header.created,
+        header.device.port_name
+    );
+
+    let mut board_config = match BoardConfig::from_registers(&header.registers) {
+        Some(board_config) => board_config,
+        None => {
+            warn!(
+                target: REPLAY_TAG,
+                "Couldn't interpret the recorded registers, assuming gain 1"
+            );
+            BoardConfig::new(header.sample_rate, ads1299::Gain::X1)
+        }
+    };
+    if let Some(labels) = matches.value_of("channel_labels") {
+        let labels: Vec<&str> = labels.split(',').map(str::trim).collect();
+        board_config.set_labels(&labels);
+    }
+    let maybe_montage = load_montage(matches, &mut board_config)?;
+    let filter_specs = filter_specs(matches)?;
+    let filters = FilterChain::new(&filter_specs, board_config.sample_rate, NUM_CHANNELS)?;
+    let zero_phase = matches.is_present("zero_phase");
+    let maybe_detector = artifact_detector(matches, &board_config)?;
+
+    #[allow(unused_mut)]
+    let mut realtime = matches.is_present("realtime");
+    // LSL consumers expect samples to arrive at the nominal rate, with current timestamps
+    #[cfg(feature = "lsl-sys")]
+    let lsl = matches.is_present("lsl");
+    #[cfg(feature = "lsl-sys")]
+    {
+        realtime |= lsl;
+    }
+    if zero_phase && realtime {
+        return Err(
+            "--zero-phase reads the whole recording first, so it can't play back in real time"
+                .into(),
+        );
+    }
+    replayer.set_realtime(realtime);
+    // played back as fast as it can be read, no output has to keep up, so all of them get every
+    // sample; in real time, the terminal would rather be current
+    let complete = sink_options(&board_config, Overflow::Block);
+    let current = if realtime {
+        sink_options(&board_config, Overflow::DropOldest)
+    } else {
+        complete
+    };
+
+    let mut pipeline = Pipeline::new();
+    if let Some(montage) = maybe_montage {
+        pipeline.add_transform(montage);
+    }
+    let prefilter = filters.description();
+    if zero_phase {
+        pipeline.add_transform(ZeroPhaseFilter::new(
+            &filter_specs,
+            board_config.sample_rate,
+        )?);
+    } else if !filters.is_empty() {
+        pipeline.add_transform(filters);
+    }
+    #[cfg(feature = "lsl-sys")]
+    let detecting = maybe_detector.is_some();
+    if let Some(detector) = maybe_detector {
+        pipeline.add_transform(detector);
+    }
+    if let Some(sink) = writer_sink(matches, &board_config, prefilter)? {
+        pipeline.add_sink(sink, complete);
+    }
+    if !matches.is_present("quiet") {
+        pipeline.add_sink(PrintSink, current);
+    }
+
+    #[cfg(feature = "lsl-sys")]
+    if lsl {
+        let stream_name = matches.value_of("lsl_stream_name").unwrap();
+        let metadata = StreamMetadata::new(stream_name, &board_config);
+        let outlet = hackeeg::lsl::create_outlet(&metadata, 32, 360)?;
+        let outlet = ChunkedOutlet::new(outlet, 32, Some(std::time::Duration::from_millis(20)));
+        let mut sink = LslSink::new(outlet, ClockSync::default());
+        if detecting {
+            sink = sink.with_markers(hackeeg::lsl::create_marker_outlet(stream_name, 360)?);
+        }
+        // the outputs above keep the recorded host times
+        pipeline.add_transform(Restamp);
+        pipeline.add_sink(sink, complete);
+    }
+
+    let sigint = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::SIGINT, Arc::clone(&sigint))?;
+    let mut source = RecordingSource {
+        replayer,
+        sigint,
+        samples: 0,
+        errors: 0,
+    };
+    // the outputs keep what was replayed before the recording couldn't be read
+    warn_dropped(&pipeline.run(&mut source)?);
+
+    info!(
+        target: REPLAY_TAG,
+        "Replayed {} samples ({} errors)", source.samples, source.errors
+    );
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks the board works without any electrodes attached: that the firmware answers, that the
+//! ADC is an ADS1299, that every channel measures the ADC's internal test signal at the right
+//! amplitude, and that with its inputs shorted each channel's noise is within bounds.
+
+use std::error::Error;
+
+use clap::{App, ArgMatches, SubCommand};
+use log::info;
+
+use hackeeg::client::commands::responses::Status;
+use hackeeg::client::HackEEGClient;
+use hackeeg::common::config::BoardConfig;
+use hackeeg::common::constants::{ads1299, NUM_CHANNELS};
+
+use super::board::describe_id;
+use super::{acquire, set_sample_rate, Acquisition, Connection};
+
+const SELFTEST_TAG: &str = "selftest";
+
+const SAMPLE_RATE: u32 = 250;
+// two seconds, enough for a few periods of the test signal
+const SAMPLES: usize = 500;
+// the test signal is a square wave of ±(VREFP − VREFN) / 2.4 mV, see the ADS1299 datasheet,
+// 9.3.1.3
+const TEST_SIGNAL_MICROVOLTS: f64 = 2.0 * ads1299::VREF / 2.4 * 1e3;
+const TEST_SIGNAL_TOLERANCE: f64 = 0.1;
+// RMS microvolts with shorted inputs at gain 24; the datasheet typical at 250 SPS is 0.14
+const NOISE_LIMIT: f64 = 1.0;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("selftest").about(
+        "Checks the firmware, the ADC, and each channel's test signal and noise, with no electrodes needed",
+    )
+}
+
+pub fn run(_matches: &ArgMatches, connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let client = connection.open()?;
+    let mut failures = 0;
+    let mut check = |passed: bool, text: String| {
+        println!("{}  {}", if passed { "pass" } else { "FAIL" }, text);
+        if !passed {
+            failures += 1;
+        }
+    };
+
+    let version = client.version()?;
+    check(true, format!("firmware {}", version));
+    let id = client.rreg(ads1299::DeviceSettings::ID as u8)?;
+    check(id & 0x1f == 0x1e, describe_id(id));
+
+    set_sample_rate(&client, SAMPLE_RATE)?;
+    info!(target: SELFTEST_TAG, "Measuring the internal test signal");
+    let test_signal = read_test_signal(&client)?;
+    check_acquisition(&mut check, "test signal", &test_signal);
+    let config = BoardConfig::new(SAMPLE_RATE, ads1299::Gain::X1);
+    for (chan_idx, channel) in config.channels.iter().enumerate() {
+        let mut values: Vec<f64> = test_signal
+            .samples
+            .iter()
+            .map(|sample| channel.to_microvolts(sample.channels[chan_idx].sample))
+            .collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // the levels of the square wave, clear of the samples taken mid-transition
+        let low = values[values.len() / 10];
+        let high = values[values.len() * 9 / 10];
+        let amplitude = high - low;
+        let error = (amplitude - TEST_SIGNAL_MICROVOLTS).abs() / TEST_SIGNAL_MICROVOLTS;
+        check(
+            error <= TEST_SIGNAL_TOLERANCE,
+            format!(
+                "{} test signal {:.0} µV peak to peak, expected {:.0}",
+                channel.label, amplitude, TEST_SIGNAL_MICROVOLTS
+            ),
+        );
+    }
+
+    info!(target: SELFTEST_TAG, "Measuring noise with shorted inputs");
+    let shorted = read_shorted(&client)?;
+    check_acquisition(&mut check, "shorted inputs", &shorted);
+    let config = BoardConfig::new(SAMPLE_RATE, ads1299::Gain::X24);
+    for (chan_idx, channel) in config.channels.iter().enumerate() {
+        let values: Vec<f64> = shorted
+            .samples
+            .iter()
+            .map(|sample| channel.to_microvolts(sample.channels[chan_idx].sample))
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let rms = (values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / values.len() as f64)
+            .sqrt();
+        check(
+            rms <= NOISE_LIMIT,
+            format!(
+                "{} noise {:.2} µV RMS, limit {:.2}",
+                channel.label, rms, NOISE_LIMIT
+            ),
+        );
+    }
+
+    // back to the normal inputs, as the board starts up
+    client
+        .wreg::<Status>(
+            ads1299::GlobalSettings::CONFIG2 as u8,
+            ads1299::CONFIG2_const,
+        )?
+        .assert()?;
+    client.enable_all_channels(Some(ads1299::Gain::X1))?;
+
+    if failures > 0 {
+        return Err(format!("{} checks failed", failures).into());
+    }
+    println!("All checks passed");
+    Ok(())
+}
+
+fn check_acquisition<F: FnMut(bool, String)>(check: &mut F, name: &str, acquisition: &Acquisition) {
+    check(
+        acquisition.errors == 0 && acquisition.missed == 0,
+        format!(
+            "{} samples of {}, {} unreadable frames, {} missed samples",
+            acquisition.samples.len(),
+            name,
+            acquisition.errors,
+            acquisition.missed
+        ),
+    );
+}
+
+/// Reads every channel connected to the internal test signal at gain 1
+fn read_test_signal(client: &HackEEGClient) -> Result<Acquisition, Box<dyn Error>> {
+    client
+        .wreg::<Status>(
+            ads1299::GlobalSettings::CONFIG2 as u8,
+            ads1299::CONFIG2_const | ads1299::INT_TEST | ads1299::TEST_FREQ0,
+        )?
+        .assert()?;
+    set_inputs(client, ads1299::TEST_SIGNAL, ads1299::Gain::X1)?;
+    acquire(client, SAMPLES)
+}
+
+/// Reads every channel with its inputs shorted together at gain 24
+fn read_shorted(client: &HackEEGClient) -> Result<Acquisition, Box<dyn Error>> {
+    set_inputs(client, ads1299::SHORTED, ads1299::Gain::X24)?;
+    acquire(client, SAMPLES)
+}
+
+fn set_inputs(
+    client: &HackEEGClient,
+    input: u8,
+    gain: ads1299::Gain,
+) -> Result<(), Box<dyn Error>> {
+    for chan_num in 1..=NUM_CHANNELS as u8 {
+        client
+            .wreg::<Status>(
+                ads1299::ChannelSettings::CHnSET as u8 + chan_num,
+                input | gain.register_bits(),
+            )?
+            .assert()?;
+    }
+    Ok(())
+}
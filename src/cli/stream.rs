@@ -0,0 +1,1106 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use log::{info, warn};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use hackeeg::artifact::{ArtifactDetector, DetectorSpec};
+use hackeeg::client::commands::responses::Status;
+use hackeeg::client::decode_rdatac_frame;
+use hackeeg::client::HackEEGClient;
+use hackeeg::clock;
+#[cfg(feature = "lsl-sys")]
+use hackeeg::clock::ClockSync;
+use hackeeg::common::config::BoardConfig;
+use hackeeg::common::constants::{ads1299, NUM_CHANNELS};
+#[cfg(feature = "lsl-sys")]
+use hackeeg::common::metadata::StreamMetadata;
+#[cfg(feature = "lsl-sys")]
+use hackeeg::dsp::{Band, Welch, WelchOptions};
+use hackeeg::dsp::{FilterChain, FilterSpec, Resampler};
+use hackeeg::export::{self, Column, ExportOptions};
+#[cfg(feature = "lsl-sys")]
+use hackeeg::lsl::ChunkedOutlet;
+use hackeeg::montage::Montage;
+use hackeeg::net::{
+    self, OscSender, PacketOptions, StreamOptions, TcpServer, UdpSender, WebSocketServer,
+};
+#[cfg(unix)]
+use hackeeg::openbci::{self, PtyBridge};
+#[cfg(feature = "lsl-sys")]
+use hackeeg::pipeline::{BandPowerSink, LslSink};
+use hackeeg::pipeline::{
+    Event, Overflow, Pipeline, PrintSink, QualitySink, SinkOptions, SinkReport, Source, Stage,
+    TimedSample, WriterSink,
+};
+use hackeeg::quality::{QualityMonitor, QualityOptions};
+use hackeeg::record::{DeviceInfo, Recorder, RecordingHeader};
+use hackeeg::tui::{Tui, TuiCommand, TuiOptions};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::{set_sample_rate, Connection, SAMPLE_RATES};
+
+const STREAM_TAG: &str = "stream";
+#[cfg(feature = "lsl-sys")]
+const DEFAULT_STREAM_NAME: &str = "HackEEG";
+// seconds of samples each output can fall behind by
+const SINK_QUEUE_SECONDS: u32 = 2;
+// outputs that are told the gain when they start, so it can't change from the TUI while they run
+const FIXED_GAIN_ARGS: [&str; 12] = [
+    "channel_test",
+    "record",
+    "output",
+    "artifact",
+    "websocket",
+    "tcp",
+    "udp",
+    "osc",
+    "lsl",
+    "lsl_band_power",
+    "openbci",
+    "openbci_link",
+];
+
+#[cfg(feature = "lsl-sys")]
+fn lsl_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("lsl")
+            .short("L")
+            .long("lsl")
+            .help("Send samples to an LSL stream instead of terminal"),
+    )
+    .arg(
+        Arg::with_name("lsl_stream_name")
+            .short("N")
+            .long("lsl-stream-name")
+            .help("Name of LSL stream to create")
+            .default_value(DEFAULT_STREAM_NAME),
+    )
+    .arg(
+        Arg::with_name("lsl_chunk_size")
+            .long("lsl-chunk-size")
+            .help("Number of samples to accumulate before pushing a chunk to LSL")
+            .default_value("32")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("lsl_chunk_ms")
+            .long("lsl-chunk-ms")
+            .help("Push a partial chunk to LSL once its oldest sample has waited this many milliseconds (0 to wait for full chunks, unless samples stop)")
+            .default_value("20")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("lsl_max_buffered")
+            .long("lsl-max-buffered")
+            .help("Seconds of data the LSL outlet buffers for slow consumers")
+            .default_value("360")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("clock_window")
+            .long("clock-window")
+            .help("Seconds of samples used to fit the board clock to the LSL clock")
+            .default_value("30")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("lsl_band_power")
+            .long("lsl-band-power")
+            .help("Also publish each channel's band powers, in µV², as a second LSL stream"),
+    )
+    .arg(
+        Arg::with_name("bands")
+            .long("bands")
+            .help("Bands for --lsl-band-power: names of standard bands, or ranges like smr:12-15")
+            .default_value("delta,theta,alpha,beta,gamma")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("welch_segment")
+            .long("welch-segment")
+            .help("Seconds per spectrum segment for --lsl-band-power; its reciprocal is the frequency resolution")
+            .default_value("1")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("welch_overlap")
+            .long("welch-overlap")
+            .help("Fraction of each segment shared with the next, which sets how often band powers are published")
+            .default_value("0.5")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("welch_taper")
+            .long("welch-taper")
+            .help("Window applied to each segment")
+            .possible_values(&["hann", "hamming", "blackman", "rectangular"])
+            .default_value("hann")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("welch_average")
+            .long("welch-average")
+            .help("Number of segments averaged into each band power estimate")
+            .default_value("4")
+            .takes_value(true),
+    )
+}
+
+#[cfg(feature = "lsl-sys")]
+fn create_lsl_outlet(
+    matches: &ArgMatches,
+    board_config: &BoardConfig,
+) -> Result<Option<ChunkedOutlet>, Box<dyn Error>> {
+    if !matches.is_present("lsl") {
+        return Ok(None);
+    }
+
+    let stream_name = matches.value_of("lsl_stream_name").unwrap();
+    let chunk_size = matches
+        .value_of("lsl_chunk_size")
+        .unwrap()
+        .parse::<usize>()?;
+    let chunk_ms = matches.value_of("lsl_chunk_ms").unwrap().parse::<u64>()?;
+    let max_buffered = matches
+        .value_of("lsl_max_buffered")
+        .unwrap()
+        .parse::<u32>()?;
+    let max_latency = if chunk_ms > 0 {
+        Some(Duration::from_millis(chunk_ms))
+    } else {
+        None
+    };
+
+    info!(
+        target: STREAM_TAG,
+        "Creating LSL outlet '{}' with chunks of {} samples", stream_name, chunk_size
+    );
+    let metadata = StreamMetadata::new(stream_name, board_config);
+    let outlet = hackeeg::lsl::create_outlet(&metadata, chunk_size, max_buffered)?;
+    Ok(Some(ChunkedOutlet::new(outlet, chunk_size, max_latency)))
+}
+
+#[cfg(feature = "lsl-sys")]
+fn create_band_power_sink(
+    matches: &ArgMatches,
+    board_config: &BoardConfig,
+) -> Result<Option<BandPowerSink>, Box<dyn Error>> {
+    if !matches.is_present("lsl_band_power") {
+        return Ok(None);
+    }
+
+    let bands = Band::parse_list(matches.value_of("bands").unwrap())?;
+    let options = WelchOptions {
+        segment: matches.value_of("welch_segment").unwrap().parse::<f64>()?,
+        overlap: matches.value_of("welch_overlap").unwrap().parse::<f64>()?,
+        taper: matches.value_of("welch_taper").unwrap().parse()?,
+        average: matches
+            .value_of("welch_average")
+            .unwrap()
+            .parse::<usize>()?,
+    };
+    let welch = Welch::new(board_config.sample_rate, NUM_CHANNELS, &options)?;
+    welch.check_bands(&bands)?;
+
+    let stream_name = matches.value_of("lsl_stream_name").unwrap();
+    let max_buffered = matches
+        .value_of("lsl_max_buffered")
+        .unwrap()
+        .parse::<u32>()?;
+    let metadata = StreamMetadata::band_power(
+        stream_name,
+        board_config,
+        &bands,
+        &options,
+        welch.update_rate(),
+    );
+    info!(
+        target: STREAM_TAG,
+        "Creating LSL outlet '{}' with {} band powers {} times a second",
+        metadata.name,
+        metadata.channel_count,
+        welch.update_rate()
+    );
+    let outlet = hackeeg::lsl::create_feature_outlet(&metadata, max_buffered)?;
+    Ok(Some(BandPowerSink::new(outlet, board_config, bands, welch)))
+}
+
+#[cfg(unix)]
+fn openbci_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("openbci").long("openbci").help(
+            "Serve samples as OpenBCI Cyton packets on a pseudo-terminal, for OpenBCI software",
+        ),
+    )
+    .arg(
+        Arg::with_name("openbci_link")
+            .long("openbci-link")
+            .help(
+                "Create a symlink to the OpenBCI pseudo-terminal at this path (implies --openbci)",
+            )
+            .takes_value(true),
+    )
+}
+
+#[cfg(unix)]
+fn open_openbci_bridge(
+    matches: &ArgMatches,
+    board_config: &BoardConfig,
+) -> Result<Option<PtyBridge>, Box<dyn Error>> {
+    let link = matches.value_of("openbci_link");
+    if !matches.is_present("openbci") && link.is_none() {
+        return Ok(None);
+    }
+
+    if board_config.sample_rate != openbci::CYTON_SAMPLE_RATE {
+        warn!(
+            target: STREAM_TAG,
+            "OpenBCI software may assume {} samples per second; this stream has {}",
+            openbci::CYTON_SAMPLE_RATE,
+            board_config.sample_rate
+        );
+    }
+    let bridge = PtyBridge::open(board_config, link)?;
+    info!(
+        target: STREAM_TAG,
+        "Serving OpenBCI Cyton packets on {}", bridge.path()
+    );
+    Ok(Some(bridge))
+}
+
+/// Queue space for `SINK_QUEUE_SECONDS` of samples
+pub fn sink_options(config: &BoardConfig, overflow: Overflow) -> SinkOptions {
+    SinkOptions {
+        capacity: (config.sample_rate * SINK_QUEUE_SECONDS) as usize,
+        overflow,
+    }
+}
+
+/// Loads the `--montage`, if one is given, and replaces `board_config` with the configuration of
+/// the channels it derives
+pub fn load_montage(
+    matches: &ArgMatches,
+    board_config: &mut BoardConfig,
+) -> Result<Option<Montage>, Box<dyn Error>> {
+    let path = match matches.value_of("montage") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let montage = Montage::load(path, board_config)?;
+    let labels: Vec<&str> = montage
+        .config()
+        .channels
+        .iter()
+        .map(|channel| channel.label.as_str())
+        .collect();
+    info!(
+        target: STREAM_TAG,
+        "Applying montage '{}': {}",
+        montage.name(),
+        labels.join(", ")
+    );
+    *board_config = montage.config().clone();
+    Ok(Some(montage))
+}
+
+/// The `--filter`s, in the order given
+pub fn filter_specs(matches: &ArgMatches) -> Result<Vec<FilterSpec>, Box<dyn Error>> {
+    match matches.values_of("filter") {
+        Some(specs) => Ok(specs
+            .map(str::parse::<FilterSpec>)
+            .collect::<Result<Vec<_>, _>>()?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// A detector for the `--artifact`s, if any are given
+pub fn artifact_detector(
+    matches: &ArgMatches,
+    board_config: &BoardConfig,
+) -> Result<Option<ArtifactDetector>, Box<dyn Error>> {
+    let specs = match matches.values_of("artifact") {
+        Some(specs) => specs
+            .map(str::parse::<DetectorSpec>)
+            .collect::<Result<Vec<_>, _>>()?,
+        None => return Ok(None),
+    };
+    let mask = matches.is_present("artifact_mask");
+    let detector = ArtifactDetector::new(&specs, board_config, mask)?;
+    let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+    info!(
+        target: STREAM_TAG,
+        "Detecting artifacts with {}{}",
+        specs.join(" "),
+        if mask { ", masking them" } else { "" }
+    );
+    Ok(Some(detector))
+}
+
+/// A sink writing the `--output` file, if one is given, noting `prefilter` as the filtering the
+/// samples have had
+pub fn writer_sink(
+    matches: &ArgMatches,
+    board_config: &BoardConfig,
+    prefilter: String,
+) -> Result<Option<WriterSink>, Box<dyn Error>> {
+    let path = match matches.value_of("output") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    info!(target: STREAM_TAG, "Writing samples to {}", path);
+    #[allow(unused_mut)]
+    let mut options = ExportOptions {
+        units: matches.value_of("units").unwrap().parse()?,
+        columns: Column::parse_list(matches.value_of("columns").unwrap())?,
+        wav_encoding: matches.value_of("wav_encoding").unwrap().parse()?,
+        wav_scale: matches.value_of("wav_scale").unwrap().parse::<f64>()?,
+        prefilter,
+        ..ExportOptions::default()
+    };
+    // name the XDF stream after the LSL outlet, so the two can be matched up
+    #[cfg(feature = "lsl-sys")]
+    {
+        options.stream_name = matches.value_of("lsl_stream_name").unwrap().to_string();
+    }
+    let writer = export::open_writer(path, board_config, &options)?;
+    Ok(Some(WriterSink::new(writer, board_config.sample_rate)))
+}
+
+/// Warns about the outputs that fell behind and skipped samples
+pub fn warn_dropped(reports: &[SinkReport]) {
+    for report in reports {
+        if report.dropped > 0 {
+            warn!(
+                target: STREAM_TAG,
+                "The {} output fell behind and skipped {} of {} samples",
+                report.name,
+                report.dropped,
+                report.dropped + report.consumed
+            );
+        }
+    }
+}
+
+fn device_info(client: &HackEEGClient) -> DeviceInfo {
+    let firmware_version = match client.version() {
+        Ok(version) => Some(version),
+        Err(e) => {
+            warn!(target: STREAM_TAG, "Couldn't read firmware version: {:?}", e);
+            None
+        }
+    };
+    DeviceInfo {
+        port_name: client.port_name().to_string(),
+        firmware_version,
+    }
+}
+
+/// Reads samples from the board, pausing and resuming when WebSocket clients or the TUI ask
+struct BoardSource {
+    client: HackEEGClient,
+    recorder: Option<Recorder<BufWriter<File>>>,
+    server: Option<Arc<WebSocketServer>>,
+    tui: Option<Tui>,
+    sigint: Arc<AtomicBool>,
+    acquiring: bool,
+    // the channels the TUI's gain changes apply to, numbered from 1
+    enabled_channels: Vec<u8>,
+    // markers from the TUI for the next sample
+    pending_events: Vec<Event>,
+    // 0 for no limit
+    max_samples: u64,
+    samples: u64,
+    errors: u64,
+}
+
+impl BoardSource {
+    fn add_error(&mut self) {
+        self.errors += 1;
+        if let Some(ref server) = self.server {
+            server.stats().add_error();
+        }
+        if let Some(ref tui) = self.tui {
+            tui.add_error();
+        }
+    }
+
+    fn set_acquiring(&mut self, acquiring: bool) -> Result<(), Box<dyn Error>> {
+        if acquiring && !self.acquiring {
+            info!(target: STREAM_TAG, "Resuming acquisition");
+            self.client.start()?;
+            self.client.rdatac()?;
+        } else if !acquiring && self.acquiring {
+            info!(target: STREAM_TAG, "Pausing acquisition");
+            self.client.stop_and_sdatac()?;
+        }
+        self.acquiring = acquiring;
+        if let Some(ref server) = self.server {
+            server.stats().set_acquiring(acquiring);
+        }
+        if let Some(ref tui) = self.tui {
+            tui.set_acquiring(acquiring);
+        }
+        Ok(())
+    }
+
+    fn set_gain(&mut self, gain: ads1299::Gain) -> Result<(), Box<dyn Error>> {
+        info!(target: STREAM_TAG, "Changing the channels to {}", gain);
+        let acquiring = self.acquiring;
+        self.set_acquiring(false)?;
+        for &chan_num in &self.enabled_channels {
+            self.client.enable_channel(chan_num, Some(gain))?;
+        }
+        if let Some(ref tui) = self.tui {
+            tui.set_gain(gain);
+        }
+        self.set_acquiring(acquiring)
+    }
+
+    fn handle_commands(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut commands = Vec::new();
+        if let Some(ref server) = self.server {
+            while let Some(command) = server.poll_command() {
+                commands.push(match command {
+                    net::Command::Start => TuiCommand::Start,
+                    net::Command::Stop => TuiCommand::Stop,
+                });
+            }
+        }
+        if let Some(ref tui) = self.tui {
+            while let Some(command) = tui.poll_command() {
+                commands.push(command);
+            }
+        }
+
+        for command in commands {
+            match command {
+                TuiCommand::Start => self.set_acquiring(true)?,
+                TuiCommand::Stop => self.set_acquiring(false)?,
+                TuiCommand::SetGain(gain) => self.set_gain(gain)?,
+                TuiCommand::Marker(text) => {
+                    info!(target: STREAM_TAG, "{}", text);
+                    self.pending_events.push(Event {
+                        onset: 0.0,
+                        duration: None,
+                        text,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Stage for BoardSource {
+    fn name(&self) -> &str {
+        "board"
+    }
+}
+
+impl Source for BoardSource {
+    fn next(&mut self) -> Result<Option<TimedSample>, Box<dyn Error>> {
+        loop {
+            if self.sigint.load(Ordering::Relaxed) {
+                info!(target: STREAM_TAG, "Got SIGINT, breaking read loop");
+                return Ok(None);
+            }
+            if self.max_samples > 0 && self.samples >= self.max_samples {
+                info!(
+                    target: STREAM_TAG,
+                    "Reached {} samples, breaking", self.max_samples
+                );
+                return Ok(None);
+            }
+
+            self.handle_commands()?;
+            if !self.acquiring {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            let frame = match self.client.read_rdatac_frame() {
+                Err(e) => {
+                    self.add_error();
+                    warn!(target: STREAM_TAG, "Error reading frame: {:?}", e);
+                    continue;
+                }
+                Ok(frame) => frame,
+            };
+            let host_time = clock::local_clock();
+
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.write_frame(host_time, &frame)?;
+            }
+
+            match decode_rdatac_frame(self.client.mode(), &frame) {
+                Err(e) => {
+                    self.add_error();
+                    warn!(target: STREAM_TAG, "Error getting response: {:?}", e);
+                }
+                Ok(sample) => {
+                    self.samples += 1;
+                    if let Some(ref tui) = self.tui {
+                        tui.count_sample(&sample);
+                    }
+                    let mut timed = TimedSample::new(host_time, sample);
+                    timed.events.append(&mut self.pending_events);
+                    return Ok(Some(timed));
+                }
+            }
+        }
+    }
+}
+
+/// The `stream` command, or with `recording` the `record` command, which is `stream --record`
+/// with the file as its argument
+pub fn subcommand<'a, 'b>(name: &str, recording: bool) -> App<'a, 'b> {
+    let (about, record) = if recording {
+        (
+            "Records the raw frames from the board, for playing back with hackeeg replay, while streaming them like hackeeg stream",
+            Arg::with_name("record")
+                .help("The file to record to")
+                .required(true),
+        )
+    } else {
+        (
+            "Reads samples from the board and sends them to the terminal, files and network outputs",
+            Arg::with_name("record")
+                .short("r")
+                .long("record")
+                .help("Record the raw frames from the board to this file, for playing back with hackeeg replay")
+                .takes_value(true),
+        )
+    };
+    let app = SubCommand::with_name(name)
+        .about(about)
+        .arg(record)
+        .arg(
+            Arg::with_name("sps")
+                .short("s")
+                .long("sps")
+                .help("Samples per second")
+                .possible_values(&SAMPLE_RATES)
+                .default_value("500"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Quiet mode: do not print signal quality or sample data (used for performance testing)"),
+        )
+        .arg(
+            Arg::with_name("print_samples")
+                .long("print-samples")
+                .help("Print every sample's channel counts instead of periodic signal quality reports"),
+        )
+        .arg(
+            Arg::with_name("quality_window")
+                .long("quality-window")
+                .help("Seconds of signal each signal quality report covers")
+                .default_value("2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("line_frequency")
+                .long("line-frequency")
+                .help("The mains frequency in Hz, for measuring line noise in signal quality reports")
+                .default_value("60")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .conflicts_with_all(&["quiet", "print_samples"])
+                .help("Show scrolling traces of the channels in the terminal, with keys to pause, change the gain, hide channels and insert markers"),
+        )
+        .arg(
+            Arg::with_name("tui_seconds")
+                .long("tui-seconds")
+                .help("Seconds of signal the --tui traces show")
+                .default_value("5")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("samples")
+                .short("S")
+                .long("samples")
+                .help("How many samples to capture")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Write samples to this file, in the format given by its extension (.edf, .bdf, .csv, .tsv, .xdf, .npy, .npz, .wav, .rf64, OpenBCI GUI .txt, and with the arrow feature .arrow, .parquet)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("units")
+                .long("units")
+                .help("Units for channel values in output files that offer a choice: counts or uv")
+                .default_value("counts"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .help("Comma-separated columns for CSV/TSV output, from sample_number, timestamp, host_time, channels, ch1-ch8, ads_gpio, loff_statp, loff_statn")
+                .default_value("sample_number,timestamp,host_time,channels"),
+        )
+        .arg(
+            Arg::with_name("wav_encoding")
+                .long("wav-encoding")
+                .help("Sample encoding for WAV output: pcm32 or float32")
+                .default_value("pcm32"),
+        )
+        .arg(
+            Arg::with_name("wav_scale")
+                .long("wav-scale")
+                .help("Multiplier for WAV samples, where 1 maps the ADC's full scale to the audio full scale")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("channel_labels")
+                .long("channel-labels")
+                .help("Comma-separated channel labels for output files, e.g. Fp1,Fp2,C3,C4,P7,P8,O1,O2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("websocket")
+                .long("websocket")
+                .help("Serve samples to WebSocket clients at this address, e.g. 127.0.0.1:9000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("websocket_format")
+                .long("websocket-format")
+                .help("How WebSocket clients are sent samples until they ask otherwise: json or binary")
+                .default_value("json"),
+        )
+        .arg(
+            Arg::with_name("websocket_decimate")
+                .long("websocket-decimate")
+                .help("Send WebSocket clients every nth sample, for displays that don't need the full rate")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("montage")
+                .long("montage")
+                .help("Re-reference the channels with the montage in this TOML file, before filtering and output")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .help("Filter the channels before display and output, e.g. highpass:0.5, bandpass:1-40,order=2, lowpass:40,taps=101 or notch:60,harmonics=3; may be given more than once")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("artifact")
+                .long("artifact")
+                .help("Detect artifacts after filtering and mark them in --output files and as LSL markers, e.g. threshold:100, gradient:50 or blink:80,channels=Fp1+Fp2; may be given more than once")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("artifact_mask")
+                .long("artifact-mask")
+                .requires("artifact")
+                .help("Zero the channels where artifacts are detected, holding samples back to mask the start of blinks"),
+        )
+        .arg(
+            Arg::with_name("output_rate")
+                .long("output-rate")
+                .help("Resample to this many samples per second for display, LSL and network outputs; --output and --record files keep the full rate")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tcp")
+                .long("tcp")
+                .help("Serve samples to raw TCP clients at this address, e.g. 127.0.0.1:9001")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("udp")
+                .long("udp")
+                .help("Send samples over UDP to this unicast or multicast address, e.g. 239.255.0.1:9002")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("udp_ttl")
+                .long("udp-ttl")
+                .help("Time-to-live for multicast UDP and OSC packets; 1 keeps them on the local network")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("net_records_per_packet")
+                .long("net-records-per-packet")
+                .help("Samples per TCP/UDP packet; more means fewer packets but more latency")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("osc")
+                .long("osc")
+                .help("Send samples, alpha band power and lead-off as OSC messages to this UDP address, e.g. 127.0.0.1:9003")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("osc_chunk")
+                .long("osc-chunk")
+                .help("Bundle this many samples per OSC packet, rather than sending one message per sample")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("channel_test")
+                .short("T")
+                .long("channel-test")
+                .help("Set the channels to internal test settings for software testing")
+        )
+        .arg(
+            Arg::with_name("gain")
+                .short("g")
+                .long("gain")
+                .help("ADS1299 gain setting for all channels")
+                .default_value("1")
+                .takes_value(true)
+        );
+    #[cfg(feature = "lsl-sys")]
+    let app = lsl_args(app);
+    #[cfg(unix)]
+    let app = openbci_args(app);
+    app
+}
+
+pub fn run(matches: &ArgMatches, connection: &Connection) -> Result<(), Box<dyn Error>> {
+    let sps = matches.value_of("sps").unwrap().parse::<u32>()?;
+    let show_tui = matches.is_present("tui");
+
+    let mut client = connection.open()?;
+
+    client.blink_board_led()?;
+
+    set_sample_rate(&client, sps)?;
+
+    info!(target: STREAM_TAG, "Disabling all channels");
+    client.disable_all_channels()?;
+
+    // the channel config test sets every channel to gain 1
+    let gain = if matches.is_present("channel_test") {
+        info!(target: STREAM_TAG, "Enabling channel config test");
+        client.channel_config_test()?;
+        ads1299::Gain::X1
+    } else {
+        let gain: ads1299::Gain = matches
+            .value_of("gain")
+            .expect("Expected gain")
+            .parse::<u32>()?
+            .into();
+        info!(target: STREAM_TAG, "Configuring channels with gain {}", gain);
+        client.enable_all_channels(Some(gain))?;
+        gain
+    };
+
+    // Route reference electrode to SRB1: JP8:1-2, JP7:NC (not connected)
+    // use this with humans to reduce noise
+    info!(target: STREAM_TAG, "Enabling reference electrode SRB1");
+    client
+        .wreg::<Status>(ads1299::MISC1, ads1299::SRB1 | ads1299::MISC1_const)?
+        .assert()?;
+
+    // Single-ended mode - setting SRB1 bit sends mid-supply voltage to the N inputs
+    // use this with a signal generator
+    // client.wreg(ads1299::MISC1, ads1299::SRB1)?;
+
+    // Dual-ended mode
+    info!(target: STREAM_TAG, "Setting dual-ended mode");
+    client
+        .wreg::<Status>(ads1299::MISC1, ads1299::MISC1_const)?
+        .assert()?;
+
+    // registers have to be read before switching to MessagePack, which changes how the board
+    // answers commands
+    let registers = client.read_registers()?;
+    let mut board_config = match BoardConfig::from_registers(&registers) {
+        Some(board_config) => board_config,
+        None => {
+            warn!(
+                target: STREAM_TAG,
+                "Couldn't interpret the board registers, assuming the requested settings"
+            );
+            BoardConfig::new(sps, gain)
+        }
+    };
+    if let Some(labels) = matches.value_of("channel_labels") {
+        let labels: Vec<&str> = labels.split(',').map(str::trim).collect();
+        board_config.set_labels(&labels);
+    }
+    // signal quality is judged on the channels as the board measured them
+    let input_config = board_config.clone();
+    let maybe_montage = load_montage(matches, &mut board_config)?;
+
+    let filter_specs = filter_specs(matches)?;
+    let filters = FilterChain::new(&filter_specs, board_config.sample_rate, NUM_CHANNELS)?;
+    if !filters.is_empty() {
+        info!(
+            target: STREAM_TAG,
+            "Filtering with {} ({} samples delay)",
+            filters.description(),
+            filters.delay()
+        );
+    }
+
+    let maybe_detector = artifact_detector(matches, &board_config)?;
+
+    let maybe_recorder = match matches.value_of("record") {
+        Some(path) => {
+            info!(target: STREAM_TAG, "Recording raw frames to {}", path);
+            let header =
+                RecordingHeader::new(connection.mode, sps, registers, device_info(&client));
+            Some(Recorder::create(path, &header)?)
+        }
+        None => None,
+    };
+
+    let mut pipeline = Pipeline::new();
+    if !matches.is_present("quiet") && !matches.is_present("print_samples") && !show_tui {
+        let options = QualityOptions {
+            window: matches.value_of("quality_window").unwrap().parse::<f64>()?,
+            line_frequency: matches.value_of("line_frequency").unwrap().parse::<f64>()?,
+            ..QualityOptions::default()
+        };
+        let monitor = QualityMonitor::new(&input_config, &options)?;
+        pipeline.add_sink(
+            QualitySink::new(monitor),
+            sink_options(&input_config, Overflow::DropOldest),
+        );
+    }
+    if let Some(montage) = maybe_montage {
+        pipeline.add_transform(montage);
+    }
+    let prefilter = filters.description();
+    if !filters.is_empty() {
+        pipeline.add_transform(filters);
+    }
+    #[cfg(feature = "lsl-sys")]
+    let detecting = maybe_detector.is_some();
+    if let Some(detector) = maybe_detector {
+        pipeline.add_transform(detector);
+    }
+    if let Some(sink) = writer_sink(matches, &board_config, prefilter)? {
+        pipeline.add_sink(sink, sink_options(&board_config, Overflow::Block));
+    }
+
+    // the outputs added after this get the published rate
+    let mut published_config = board_config.clone();
+    if let Some(rate) = matches.value_of("output_rate") {
+        let rate = rate.parse::<u32>()?;
+        if rate > board_config.sample_rate {
+            return Err(format!(
+                "--output-rate {} is above the sample rate of {}",
+                rate, board_config.sample_rate
+            )
+            .into());
+        }
+        if rate < board_config.sample_rate {
+            let resampler = Resampler::new(board_config.sample_rate, rate, NUM_CHANNELS)?;
+            info!(
+                target: STREAM_TAG,
+                "Resampling to {} samples per second for display and streaming ({:.1} ms delay)",
+                rate,
+                resampler.delay() * 1000.0
+            );
+            pipeline.add_transform(resampler);
+            published_config.sample_rate = rate;
+        }
+    }
+    // LSL must get every sample; displays and network clients would rather be current
+    #[cfg(feature = "lsl-sys")]
+    let complete = sink_options(&published_config, Overflow::Block);
+    let current = sink_options(&published_config, Overflow::DropOldest);
+
+    if !matches.is_present("quiet") && matches.is_present("print_samples") {
+        pipeline.add_sink(PrintSink, current);
+    }
+
+    #[cfg(unix)]
+    if let Some(bridge) = open_openbci_bridge(matches, &published_config)? {
+        pipeline.add_sink(bridge, current);
+    }
+
+    let maybe_server = match matches.value_of("websocket") {
+        Some(addr) => {
+            let options = StreamOptions {
+                format: matches.value_of("websocket_format").unwrap().parse()?,
+                units: matches.value_of("units").unwrap().parse()?,
+                decimate: matches
+                    .value_of("websocket_decimate")
+                    .unwrap()
+                    .parse::<u32>()?
+                    .max(1),
+            };
+            let server = Arc::new(WebSocketServer::bind(addr, &published_config, &options)?);
+            info!(
+                target: STREAM_TAG,
+                "Serving WebSocket clients on ws://{}", server.local_addr()
+            );
+            pipeline.add_sink(server.clone(), current);
+            Some(server)
+        }
+        None => None,
+    };
+
+    let packet_options = PacketOptions {
+        units: matches.value_of("units").unwrap().parse()?,
+        records_per_packet: matches
+            .value_of("net_records_per_packet")
+            .unwrap()
+            .parse::<usize>()?,
+    };
+    if let Some(addr) = matches.value_of("tcp") {
+        let server = TcpServer::bind(addr, &published_config, &packet_options)?;
+        info!(
+            target: STREAM_TAG,
+            "Serving raw TCP clients on {}",
+            server.local_addr()
+        );
+        pipeline.add_sink(server, current);
+    }
+    if let Some(addr) = matches.value_of("udp") {
+        let ttl = matches.value_of("udp_ttl").unwrap().parse::<u32>()?;
+        let sender = UdpSender::connect(addr, &published_config, &packet_options, ttl)?;
+        info!(
+            target: STREAM_TAG,
+            "Sending UDP packets to {}",
+            sender.destination()
+        );
+        pipeline.add_sink(sender, current);
+    }
+
+    if let Some(addr) = matches.value_of("osc") {
+        let chunk = matches.value_of("osc_chunk").unwrap().parse::<usize>()?;
+        let ttl = matches.value_of("udp_ttl").unwrap().parse::<u32>()?;
+        let sender = OscSender::connect(addr, &published_config, chunk, ttl)?;
+        info!(
+            target: STREAM_TAG,
+            "Sending OSC messages to {}",
+            sender.destination()
+        );
+        pipeline.add_sink(sender, current);
+    }
+
+    client.ensure_mode(connection.mode)?;
+    client.start()?;
+    client.rdatac()?;
+
+    #[cfg(feature = "lsl-sys")]
+    if let Some(outlet) = create_lsl_outlet(matches, &published_config)? {
+        let clock_window = matches.value_of("clock_window").unwrap().parse::<f64>()?;
+        let clock_sync = ClockSync::new(clock_window, hackeeg::clock::DEFAULT_BIN_SECS);
+        let mut sink = LslSink::new(outlet, clock_sync);
+        if detecting || show_tui {
+            let stream_name = matches.value_of("lsl_stream_name").unwrap();
+            let max_buffered = matches
+                .value_of("lsl_max_buffered")
+                .unwrap()
+                .parse::<u32>()?;
+            info!(
+                target: STREAM_TAG,
+                "Creating LSL outlet '{}-Markers' for {}",
+                stream_name,
+                if detecting { "artifacts" } else { "markers" }
+            );
+            let markers = hackeeg::lsl::create_marker_outlet(stream_name, max_buffered)?;
+            sink = sink.with_markers(markers);
+        }
+        pipeline.add_sink(sink, complete);
+    }
+    #[cfg(feature = "lsl-sys")]
+    if let Some(sink) = create_band_power_sink(matches, &published_config)? {
+        pipeline.add_sink(sink, complete);
+    }
+
+    let sigint = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::SIGINT, Arc::clone(&sigint))?;
+
+    let max_samples = match matches.value_of("samples") {
+        Some(samples_str) => samples_str.parse::<u64>()?,
+        None => 0,
+    };
+    // last, as it takes over the terminal
+    let maybe_tui = if show_tui {
+        let options = TuiOptions {
+            seconds: matches.value_of("tui_seconds").unwrap().parse::<f64>()?,
+            gain_locked: FIXED_GAIN_ARGS.iter().any(|&arg| matches.is_present(arg)),
+        };
+        let (tui, sink) = Tui::start(&published_config, &options, sigint.clone())?;
+        pipeline.add_sink(sink, current);
+        Some(tui)
+    } else {
+        None
+    };
+    let enabled_channels = input_config
+        .channels
+        .iter()
+        .enumerate()
+        .filter(|(_, channel)| channel.enabled)
+        .map(|(chan_idx, _)| chan_idx as u8 + 1)
+        .collect();
+    let mut source = BoardSource {
+        client,
+        recorder: maybe_recorder,
+        server: maybe_server,
+        tui: maybe_tui,
+        sigint,
+        acquiring: true,
+        enabled_channels,
+        pending_events: Vec::new(),
+        max_samples,
+        samples: 0,
+        errors: 0,
+    };
+
+    let start = std::time::Instant::now();
+    let result = pipeline.run(&mut source);
+    if let Some(tui) = source.tui.take() {
+        tui.finish()?;
+    }
+
+    // the raw recording is completed even if an output failed
+    if let Some(recorder) = source.recorder.take() {
+        info!(target: STREAM_TAG, "Recorded {} frames", recorder.frames());
+        recorder.finish()?;
+    }
+    warn_dropped(&result?);
+
+    let elapsed = start.elapsed();
+    info!(
+        target: STREAM_TAG,
+        "{} samples ({} errors) in {} seconds, or {}/s",
+        source.samples,
+        source.errors,
+        elapsed.as_secs_f32(),
+        source.samples as f32 / elapsed.as_secs_f32()
+    );
+
+    Ok(())
+}
@@ -43,6 +43,26 @@ impl From<Status> for Box<dyn std::error::Error> {
     }
 }
 
+/// Response to a command that returns a value, like `rreg` or `version`
+#[derive(Deserialize, Clone, Debug)]
+pub struct DataResponse<T> {
+    #[serde(rename = "STATUS_CODE")]
+    pub status_code: u32,
+    #[serde(rename = "STATUS_TEXT")]
+    pub status_text: String,
+    #[serde(rename = "DATA")]
+    pub data: T,
+}
+
+impl<T> DataResponse<T> {
+    pub fn status(&self) -> Status {
+        Status {
+            status_code: self.status_code,
+            status_text: self.status_text.clone(),
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct JSONPayload {
     #[serde(rename = "C")]
@@ -13,7 +13,6 @@
 // limitations under the License.
 
 use log::{debug, info, trace, warn};
-use lsl_sys;
 use serde_json::json;
 use serialport::prelude::*;
 use serialport::Result as SerialResult;
@@ -24,11 +23,12 @@ use std::io::{BufRead, BufReader, Read};
 use std::time::Duration;
 
 pub mod commands;
-mod err;
+pub mod err;
 pub mod modes;
-mod sample;
+pub mod ports;
+pub mod sample;
 
-use crate::client::commands::responses::Status;
+use crate::client::commands::responses::{DataResponse, Status};
 use crate::common::constants;
 use commands::args::NoArgs;
 use constants::ads1299;
@@ -97,7 +97,7 @@ impl HackEEGClient {
 
         let status: Status = self.wreg(
             ads1299::ChannelSettings::CHnSET as u8 + chan_num,
-            ads1299::ELECTRODE_INPUT | gain as u8,
+            ads1299::ELECTRODE_INPUT | gain.register_bits(),
         )?;
         status.assert();
 
@@ -123,39 +123,39 @@ impl HackEEGClient {
 
         self.wreg(
             ads1299::ChannelSettings::CH1SET as u8,
-            ads1299::INT_TEST_DC | ads1299::Gain::X1 as u8,
+            ads1299::INT_TEST_DC | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
 
         self.wreg(
             ads1299::ChannelSettings::CH2SET as u8,
-            ads1299::SHORTED | ads1299::Gain::X1 as u8,
+            ads1299::SHORTED | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
 
         self.wreg(
             ads1299::ChannelSettings::CH3SET as u8,
-            ads1299::MVDD | ads1299::Gain::X1 as u8,
+            ads1299::MVDD | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
         self.wreg(
             ads1299::ChannelSettings::CH4SET as u8,
-            ads1299::BIAS_DRN | ads1299::Gain::X1 as u8,
+            ads1299::BIAS_DRN | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
         self.wreg(
             ads1299::ChannelSettings::CH5SET as u8,
-            ads1299::BIAS_DRP | ads1299::Gain::X1 as u8,
+            ads1299::BIAS_DRP | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
         self.wreg(
             ads1299::ChannelSettings::CH6SET as u8,
-            ads1299::TEMP | ads1299::Gain::X1 as u8,
+            ads1299::TEMP | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
         self.wreg(
             ads1299::ChannelSettings::CH7SET as u8,
-            ads1299::TEST_SIGNAL | ads1299::Gain::X1 as u8,
+            ads1299::TEST_SIGNAL | ads1299::Gain::X1.register_bits(),
         )
         .map(map_status)?;
 
@@ -171,6 +171,49 @@ impl HackEEGClient {
         self.execute_json_cmd("wreg", [reg, val])
     }
 
+    pub fn rreg(&self, reg: u8) -> ClientResult<u8> {
+        debug!(target: CLIENT_TAG, "Reading register {}", reg);
+        let response: DataResponse<u8> = self.execute_json_cmd("rreg", [reg])?;
+        response.status().assert()?;
+        Ok(response.data)
+    }
+
+    /// Reads every ADS1299 register, from ID (0x00) through CONFIG4 (0x17).  Continuous read
+    /// mode is suspended while reading, since register reads don't work during it.
+    pub fn read_registers(&self) -> ClientResult<Vec<u8>> {
+        let was_reading = self.continuous_read.get();
+        if was_reading {
+            self.sdatac()?;
+        }
+
+        let registers = (0..constants::NUM_REGISTERS as u8)
+            .map(|reg| self.rreg(reg))
+            .collect::<ClientResult<Vec<u8>>>()?;
+
+        if was_reading {
+            self.rdatac()?;
+        }
+        Ok(registers)
+    }
+
+    /// Returns the version string reported by the board's firmware
+    pub fn version(&self) -> ClientResult<String> {
+        let response: DataResponse<serde_json::Value> = self.execute_json_cmd("version", NoArgs)?;
+        response.status().assert()?;
+        Ok(match response.data {
+            serde_json::Value::String(version) => version,
+            other => other.to_string(),
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
     pub fn disable_all_channels(&self) -> ClientResult<()> {
         info!(target: CLIENT_TAG, "Disabling all channels");
         for chan_idx in 1..=constants::NUM_CHANNELS {
@@ -312,27 +355,30 @@ impl HackEEGClient {
         Ok(())
     }
 
-    fn messagepack_read(&self) -> ClientResult<sample::Sample> {
+    fn messagepack_read(&self) -> ClientResult<Vec<u8>> {
         let mut port = self.port.borrow_mut();
-        let mut mp_buf = [0; constants::MP_MESSAGE_SIZE];
+        let mut mp_buf = vec![0; constants::MP_MESSAGE_SIZE];
         port.read_exact(&mut mp_buf)?;
-        let sample = mp_buf[constants::MP_BINARY_OFFSET..].into();
-        Ok(sample)
+        Ok(mp_buf)
     }
 
-    pub fn read_rdatac_response(&self) -> ClientResult<sample::Sample> {
+    /// Reads one undecoded rdatac frame: a MessagePack message in MessagePack mode, or a JSON
+    /// line otherwise.  `decode_rdatac_frame` turns it into a `Sample`.
+    pub fn read_rdatac_frame(&self) -> ClientResult<Vec<u8>> {
         if self.mode == Mode::MsgPack {
-            let sample = self.messagepack_read()?;
-            Ok(sample)
+            self.messagepack_read()
         } else {
             let resp = self.read_response_line()?;
-
             trace!(target: CLIENT_TAG, "Raw rdatac response line: {:?}", resp);
-            let payload: commands::responses::JSONPayload = serde_json::from_str(&resp)?;
-            Ok(payload.data.into())
+            Ok(resp.into_bytes())
         }
     }
 
+    pub fn read_rdatac_response(&self) -> ClientResult<sample::Sample> {
+        let frame = self.read_rdatac_frame()?;
+        decode_rdatac_frame(self.mode, &frame)
+    }
+
     pub fn stop_and_sdatac_messagepack(&self) -> ClientResult<()> {
         self.stop()?;
         self.sdatac()?;
@@ -343,6 +389,22 @@ impl HackEEGClient {
         Ok(())
     }
 
+    /// Stops conversions and continuous reading, e.g. to pause acquisition, and drains the
+    /// samples still arriving so the board is ready for commands again
+    pub fn stop_and_sdatac(&self) -> ClientResult<()> {
+        if self.mode == Mode::MsgPack {
+            return self.stop_and_sdatac_messagepack();
+        }
+
+        // the replies are mixed in with the last samples, so they can't be checked
+        let _ = self.stop();
+        let _ = self.sdatac();
+        self.continuous_read.set(false);
+        self.drain_to_eof()?;
+        self.noop()?;
+        Ok(())
+    }
+
     pub fn drain_to_eof(&self) -> ClientResult<usize> {
         debug!(target: CLIENT_TAG, "Draining port to EOF...");
         let mut port = self.port.borrow_mut();
@@ -425,6 +487,21 @@ impl HackEEGClient {
     }
 }
 
+/// Decodes a frame read by `HackEEGClient::read_rdatac_frame` while the board was in `mode`
+pub fn decode_rdatac_frame(mode: Mode, frame: &[u8]) -> ClientResult<sample::Sample> {
+    if mode == Mode::MsgPack {
+        if frame.len() < constants::MP_MESSAGE_SIZE {
+            return Err(ClientError::Other(
+                format!("MessagePack frame is only {} bytes", frame.len()).into(),
+            ));
+        }
+        Ok(frame[constants::MP_BINARY_OFFSET..].into())
+    } else {
+        let payload: commands::responses::JSONPayload = serde_json::from_slice(frame)?;
+        Ok(payload.data.into())
+    }
+}
+
 fn json_cmd<G>(cmd: &str, args: G) -> String
 where
     G: serde::Serialize,
@@ -0,0 +1,91 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Finding the serial port a HackEEG board is on.  The board sits on an Arduino Due, which shows
+//! up as one USB serial port for its native USB connector and another for its programming
+//! connector; the firmware answers on either.
+
+use log::debug;
+use serialport::{SerialPortInfo, SerialPortSettings, SerialPortType};
+
+use crate::client::HackEEGClient;
+
+const PORTS_TAG: &str = "ports";
+
+pub const ARDUINO_VID: u16 = 0x2341;
+pub const DUE_PROGRAMMING_PID: u16 = 0x003d;
+pub const DUE_NATIVE_PID: u16 = 0x003e;
+
+/// Which of the Arduino Due's USB connectors a port is
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DuePort {
+    Native,
+    Programming,
+}
+
+impl std::fmt::Display for DuePort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            DuePort::Native => write!(f, "Arduino Due native port"),
+            DuePort::Programming => write!(f, "Arduino Due programming port"),
+        }
+    }
+}
+
+/// The Due connector `port` is, if it's one
+pub fn due_port(port: &SerialPortInfo) -> Option<DuePort> {
+    match &port.port_type {
+        SerialPortType::UsbPort(usb) if usb.vid == ARDUINO_VID => match usb.pid {
+            DUE_NATIVE_PID => Some(DuePort::Native),
+            DUE_PROGRAMMING_PID => Some(DuePort::Programming),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether HackEEG firmware answers a `nop` on `port_name`
+pub fn probe(port_name: &str, settings: &SerialPortSettings) -> bool {
+    let client = match HackEEGClient::new(port_name, settings) {
+        Ok(client) => client,
+        Err(e) => {
+            debug!(target: PORTS_TAG, "Couldn't open {}: {}", port_name, e);
+            return false;
+        }
+    };
+    match client.noop() {
+        Ok(answered) => answered,
+        Err(e) => {
+            debug!(target: PORTS_TAG, "No answer from {}: {:?}", port_name, e);
+            false
+        }
+    }
+}
+
+/// The first Arduino Due port, native ones first as they're faster, whose firmware answers a
+/// `nop`
+pub fn find_board(settings: &SerialPortSettings) -> serialport::Result<Option<String>> {
+    let mut candidates: Vec<(DuePort, String)> = serialport::available_ports()?
+        .into_iter()
+        .filter_map(|port| due_port(&port).map(|due| (due, port.port_name)))
+        .collect();
+    candidates.sort_by_key(|(due, _)| *due != DuePort::Native);
+    for (due, port_name) in candidates {
+        debug!(target: PORTS_TAG, "Probing {} on {}", due, port_name);
+        if probe(&port_name, settings) {
+            return Ok(Some(port_name));
+        }
+    }
+    Ok(None)
+}
@@ -28,6 +28,7 @@ impl From<&[u8]> for Channel {
     }
 }
 
+#[derive(Clone)]
 pub struct Sample {
     pub timestamp: u32,
     pub sample_number: u32,
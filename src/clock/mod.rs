@@ -0,0 +1,318 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Maps the board's sample timestamps onto a host clock.
+//!
+//! The Arduino stamps each sample with `micros()`, a `u32` that wraps every ~71.6 minutes and is
+//! driven by a crystal that drifts relative to the host.  `ClockSync` unwraps the counter and
+//! fits `host = offset + rate * board` over a sliding window, so samples can be published with
+//! host timestamps (e.g. `lsl_local_clock()`) instead of raw board ticks.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+const MICROS_PER_SEC: f64 = 1_000_000.0;
+
+/// Default length of the regression window, in seconds of board time
+pub const DEFAULT_WINDOW_SECS: f64 = 30.0;
+
+/// Default length of each minimum-latency bin, in seconds of board time
+pub const DEFAULT_BIN_SECS: f64 = 0.1;
+
+/// Returns the current host time in seconds.  With the `lsl` feature this is LSL's local clock,
+/// so host timestamps can be compared with other LSL streams on the machine; otherwise it's the
+/// system clock, in seconds since the Unix epoch.
+pub fn local_clock() -> f64 {
+    #[cfg(feature = "lsl-sys")]
+    {
+        lsl_sys::local_clock()
+    }
+
+    #[cfg(not(feature = "lsl-sys"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+}
+
+/// Extends the board's wrapping `u32` microsecond counter to 64 bits.
+#[derive(Default)]
+pub struct Unwrapper {
+    last: Option<u32>,
+    wraps: u64,
+}
+
+impl Unwrapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn unwrap(&mut self, ticks: u32) -> u64 {
+        if let Some(last) = self.last {
+            // a large backwards jump is a wrap; a small one is just out-of-order noise
+            if ticks < last && last - ticks > u32::MAX / 2 {
+                self.wraps += 1;
+            }
+        }
+        self.last = Some(ticks);
+        (self.wraps << 32) | ticks as u64
+    }
+}
+
+/// Running statistics of the difference between when a sample arrived on the host and when the
+/// fitted clock model says it was taken, i.e. the serial/USB transfer jitter.
+#[derive(Clone, Copy, Debug)]
+pub struct JitterStats {
+    pub count: u64,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    m2: f64,
+}
+
+impl Default for JitterStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            m2: 0.0,
+        }
+    }
+}
+
+impl JitterStats {
+    fn push(&mut self, value: f64) {
+        // Welford's online algorithm
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+    }
+}
+
+impl fmt::Display for JitterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return write!(f, "no samples");
+        }
+        write!(
+            f,
+            "mean {:.3} ms, std dev {:.3} ms, min {:.3} ms, max {:.3} ms over {} samples",
+            self.mean * 1000.0,
+            self.std_dev() * 1000.0,
+            self.min * 1000.0,
+            self.max * 1000.0,
+            self.count
+        )
+    }
+}
+
+/// Estimates the offset and drift between the board clock and a host clock.
+///
+/// Within each bin only the sample with the smallest host-minus-board difference is kept, since
+/// it was delayed the least on its way to the host.  A least-squares line through the bin minima
+/// of the last `window_secs` then gives the mapping used for every sample.
+pub struct ClockSync {
+    unwrapper: Unwrapper,
+    bin_secs: f64,
+    max_bins: usize,
+    // board and host time of the first sample; everything else is relative to these, which keeps
+    // the regression sums well conditioned
+    origin: Option<(f64, f64)>,
+    bin_start: f64,
+    bin_min: Option<(f64, f64)>,
+    points: VecDeque<(f64, f64)>,
+    offset: f64,
+    rate: f64,
+    jitter: JitterStats,
+    total_jitter: JitterStats,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW_SECS, DEFAULT_BIN_SECS)
+    }
+}
+
+impl ClockSync {
+    pub fn new(window_secs: f64, bin_secs: f64) -> Self {
+        let max_bins = ((window_secs / bin_secs).ceil() as usize).max(2);
+        Self {
+            unwrapper: Unwrapper::new(),
+            bin_secs,
+            max_bins,
+            origin: None,
+            bin_start: 0.0,
+            bin_min: None,
+            points: VecDeque::with_capacity(max_bins),
+            offset: 0.0,
+            rate: 1.0,
+            jitter: JitterStats::default(),
+            total_jitter: JitterStats::default(),
+        }
+    }
+
+    /// Feeds one sample's board timestamp along with the host time it was received at, and
+    /// returns the sample's timestamp on the host clock.
+    pub fn update(&mut self, board_ticks: u32, host_time: f64) -> f64 {
+        let board_secs = self.unwrapper.unwrap(board_ticks) as f64 / MICROS_PER_SEC;
+        let (board_origin, host_origin) = *self.origin.get_or_insert((board_secs, host_time));
+        let board = board_secs - board_origin;
+        let host = host_time - host_origin;
+
+        let is_new_min = match self.bin_min {
+            Some((min_board, min_host)) => host - board < min_host - min_board,
+            None => true,
+        };
+        if is_new_min {
+            self.bin_min = Some((board, host));
+        }
+
+        if board - self.bin_start >= self.bin_secs {
+            if let Some(point) = self.bin_min.take() {
+                self.points.push_back(point);
+                if self.points.len() > self.max_bins {
+                    self.points.pop_front();
+                }
+                self.fit();
+            }
+            self.bin_start = board;
+        }
+
+        let mapped = self.offset + self.rate * board;
+        let residual = host - mapped;
+        self.jitter.push(residual);
+        self.total_jitter.push(residual);
+
+        host_origin + mapped
+    }
+
+    fn fit(&mut self) {
+        let n = self.points.len() as f64;
+        if self.points.len() < 2 {
+            // not enough for a slope yet; assume the clocks run at the same rate
+            let (board, host) = self.points[0];
+            self.offset = host - board;
+            self.rate = 1.0;
+            return;
+        }
+
+        let (sum_x, sum_y) = self
+            .points
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+        let (sxx, sxy) = self.points.iter().fold((0.0, 0.0), |(sxx, sxy), (x, y)| {
+            let dx = x - mean_x;
+            (sxx + dx * dx, sxy + dx * (y - mean_y))
+        });
+
+        if sxx > 0.0 {
+            self.rate = sxy / sxx;
+            self.offset = mean_y - self.rate * mean_x;
+        }
+    }
+
+    /// Host clock minus board clock at the first sample, in seconds
+    pub fn offset(&self) -> f64 {
+        match self.origin {
+            Some((board_origin, host_origin)) => host_origin + self.offset - board_origin,
+            None => 0.0,
+        }
+    }
+
+    /// How much faster the host clock runs than the board clock, in parts per million
+    pub fn drift_ppm(&self) -> f64 {
+        (self.rate - 1.0) * 1e6
+    }
+
+    /// Returns the jitter statistics accumulated since the last call, and resets them
+    pub fn take_jitter(&mut self) -> JitterStats {
+        std::mem::take(&mut self.jitter)
+    }
+
+    /// Jitter statistics over the whole session
+    pub fn total_jitter(&self) -> JitterStats {
+        self.total_jitter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwrapper_counts_wraps_but_not_jitter() {
+        let mut unwrapper = Unwrapper::new();
+        assert_eq!(unwrapper.unwrap(u32::MAX - 10), u32::MAX as u64 - 10);
+        assert_eq!(unwrapper.unwrap(5), (1 << 32) + 5);
+        // out of order by a little, which isn't another wrap
+        assert_eq!(unwrapper.unwrap(2), (1 << 32) + 2);
+        assert_eq!(
+            unwrapper.unwrap(u32::MAX / 4),
+            (1 << 32) + u32::MAX as u64 / 4
+        );
+    }
+
+    #[test]
+    fn sync_follows_drift_across_a_wrap() {
+        const RATE: u32 = 250;
+        const DRIFT: f64 = 50e-6;
+        let start_ticks = u32::MAX - 5_000_000;
+        let host_start = 1000.0;
+
+        let mut sync = ClockSync::default();
+        let mut last = f64::NEG_INFINITY;
+        for n in 0..60 * RATE {
+            let board_secs = n as f64 / RATE as f64;
+            let ticks = start_ticks.wrapping_add(n * (1_000_000 / RATE));
+            let taken = host_start + board_secs * (1.0 + DRIFT);
+            // up to 4 ms of transfer latency, with an undelayed sample in every bin
+            let latency = (n * 7 % 5) as f64 * 0.001;
+            let mapped = sync.update(ticks, taken + latency);
+
+            assert!(mapped > last, "timestamps went backwards at sample {}", n);
+            last = mapped;
+            if board_secs > 10.0 {
+                assert!(
+                    (mapped - taken).abs() < 50e-6,
+                    "sample {} is off by {} s",
+                    n,
+                    mapped - taken
+                );
+            }
+        }
+
+        assert!((sync.drift_ppm() - DRIFT * 1e6).abs() < 1.0);
+        let expected_offset = host_start - start_ticks as f64 / MICROS_PER_SEC;
+        assert!((sync.offset() - expected_offset).abs() < 1e-4);
+        let jitter = sync.total_jitter();
+        assert_eq!(jitter.count, 60 * RATE as u64);
+        assert!(jitter.min > -50e-6 && jitter.max < 0.0041);
+    }
+}
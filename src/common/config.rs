@@ -0,0 +1,145 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The acquisition settings needed to interpret samples: rate, and per-channel gain and labels
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::constants::ads1299;
+use crate::common::constants::{NUM_CHANNELS, NUM_REGISTERS};
+
+// full scale of the 24-bit two's complement samples, see the ADS1299 datasheet, 9.4.4.3
+pub const DIGITAL_MIN: i32 = -(1 << 23);
+pub const DIGITAL_MAX: i32 = (1 << 23) - 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChannelConfig {
+    pub label: String,
+    pub gain: u32,
+    /// false if the channel is powered down
+    pub enabled: bool,
+    /// the MUXn[2:0] input selection, e.g. `ads1299::ELECTRODE_INPUT`
+    pub input: u8,
+}
+
+impl ChannelConfig {
+    /// Microvolts per count of this channel's samples
+    pub fn microvolts_per_count(&self) -> f64 {
+        ads1299::VREF * 1e6 / self.gain as f64 / (1 << 23) as f64
+    }
+
+    pub fn to_microvolts(&self, counts: i32) -> f64 {
+        counts as f64 * self.microvolts_per_count()
+    }
+
+    pub fn physical_min(&self) -> f64 {
+        self.to_microvolts(DIGITAL_MIN)
+    }
+
+    pub fn physical_max(&self) -> f64 {
+        self.to_microvolts(DIGITAL_MAX)
+    }
+}
+
+/// How the channels were re-referenced in software, see `montage::Montage`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MontageInfo {
+    pub name: String,
+    /// what was subtracted from the channels, e.g. `average`, `A1` or `A1+A2`, or `bipolar`
+    /// when each channel had its own
+    pub reference: String,
+    pub common_average: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BoardConfig {
+    pub sample_rate: u32,
+    pub channels: Vec<ChannelConfig>,
+    /// `None` for the channels as the board measured them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub montage: Option<MontageInfo>,
+}
+
+impl BoardConfig {
+    pub fn default_label(chan_idx: usize) -> String {
+        format!("Ch{}", chan_idx + 1)
+    }
+
+    /// Builds a config with every channel enabled at the same gain
+    pub fn new(sample_rate: u32, gain: ads1299::Gain) -> Self {
+        let channels = (0..NUM_CHANNELS)
+            .map(|chan_idx| ChannelConfig {
+                label: Self::default_label(chan_idx),
+                gain: gain.multiplier(),
+                enabled: true,
+                input: ads1299::ELECTRODE_INPUT,
+            })
+            .collect();
+        Self {
+            sample_rate,
+            channels,
+            montage: None,
+        }
+    }
+
+    /// Derives the config from a dump of registers ID (0x00) through CONFIG4 (0x17), as returned
+    /// by `HackEEGClient::read_registers`.  Returns `None` if the dump is short or holds an
+    /// invalid data rate or gain.
+    pub fn from_registers(registers: &[u8]) -> Option<Self> {
+        if registers.len() < NUM_REGISTERS {
+            return None;
+        }
+
+        let config1 = registers[ads1299::GlobalSettings::CONFIG1 as usize];
+        let sample_rate = ads1299::Speed::from_register(config1)?.samples_per_second();
+
+        let mut channels = Vec::with_capacity(NUM_CHANNELS);
+        for chan_idx in 0..NUM_CHANNELS {
+            let chnset = registers[ads1299::ChannelSettings::CH1SET as usize + chan_idx];
+            channels.push(ChannelConfig {
+                label: Self::default_label(chan_idx),
+                gain: ads1299::Gain::from_register(chnset)?.multiplier(),
+                enabled: chnset & ads1299::PDn == 0,
+                input: chnset & ads1299::MUX_MASK,
+            });
+        }
+
+        Some(Self {
+            sample_rate,
+            channels,
+            montage: None,
+        })
+    }
+
+    /// Replaces the channel labels, in channel order.  Channels without a label keep theirs.
+    pub fn set_labels<S: AsRef<str>>(&mut self, labels: &[S]) {
+        for (channel, label) in self.channels.iter_mut().zip(labels) {
+            channel.label = label.as_ref().to_string();
+        }
+    }
+
+    /// The index of the channel named by `name`, its label or its number from 1
+    pub fn channel_index(&self, name: &str) -> Option<usize> {
+        let name = name.trim();
+        self.channels
+            .iter()
+            .position(|channel| channel.label.eq_ignore_ascii_case(name))
+            .or_else(|| {
+                name.parse::<usize>()
+                    .ok()
+                    .and_then(|number| number.checked_sub(1))
+                    .filter(|chan_idx| *chan_idx < self.channels.len())
+            })
+    }
+}
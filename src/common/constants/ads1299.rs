@@ -71,6 +71,34 @@ pub enum LeadOffStatus {
     LOFF_STATN = 0x13,
 }
 
+/// Register names by address, ID (0x00) through CONFIG4 (0x17)
+pub const REGISTER_NAMES: [&str; 0x18] = [
+    "ID",
+    "CONFIG1",
+    "CONFIG2",
+    "CONFIG3",
+    "LOFF",
+    "CH1SET",
+    "CH2SET",
+    "CH3SET",
+    "CH4SET",
+    "CH5SET",
+    "CH6SET",
+    "CH7SET",
+    "CH8SET",
+    "BIAS_SENSP",
+    "BIAS_SENSN",
+    "LOFF_SENSP",
+    "LOFF_SENSN",
+    "LOFF_FLIP",
+    "LOFF_STATP",
+    "LOFF_STATN",
+    "GPIO",
+    "MISC1",
+    "MISC2",
+    "CONFIG4",
+];
+
 pub enum Speed {
     HIGH_RES_16k_SPS = 0x00,
     HIGH_RES_8k_SPS = 0x01,
@@ -96,11 +124,42 @@ impl From<u32> for Speed {
     }
 }
 
+impl Speed {
+    /// Decodes the data rate bits (DR[2:0]) of CONFIG1
+    pub fn from_register(config1: u8) -> Option<Self> {
+        match config1 & 0x07 {
+            0x00 => Some(Speed::HIGH_RES_16k_SPS),
+            0x01 => Some(Speed::HIGH_RES_8k_SPS),
+            0x02 => Some(Speed::HIGH_RES_4k_SPS),
+            0x03 => Some(Speed::HIGH_RES_2k_SPS),
+            0x04 => Some(Speed::HIGH_RES_1k_SPS),
+            0x05 => Some(Speed::HIGH_RES_500_SPS),
+            0x06 => Some(Speed::HIGH_RES_250_SPS),
+            _ => None,
+        }
+    }
+
+    pub fn samples_per_second(&self) -> u32 {
+        match self {
+            Speed::HIGH_RES_16k_SPS => 16000,
+            Speed::HIGH_RES_8k_SPS => 8000,
+            Speed::HIGH_RES_4k_SPS => 4000,
+            Speed::HIGH_RES_2k_SPS => 2000,
+            Speed::HIGH_RES_1k_SPS => 1000,
+            Speed::HIGH_RES_500_SPS => 500,
+            Speed::HIGH_RES_250_SPS => 250,
+        }
+    }
+}
+
 // TODO do the rest of these.  not all of them are classified into enums, like the above.  where
 // grouping together into an enum doesn't make sense, use a const
 
 //GPIO = 0x14
 pub const MISC1: u8 = 0x15;
+
+// internal reference voltage, in volts.  a code of 2^23 - 1 is +VREF / gain
+pub const VREF: f64 = 4.5;
 //RESP = 0x16
 //CONFIG4 = 0x17
 //WCT1 = 0x18
@@ -184,10 +243,20 @@ pub const INT_TEST_DC: u8 = (INT_TEST | TEST_FREQ1 | TEST_FREQ0);
 //FLEAD_OFF_AC = FLEAD_OFF0
 //FLEAD_OFF_DC = (FLEAD_OFF1 | FLEAD_OFF0)
 //
+// the ADS1299's lead-off currents and frequencies differ from the ADS1298's above, see the
+// ADS1299 datasheet, 9.6.1.5
+pub const ILEAD_OFF_6NA: u8 = 0x00;
+pub const ILEAD_OFF_24NA: u8 = 0x04;
+pub const FLEAD_OFF_AC_31_2HZ: u8 = 0x02;
+// the frequency of FLEAD_OFF_AC_31_2HZ exactly: fCLK / 2^16, with the 2.048 MHz internal clock
+pub const LEAD_OFF_AC_HZ: f64 = 31.25;
+
 pub const PDn: u8 = 0x80;
 //GAINn2 = 0x40
 //GAINn1 = 0x20
 //GAINn0 = 0x10
+pub const GAIN_SHIFT: u8 = 4;
+pub const GAIN_MASK: u8 = 0x70;
 //SRB2n0 = 0x08
 
 pub const MUXn2: u8 = 0x04;
@@ -225,6 +294,39 @@ impl fmt::Display for Gain {
     }
 }
 
+impl Gain {
+    /// The gain bits to OR into a CHnSET register value
+    pub fn register_bits(self) -> u8 {
+        (self as u8) << GAIN_SHIFT
+    }
+
+    /// Decodes the gain bits (GAINn[2:0]) of a CHnSET register value
+    pub fn from_register(chnset: u8) -> Option<Self> {
+        match (chnset & GAIN_MASK) >> GAIN_SHIFT {
+            0b000 => Some(Gain::X1),
+            0b001 => Some(Gain::X2),
+            0b010 => Some(Gain::X4),
+            0b011 => Some(Gain::X6),
+            0b100 => Some(Gain::X8),
+            0b101 => Some(Gain::X12),
+            0b110 => Some(Gain::X24),
+            _ => None,
+        }
+    }
+
+    pub fn multiplier(self) -> u32 {
+        match self {
+            Gain::X1 => 1,
+            Gain::X2 => 2,
+            Gain::X4 => 4,
+            Gain::X6 => 6,
+            Gain::X8 => 8,
+            Gain::X12 => 12,
+            Gain::X24 => 24,
+        }
+    }
+}
+
 impl From<u32> for Gain {
     fn from(num: u32) -> Self {
         match num {
@@ -250,6 +352,7 @@ impl From<u32> for Gain {
 //ADS1298_GAIN_8X = (GAINn2 | GAINn0)
 //ADS1298_GAIN_12X = (GAINn2 | GAINn1)
 //
+pub const MUX_MASK: u8 = 0x07;
 pub const ELECTRODE_INPUT: u8 = 0x00;
 pub const SHORTED: u8 = 0x01;
 //RLD_INPUT = MUXn1
@@ -258,6 +361,20 @@ pub const TEMP: u8 = MUXn2;
 pub const TEST_SIGNAL: u8 = (MUXn2 | MUXn0);
 pub const BIAS_DRP: u8 = (MUXn2 | MUXn1);
 pub const BIAS_DRN: u8 = (MUXn2 | MUXn1 | MUXn0);
+
+/// Describes a MUXn[2:0] channel input selection
+pub fn input_name(input: u8) -> &'static str {
+    match input & MUX_MASK {
+        ELECTRODE_INPUT => "electrode",
+        SHORTED => "shorted",
+        0x02 => "bias measurement",
+        MVDD => "supply",
+        TEMP => "temperature",
+        TEST_SIGNAL => "test signal",
+        BIAS_DRP => "bias drive P",
+        _ => "bias drive N",
+    }
+}
 //
 //PD_1 = 0x80
 //GAIN12 = 0x40
@@ -16,6 +16,9 @@ pub mod ads1299;
 
 pub const NUM_CHANNELS: usize = 8;
 
+// registers ID (0x00) through CONFIG4 (0x17)
+pub const NUM_REGISTERS: usize = 0x18;
+
 // message pack manual sizes and offsets, for faster decoding
 pub const MP_MESSAGE_SIZE: usize = 44;
 pub const MP_BINARY_OFFSET: usize = 9;
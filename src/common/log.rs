@@ -16,7 +16,42 @@ pub fn setup_logger(
     level: log::LevelFilter,
     maybe_output_file: Option<&std::path::Path>,
 ) -> Result<(), fern::InitError> {
-    let mut logger = fern::Dispatch::new()
+    let mut logger = dispatch(level).chain(std::io::stdout());
+
+    if let Some(output_file) = maybe_output_file {
+        logger = logger.chain(fern::log_file(output_file)?);
+    }
+
+    logger.apply()?;
+
+    Ok(())
+}
+
+/// Like `setup_logger`, but hands each formatted message to `output` instead of printing it,
+/// e.g. for a terminal UI that owns the screen
+pub fn setup_logger_with_output<F>(
+    level: log::LevelFilter,
+    maybe_output_file: Option<&std::path::Path>,
+    output: F,
+) -> Result<(), fern::InitError>
+where
+    F: Fn(&str) + Sync + Send + 'static,
+{
+    let mut logger = dispatch(level).chain(fern::Output::call(move |record| {
+        output(&record.args().to_string())
+    }));
+
+    if let Some(output_file) = maybe_output_file {
+        logger = logger.chain(fern::log_file(output_file)?);
+    }
+
+    logger.apply()?;
+
+    Ok(())
+}
+
+fn dispatch(level: log::LevelFilter) -> fern::Dispatch {
+    fern::Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
                 "{}[Thread: {:?}][{}][{}] {}",
@@ -28,13 +63,4 @@ pub fn setup_logger(
             ))
         })
         .level(level)
-        .chain(std::io::stdout());
-
-    if let Some(output_file) = maybe_output_file {
-        logger = logger.chain(fern::log_file(output_file)?);
-    }
-
-    logger.apply()?;
-
-    Ok(())
 }
@@ -0,0 +1,248 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stream metadata shared by the LSL outlet and XDF files, so a recording made either way
+//! describes the channels identically.  The `desc` fields follow the XDF meta-data conventions,
+//! see https://github.com/sccn/xdf/wiki/EEG-Meta-Data.
+
+use std::fmt::Write;
+
+use crate::common::config::BoardConfig;
+use crate::dsp::{Band, WelchOptions};
+
+pub const STREAM_TYPE: &str = "EEG";
+pub const MARKER_STREAM_TYPE: &str = "Markers";
+pub const BAND_POWER_STREAM_TYPE: &str = "BandPower";
+
+/// A simple XML element: either a value or a list of children
+#[derive(Clone, Debug)]
+pub struct XmlNode {
+    pub name: String,
+    pub value: Option<String>,
+    pub children: Vec<XmlNode>,
+}
+
+impl XmlNode {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_value<T: ToString>(name: &str, value: T) -> Self {
+        Self {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn child(mut self, child: XmlNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn write_xml(&self, xml: &mut String) {
+        match &self.value {
+            Some(value) => {
+                let _ = write!(xml, "<{0}>{1}</{0}>", self.name, escape_xml(value));
+            }
+            None if self.children.is_empty() => {
+                let _ = write!(xml, "<{}/>", self.name);
+            }
+            None => {
+                let _ = write!(xml, "<{}>", self.name);
+                for child in &self.children {
+                    child.write_xml(xml);
+                }
+                let _ = write!(xml, "</{}>", self.name);
+            }
+        }
+    }
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamMetadata {
+    pub name: String,
+    pub stream_type: String,
+    pub source_id: String,
+    pub channel_count: usize,
+    /// 0 for irregular streams
+    pub sample_rate: f64,
+    /// an LSL channel format name, e.g. `int32`
+    pub channel_format: String,
+    pub desc: XmlNode,
+}
+
+impl StreamMetadata {
+    /// Describes the board's channels, which are sent as int32 counts
+    pub fn new(name: &str, config: &BoardConfig) -> Self {
+        let mut channels = XmlNode::new("channels");
+        for channel in &config.channels {
+            channels = channels.child(
+                XmlNode::new("channel")
+                    .child(XmlNode::with_value("label", &channel.label))
+                    .child(XmlNode::with_value("unit", "counts"))
+                    .child(XmlNode::with_value("type", STREAM_TYPE))
+                    .child(XmlNode::with_value(
+                        "scaling_factor",
+                        channel.microvolts_per_count(),
+                    ))
+                    .child(XmlNode::with_value("scaled_unit", "microvolts"))
+                    .child(XmlNode::with_value("gain", channel.gain)),
+            );
+        }
+        let acquisition = XmlNode::new("acquisition")
+            .child(XmlNode::with_value("manufacturer", "Starcat"))
+            .child(XmlNode::with_value("model", "HackEEG"))
+            .child(XmlNode::with_value("precision", 24));
+
+        let mut desc = XmlNode::new("desc").child(channels).child(acquisition);
+        if let Some(ref montage) = config.montage {
+            let yes_no = |yes| if yes { "yes" } else { "no" };
+            desc = desc
+                .child(
+                    XmlNode::new("reference")
+                        .child(XmlNode::with_value("label", &montage.reference))
+                        .child(XmlNode::with_value("subtracted", "yes"))
+                        .child(XmlNode::with_value(
+                            "common_average",
+                            yes_no(montage.common_average),
+                        )),
+                )
+                .child(XmlNode::new("montage").child(XmlNode::with_value("name", &montage.name)));
+        }
+
+        Self {
+            name: name.to_string(),
+            stream_type: STREAM_TYPE.to_string(),
+            source_id: Self::source_id(name, STREAM_TYPE, config.channels.len()),
+            channel_count: config.channels.len(),
+            sample_rate: config.sample_rate as f64,
+            channel_format: "int32".to_string(),
+            desc,
+        }
+    }
+
+    /// Describes an irregular stream of text markers accompanying the stream `name`
+    pub fn markers(name: &str) -> Self {
+        let name = format!("{}-Markers", name);
+        Self {
+            source_id: Self::source_id(&name, MARKER_STREAM_TYPE, 1),
+            name,
+            stream_type: MARKER_STREAM_TYPE.to_string(),
+            channel_count: 1,
+            sample_rate: 0.0,
+            channel_format: "string".to_string(),
+            desc: XmlNode::new("desc"),
+        }
+    }
+
+    /// Describes a stream of each channel's power in `bands`, accompanying the stream `name`,
+    /// with a float32 channel per channel and band and `rate` estimates per second
+    pub fn band_power(
+        name: &str,
+        config: &BoardConfig,
+        bands: &[Band],
+        options: &WelchOptions,
+        rate: f64,
+    ) -> Self {
+        let name = format!("{}-BandPower", name);
+        let mut channels = XmlNode::new("channels");
+        for channel in &config.channels {
+            for band in bands {
+                channels = channels.child(
+                    XmlNode::new("channel")
+                        .child(XmlNode::with_value(
+                            "label",
+                            format!("{}-{}", channel.label, band.name),
+                        ))
+                        .child(XmlNode::with_value("unit", "microvolts^2"))
+                        .child(XmlNode::with_value("type", BAND_POWER_STREAM_TYPE)),
+                );
+            }
+        }
+        let mut band_nodes = XmlNode::new("bands");
+        for band in bands {
+            band_nodes = band_nodes.child(
+                XmlNode::new("band")
+                    .child(XmlNode::with_value("name", &band.name))
+                    .child(XmlNode::with_value("low", band.low))
+                    .child(XmlNode::with_value("high", band.high)),
+            );
+        }
+        let method = XmlNode::new("spectrum")
+            .child(XmlNode::with_value("method", "welch"))
+            .child(XmlNode::with_value("segment", options.segment))
+            .child(XmlNode::with_value("overlap", options.overlap))
+            .child(XmlNode::with_value("taper", options.taper))
+            .child(XmlNode::with_value("average", options.average));
+        let channel_count = config.channels.len() * bands.len();
+
+        Self {
+            source_id: Self::source_id(&name, BAND_POWER_STREAM_TYPE, channel_count),
+            name,
+            stream_type: BAND_POWER_STREAM_TYPE.to_string(),
+            channel_count,
+            sample_rate: rate,
+            channel_format: "float32".to_string(),
+            desc: XmlNode::new("desc")
+                .child(channels)
+                .child(band_nodes)
+                .child(method),
+        }
+    }
+
+    /// A stable id derived from name-type-num_channels, so consumers can recognise the stream
+    /// across restarts
+    fn source_id(name: &str, stream_type: &str, channel_count: usize) -> String {
+        let uuid_name = format!("{}-{}-{}", name, stream_type, channel_count);
+        uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, uuid_name.as_bytes())
+            .to_simple()
+            .to_string()
+    }
+
+    /// The full stream description in the form LSL reports it, as stored in XDF stream headers
+    pub fn info_xml(&self, created_at: f64) -> String {
+        let uid_name = format!("{}-{}", self.source_id, created_at);
+        let uid = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, uid_name.as_bytes());
+
+        let info = XmlNode::new("info")
+            .child(XmlNode::with_value("name", &self.name))
+            .child(XmlNode::with_value("type", &self.stream_type))
+            .child(XmlNode::with_value("channel_count", self.channel_count))
+            .child(XmlNode::with_value("channel_format", &self.channel_format))
+            .child(XmlNode::with_value("source_id", &self.source_id))
+            .child(XmlNode::with_value("nominal_srate", self.sample_rate))
+            .child(XmlNode::with_value("version", "1.1"))
+            .child(XmlNode::with_value("created_at", created_at))
+            .child(XmlNode::with_value("uid", uid))
+            .child(XmlNode::with_value("session_id", "default"))
+            .child(self.desc.clone());
+
+        let mut xml = String::from("<?xml version=\"1.0\"?>");
+        info.write_xml(&mut xml);
+        xml
+    }
+}
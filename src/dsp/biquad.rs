@@ -0,0 +1,173 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Second-order IIR sections, designed with the bilinear transform as in Robert Bristow-Johnson's
+//! Audio EQ Cookbook, and cascades of them for higher-order Butterworth responses.
+
+use std::f64::consts::PI;
+
+use super::Filter;
+
+/// Coefficients of one section, normalized so that a0 is 1
+#[derive(Copy, Clone, Debug)]
+pub struct Biquad {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl Biquad {
+    fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+        }
+    }
+
+    /// (cos ω0, alpha) for the cookbook formulas
+    fn cookbook_terms(frequency: f64, q: f64, sample_rate: f64) -> (f64, f64) {
+        let omega = 2.0 * PI * frequency / sample_rate;
+        (omega.cos(), omega.sin() / (2.0 * q))
+    }
+
+    pub fn lowpass(frequency: f64, q: f64, sample_rate: f64) -> Self {
+        let (cos, alpha) = Self::cookbook_terms(frequency, q, sample_rate);
+        Self::normalized(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    pub fn highpass(frequency: f64, q: f64, sample_rate: f64) -> Self {
+        let (cos, alpha) = Self::cookbook_terms(frequency, q, sample_rate);
+        Self::normalized(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// A notch whose -3 dB width is `frequency / q`
+    pub fn notch(frequency: f64, q: f64, sample_rate: f64) -> Self {
+        let (cos, alpha) = Self::cookbook_terms(frequency, q, sample_rate);
+        Self::normalized(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// A first-order lowpass, for odd Butterworth orders
+    pub fn lowpass1(frequency: f64, sample_rate: f64) -> Self {
+        let k = (PI * frequency / sample_rate).tan();
+        Self::normalized([k, k, 0.0], [k + 1.0, k - 1.0, 0.0])
+    }
+
+    /// A first-order highpass, for odd Butterworth orders
+    pub fn highpass1(frequency: f64, sample_rate: f64) -> Self {
+        let k = (PI * frequency / sample_rate).tan();
+        Self::normalized([1.0, -1.0, 0.0], [k + 1.0, k - 1.0, 0.0])
+    }
+
+    /// The sections of a Butterworth lowpass or highpass of the given order
+    pub fn butterworth(
+        order: usize,
+        frequency: f64,
+        sample_rate: f64,
+        highpass: bool,
+    ) -> Vec<Self> {
+        let mut sections: Vec<Self> = (0..order / 2)
+            .map(|k| {
+                // each pair of poles, at angle θ from the negative real axis, has Q = 1 / (2 cos θ).
+                // Odd orders also have a real pole, at θ = 0, which the first-order section adds.
+                let theta = if order & 1 == 0 {
+                    PI * (2 * k + 1) as f64 / (2 * order) as f64
+                } else {
+                    PI * (k + 1) as f64 / order as f64
+                };
+                let q = 1.0 / (2.0 * theta.cos());
+                if highpass {
+                    Self::highpass(frequency, q, sample_rate)
+                } else {
+                    Self::lowpass(frequency, q, sample_rate)
+                }
+            })
+            .collect();
+        if order & 1 == 1 {
+            sections.push(if highpass {
+                Self::highpass1(frequency, sample_rate)
+            } else {
+                Self::lowpass1(frequency, sample_rate)
+            });
+        }
+        sections
+    }
+
+    /// Gain at DC, which a constant input settles to
+    fn dc_gain(&self) -> f64 {
+        (self.b0 + self.b1 + self.b2) / (1.0 + self.a1 + self.a2)
+    }
+}
+
+/// Biquad sections applied one after another to every channel, in transposed direct form II
+pub struct BiquadCascade {
+    sections: Vec<Biquad>,
+    // two state variables per section per channel
+    state: Vec<Vec<[f64; 2]>>,
+}
+
+impl BiquadCascade {
+    pub fn new(sections: Vec<Biquad>, channels: usize) -> Self {
+        let state = vec![vec![[0.0; 2]; sections.len()]; channels];
+        Self { sections, state }
+    }
+
+    pub fn sections(&self) -> &[Biquad] {
+        &self.sections
+    }
+}
+
+impl Filter for BiquadCascade {
+    fn process(&mut self, frame: &mut [f64]) {
+        for (value, state) in frame.iter_mut().zip(&mut self.state) {
+            let mut x = *value;
+            for (section, z) in self.sections.iter().zip(state.iter_mut()) {
+                let y = section.b0 * x + z[0];
+                z[0] = section.b1 * x - section.a1 * y + z[1];
+                z[1] = section.b2 * x - section.a2 * y;
+                x = y;
+            }
+            *value = x;
+        }
+    }
+
+    fn settle(&mut self, frame: &[f64]) {
+        for (value, state) in frame.iter().zip(&mut self.state) {
+            let mut x = *value;
+            for (section, z) in self.sections.iter().zip(state.iter_mut()) {
+                let y = section.dc_gain() * x;
+                z[1] = section.b2 * x - section.a2 * y;
+                z[0] = section.b1 * x - section.a1 * y + z[1];
+                x = y;
+            }
+        }
+    }
+
+    fn delay(&self) -> usize {
+        0
+    }
+}
@@ -0,0 +1,32 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(Debug)]
+pub enum DspError {
+    BadFilter(String),
+    BadRate(String),
+    BadSpectrum(String),
+}
+
+impl std::error::Error for DspError {}
+
+impl std::fmt::Display for DspError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            DspError::BadFilter(message) => write!(f, "{}", message),
+            DspError::BadRate(message) => write!(f, "{}", message),
+            DspError::BadSpectrum(message) => write!(f, "{}", message),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Linear-phase FIR filters, designed by windowing a sinc with a Hamming window.  They delay
+//! the signal by `(taps - 1) / 2` samples, but don't distort the shape of the waveform.
+
+use std::f64::consts::PI;
+
+use super::Filter;
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn hamming(n: usize, taps: usize) -> f64 {
+    if taps == 1 {
+        return 1.0;
+    }
+    0.54 - 0.46 * (2.0 * PI * n as f64 / (taps - 1) as f64).cos()
+}
+
+/// Taps of a lowpass with unity gain at DC.  `taps` should be odd, so the delay is a whole
+/// number of samples.
+pub fn lowpass_taps(taps: usize, cutoff: f64, sample_rate: f64) -> Vec<f64> {
+    let fc = cutoff / sample_rate;
+    let middle = (taps - 1) as f64 / 2.0;
+    let mut h: Vec<f64> = (0..taps)
+        .map(|n| 2.0 * fc * sinc(2.0 * fc * (n as f64 - middle)) * hamming(n, taps))
+        .collect();
+    let sum: f64 = h.iter().sum();
+    for tap in &mut h {
+        *tap /= sum;
+    }
+    h
+}
+
+/// Taps of a highpass, by spectral inversion of the lowpass
+pub fn highpass_taps(taps: usize, cutoff: f64, sample_rate: f64) -> Vec<f64> {
+    let mut h = lowpass_taps(taps, cutoff, sample_rate);
+    for tap in &mut h {
+        *tap = -*tap;
+    }
+    h[taps / 2] += 1.0;
+    h
+}
+
+/// Taps of a bandpass, as the difference of two lowpasses
+pub fn bandpass_taps(taps: usize, low: f64, high: f64, sample_rate: f64) -> Vec<f64> {
+    let upper = lowpass_taps(taps, high, sample_rate);
+    let lower = lowpass_taps(taps, low, sample_rate);
+    upper.iter().zip(&lower).map(|(u, l)| u - l).collect()
+}
+
+/// An FIR filter applied to every channel
+pub struct Fir {
+    taps: Vec<f64>,
+    // per-channel ring buffers of the last `taps.len()` inputs
+    history: Vec<Vec<f64>>,
+    position: usize,
+}
+
+impl Fir {
+    pub fn new(taps: Vec<f64>, channels: usize) -> Self {
+        let history = vec![vec![0.0; taps.len()]; channels];
+        Self {
+            taps,
+            history,
+            position: 0,
+        }
+    }
+
+    pub fn taps(&self) -> &[f64] {
+        &self.taps
+    }
+}
+
+impl Filter for Fir {
+    fn process(&mut self, frame: &mut [f64]) {
+        let len = self.taps.len();
+        for (value, history) in frame.iter_mut().zip(&mut self.history) {
+            history[self.position] = *value;
+            // the newest input meets the first tap
+            let mut sum = 0.0;
+            for (k, tap) in self.taps.iter().enumerate() {
+                sum += tap * history[(self.position + len - k) % len];
+            }
+            *value = sum;
+        }
+        self.position = (self.position + 1) % len;
+    }
+
+    fn settle(&mut self, frame: &[f64]) {
+        for (value, history) in frame.iter().zip(&mut self.history) {
+            for past in history.iter_mut() {
+                *past = *value;
+            }
+        }
+    }
+
+    fn delay(&self) -> usize {
+        (self.taps.len() - 1) / 2
+    }
+}
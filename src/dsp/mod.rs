@@ -0,0 +1,303 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Digital filters for removing DC offset, drift and mains hum from the channels.
+//!
+//! Streaming filters keep their state between calls, so a session can be filtered a sample at
+//! a time as it arrives.  `zero_phase` filters a whole recording forwards and then backwards,
+//! cancelling the phase shift, for offline use.  A `Resampler` brings a stream down to a lower
+//! rate without aliasing, and `Welch` tracks each channel's power spectrum and band powers.
+
+pub mod biquad;
+mod err;
+pub mod fir;
+mod resample;
+mod spec;
+mod spectrum;
+
+use crate::client::sample::Sample;
+use crate::common::config::{DIGITAL_MAX, DIGITAL_MIN};
+use crate::common::constants::NUM_CHANNELS;
+pub use err::DspError;
+pub use resample::Resampler;
+pub use spec::{FilterKind, FilterSpec};
+pub use spectrum::{Band, Taper, Welch, WelchOptions};
+
+pub type DspResult<T> = Result<T, DspError>;
+
+/// Rounds a filtered value back to counts, saturating at the 24-bit range, which filters
+/// overshoot on steps and near-railed channels
+fn to_counts(value: f64) -> i32 {
+    value
+        .round()
+        .max(DIGITAL_MIN as f64)
+        .min(DIGITAL_MAX as f64) as i32
+}
+
+/// A filter applied independently to each channel
+pub trait Filter: Send {
+    /// Replaces one value per channel with the filter's output
+    fn process(&mut self, frame: &mut [f64]);
+
+    /// Sets the state as though each channel had always been at its value in `frame`, so a
+    /// large DC offset doesn't start a long transient
+    fn settle(&mut self, frame: &[f64]);
+
+    /// Samples of delay for a constant group delay, or 0 if the delay varies with frequency
+    fn delay(&self) -> usize;
+}
+
+/// Filters applied one after another, settled on the first frame they see
+pub struct FilterChain {
+    specs: Vec<FilterSpec>,
+    filters: Vec<Box<dyn Filter>>,
+    settled: bool,
+    frame: Vec<f64>,
+}
+
+impl FilterChain {
+    pub fn new(specs: &[FilterSpec], sample_rate: u32, channels: usize) -> DspResult<Self> {
+        let filters = specs
+            .iter()
+            .map(|spec| spec.build(sample_rate, channels))
+            .collect::<DspResult<Vec<_>>>()?;
+        Ok(Self {
+            specs: specs.to_vec(),
+            filters,
+            settled: false,
+            frame: vec![0.0; channels],
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn specs(&self) -> &[FilterSpec] {
+        &self.specs
+    }
+
+    /// The filters in the style of EDF's prefiltering field, e.g. `HP:0.5Hz LP:40Hz N:60Hz`
+    pub fn description(&self) -> String {
+        let specs: Vec<String> = self.specs.iter().map(|spec| spec.to_string()).collect();
+        specs.join(" ")
+    }
+
+    /// Samples of delay added by linear-phase filters
+    pub fn delay(&self) -> usize {
+        self.filters.iter().map(|filter| filter.delay()).sum()
+    }
+
+    pub fn process_frame(&mut self, frame: &mut [f64]) {
+        for filter in &mut self.filters {
+            if !self.settled {
+                filter.settle(frame);
+            }
+            filter.process(frame);
+        }
+        self.settled = true;
+    }
+
+    /// Filters a sample's channels in place, rounding back to counts within the 24-bit range
+    pub fn process_sample(&mut self, sample: &mut Sample) {
+        if self.filters.is_empty() {
+            return;
+        }
+        let mut frame = std::mem::take(&mut self.frame);
+        for (value, channel) in frame.iter_mut().zip(&sample.channels) {
+            *value = channel.sample as f64;
+        }
+        self.process_frame(&mut frame);
+        for (value, channel) in frame.iter().zip(&mut sample.channels) {
+            channel.sample = to_counts(*value);
+        }
+        self.frame = frame;
+    }
+
+    pub fn process_block(&mut self, samples: &mut [Sample]) {
+        for sample in samples {
+            self.process_sample(sample);
+        }
+    }
+}
+
+/// Filters a whole signal forwards and then backwards, so the result has no phase shift and
+/// its magnitude response is that of the filters squared.  The ends are extended by an odd
+/// reflection of up to a second of signal, to keep the start-up transients out of the result.
+pub fn zero_phase(specs: &[FilterSpec], sample_rate: u32, signal: &mut [f64]) -> DspResult<()> {
+    if signal.len() < 2 || specs.is_empty() {
+        return Ok(());
+    }
+    let pad = (sample_rate as usize).min(signal.len() - 1);
+    let first = signal[0];
+    let last = signal[signal.len() - 1];
+    let mut padded: Vec<f64> = Vec::with_capacity(signal.len() + 2 * pad);
+    padded.extend(signal[1..=pad].iter().rev().map(|x| 2.0 * first - x));
+    padded.extend_from_slice(signal);
+    padded.extend(
+        signal[signal.len() - 1 - pad..signal.len() - 1]
+            .iter()
+            .rev()
+            .map(|x| 2.0 * last - x),
+    );
+
+    for _ in 0..2 {
+        let mut chain = FilterChain::new(specs, sample_rate, 1)?;
+        for value in padded.iter_mut() {
+            chain.process_frame(std::slice::from_mut(value));
+        }
+        padded.reverse();
+    }
+
+    signal.copy_from_slice(&padded[pad..pad + signal.len()]);
+    Ok(())
+}
+
+/// Applies `zero_phase` to each channel of a block of samples
+pub fn zero_phase_samples(
+    specs: &[FilterSpec],
+    sample_rate: u32,
+    samples: &mut [Sample],
+) -> DspResult<()> {
+    let mut signal = vec![0.0; samples.len()];
+    for chan_idx in 0..NUM_CHANNELS {
+        for (value, sample) in signal.iter_mut().zip(samples.iter()) {
+            *value = sample.channels[chan_idx].sample as f64;
+        }
+        zero_phase(specs, sample_rate, &mut signal)?;
+        for (value, sample) in signal.iter().zip(samples.iter_mut()) {
+            sample.channels[chan_idx].sample = to_counts(*value);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Steady-state amplitude of a unit sine at `frequency` after the filters in `specs`
+    fn gain(specs: &str, sample_rate: u32, frequency: f64) -> f64 {
+        let specs: Vec<FilterSpec> = specs.split(' ').map(|s| s.parse().unwrap()).collect();
+        let mut chain = FilterChain::new(&specs, sample_rate, 1).unwrap();
+        // long enough for the slowest filters to settle, then a whole number of cycles
+        let settle = 20 * sample_rate as usize;
+        let measure = 10 * sample_rate as usize;
+        let (mut re, mut im) = (0.0, 0.0);
+        for n in 0..settle + measure {
+            let phase = 2.0 * PI * frequency * n as f64 / sample_rate as f64;
+            let mut value = [phase.sin()];
+            chain.process_frame(&mut value);
+            if n >= settle {
+                re += value[0] * phase.sin();
+                im += value[0] * phase.cos();
+            }
+        }
+        2.0 * (re * re + im * im).sqrt() / measure as f64
+    }
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    fn sample_with(value: i32) -> Sample {
+        let mut sample = Sample::from_bytes(&[0; 11 + 3 * NUM_CHANNELS]);
+        for channel in sample.channels.iter_mut() {
+            channel.sample = value;
+        }
+        sample
+    }
+
+    #[test]
+    fn butterworth_is_3db_down_at_cutoff() {
+        let half_power = std::f64::consts::FRAC_1_SQRT_2;
+        for &order in &[1, 2, 3, 4, 8] {
+            let highpass = format!("highpass:1,order={}", order);
+            assert_near(gain(&highpass, 250, 1.0), half_power, 0.01);
+            assert_near(gain(&highpass, 250, 20.0), 1.0, 0.01);
+
+            let lowpass = format!("lowpass:40,order={}", order);
+            assert_near(gain(&lowpass, 250, 40.0), half_power, 0.01);
+            assert_near(gain(&lowpass, 250, 2.0), 1.0, 0.01);
+        }
+        assert!(gain("lowpass:40,order=4", 250, 100.0) < 0.01);
+    }
+
+    #[test]
+    fn bandpass_passes_the_middle() {
+        let half_power = std::f64::consts::FRAC_1_SQRT_2;
+        assert_near(gain("bandpass:1-40", 250, 10.0), 1.0, 0.01);
+        assert_near(gain("bandpass:1-40", 250, 1.0), half_power, 0.02);
+        assert_near(gain("bandpass:1-40", 250, 40.0), half_power, 0.02);
+    }
+
+    #[test]
+    fn notch_removes_its_frequency_only() {
+        assert!(gain("notch:60", 250, 60.0) < 0.01);
+        assert_near(gain("notch:60", 250, 50.0), 1.0, 0.02);
+        assert_near(gain("notch:60", 250, 10.0), 1.0, 0.01);
+        assert!(gain("notch:50", 500, 50.0) < 0.01);
+    }
+
+    #[test]
+    fn fir_lowpass_is_6db_down_at_cutoff() {
+        assert_near(gain("lowpass:40,taps=101", 250, 40.0), 0.5, 0.02);
+        assert_near(gain("lowpass:40,taps=101", 250, 5.0), 1.0, 0.01);
+        assert!(gain("lowpass:40,taps=101", 250, 80.0) < 0.01);
+    }
+
+    #[test]
+    fn filtered_samples_saturate() {
+        // a full-scale step overshoots the 24-bit range on the way through either filter
+        let specs = ["highpass:1".parse().unwrap()];
+        let mut chain = FilterChain::new(&specs, 250, NUM_CHANNELS).unwrap();
+        let mut peak = 0;
+        for n in 0..500 {
+            let value = if n < 250 { DIGITAL_MIN } else { DIGITAL_MAX };
+            let mut sample = sample_with(value);
+            chain.process_sample(&mut sample);
+            peak = peak.max(sample.channels[0].sample);
+        }
+        assert_eq!(peak, DIGITAL_MAX);
+
+        let specs = ["lowpass:10".parse().unwrap()];
+        let mut samples: Vec<Sample> = (0..500)
+            .map(|n| sample_with(if n < 250 { DIGITAL_MIN } else { DIGITAL_MAX }))
+            .collect();
+        zero_phase_samples(&specs, 250, &mut samples).unwrap();
+        let min = samples.iter().map(|s| s.channels[0].sample).min().unwrap();
+        let max = samples.iter().map(|s| s.channels[0].sample).max().unwrap();
+        assert_eq!((min, max), (DIGITAL_MIN, DIGITAL_MAX));
+    }
+
+    #[test]
+    fn zero_phase_has_no_delay() {
+        let specs = ["lowpass:40".parse().unwrap()];
+        let signal: Vec<f64> = (0..1000)
+            .map(|n| (2.0 * PI * 5.0 * n as f64 / 250.0).sin())
+            .collect();
+        let mut filtered = signal.clone();
+        zero_phase(&specs, 250, &mut filtered).unwrap();
+        for (x, y) in signal.iter().zip(&filtered) {
+            assert_near(*y, *x, 0.01);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Changes the sample rate by a rational factor `up / down` with a polyphase FIR filter.
+//!
+//! Conceptually the input is upsampled by inserting `up - 1` zeros between samples, lowpass
+//! filtered at the lower of the two Nyquist frequencies, and every `down`th sample kept.  The
+//! filter is split into `up` phases of `taps / up` coefficients so only the kept outputs are
+//! computed, and the inserted zeros are never multiplied.  Integer decimation is the case
+//! `up == 1`.
+
+use super::fir::lowpass_taps;
+use super::{to_counts, DspError, DspResult};
+use crate::client::sample::Sample;
+
+// the lowpass passes up to 40% of the lower rate and stops at half of it, so only what the
+// passband can't hold is folded back
+const CUTOFF: f64 = 0.45;
+const TRANSITION: f64 = 0.1;
+
+// taps needed per unit of transition width for a Hamming window
+const HAMMING_WIDTH: f64 = 3.3;
+
+// filters any longer take too long to design for ratios that don't reduce, e.g. 16384 to 997
+const MAX_TAPS: usize = 1 << 21;
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    up: usize,
+    down: usize,
+    // phases[p][k] is tap p + k * up of the prototype filter
+    phases: Vec<Vec<f64>>,
+    // per-channel ring buffers of the last `phases[0].len()` inputs
+    history: Vec<Vec<f64>>,
+    position: usize,
+    // the next output's index at the upsampled rate, relative to the current input
+    next: usize,
+    settled: bool,
+    frame: Vec<f64>,
+    outputs: Vec<f64>,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32, channels: usize) -> DspResult<Self> {
+        if input_rate == 0 || output_rate == 0 {
+            return Err(DspError::BadRate(
+                "sample rates must be greater than 0".to_string(),
+            ));
+        }
+        let divisor = gcd(input_rate, output_rate);
+        let up = (output_rate / divisor) as usize;
+        let down = (input_rate / divisor) as usize;
+
+        let upsampled_rate = input_rate as f64 * up as f64;
+        let lower_rate = input_rate.min(output_rate) as f64;
+        let per_phase = (HAMMING_WIDTH * upsampled_rate / (TRANSITION * lower_rate) / up as f64)
+            .ceil() as usize;
+        // odd, so the delay is a whole number of input samples when not upsampling
+        let per_phase = per_phase.max(1) | 1;
+        let taps = per_phase * up;
+        if taps > MAX_TAPS {
+            return Err(DspError::BadRate(format!(
+                "resampling from {} to {} Hz needs too long a filter; choose a rate that \
+                 divides {} more evenly",
+                input_rate, output_rate, input_rate
+            )));
+        }
+
+        let prototype = lowpass_taps(taps, CUTOFF * lower_rate, upsampled_rate);
+        // each phase is scaled to unity gain at DC, which the prototype only approximates when
+        // its phases are short
+        let phases = (0..up)
+            .map(|phase| {
+                let taps: Vec<f64> = prototype[phase..].iter().step_by(up).copied().collect();
+                let sum: f64 = taps.iter().sum();
+                taps.iter().map(|tap| tap / sum).collect()
+            })
+            .collect();
+
+        Ok(Self {
+            input_rate,
+            output_rate,
+            up,
+            down,
+            phases,
+            history: vec![vec![0.0; per_phase]; channels],
+            position: 0,
+            next: 0,
+            settled: false,
+            frame: vec![0.0; channels],
+            outputs: Vec::new(),
+        })
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// The factors `(up, down)` the rate is multiplied and divided by
+    pub fn ratio(&self) -> (usize, usize) {
+        (self.up, self.down)
+    }
+
+    /// Seconds the filter delays the signal by
+    pub fn delay(&self) -> f64 {
+        let taps = self.phases.len() * self.phases[0].len();
+        (taps - 1) as f64 / 2.0 / (self.input_rate as f64 * self.up as f64)
+    }
+
+    /// Takes one value per channel, appending a frame to `out` for each output sample it
+    /// completes, which may be none or, when upsampling, several
+    pub fn process_frame(&mut self, frame: &[f64], out: &mut Vec<f64>) {
+        let per_phase = self.phases[0].len();
+        if !self.settled {
+            // as though each channel had always been at its first value
+            for (value, history) in frame.iter().zip(&mut self.history) {
+                for past in history.iter_mut() {
+                    *past = *value;
+                }
+            }
+            self.settled = true;
+        }
+        for (value, history) in frame.iter().zip(&mut self.history) {
+            history[self.position] = *value;
+        }
+
+        // outputs between this input and the next, at the upsampled rate
+        while self.next < self.up {
+            let phase = &self.phases[self.next];
+            for history in &self.history {
+                // the newest input meets the phase's first tap
+                let mut sum = 0.0;
+                for (k, tap) in phase.iter().enumerate() {
+                    sum += tap * history[(self.position + per_phase - k) % per_phase];
+                }
+                out.push(sum);
+            }
+            self.next += self.down;
+        }
+        self.next -= self.up;
+        self.position = (self.position + 1) % per_phase;
+    }
+
+    /// Resamples a sample's channels, rounding back to counts within the 24-bit range, which
+    /// the lowpass rings past on steps.  Each output carries the sample number, timestamps and
+    /// status bits of the latest input, which is the closest one to it in time before the
+    /// filter's delay.
+    pub fn process_sample(&mut self, sample: &Sample, out: &mut Vec<Sample>) {
+        let mut frame = std::mem::take(&mut self.frame);
+        for (value, channel) in frame.iter_mut().zip(&sample.channels) {
+            *value = channel.sample as f64;
+        }
+        let mut outputs = std::mem::take(&mut self.outputs);
+        self.process_frame(&frame, &mut outputs);
+        for values in outputs.chunks(frame.len()) {
+            let mut resampled = sample.clone();
+            for (channel, value) in resampled.channels.iter_mut().zip(values) {
+                channel.sample = to_counts(*value);
+            }
+            out.push(resampled);
+        }
+        outputs.clear();
+        self.outputs = outputs;
+        self.frame = frame;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::{DIGITAL_MAX, DIGITAL_MIN};
+    use crate::common::constants::NUM_CHANNELS;
+    use std::f64::consts::PI;
+
+    /// Resamples `seconds` of a unit sine at `frequency`
+    fn resample_sine(input_rate: u32, output_rate: u32, frequency: f64, seconds: u32) -> Vec<f64> {
+        let mut resampler = Resampler::new(input_rate, output_rate, 1).unwrap();
+        let mut out = Vec::new();
+        for n in 0..input_rate * seconds {
+            let phase = 2.0 * PI * frequency * n as f64 / input_rate as f64;
+            resampler.process_frame(&[phase.sin()], &mut out);
+        }
+        out
+    }
+
+    /// Amplitude of the component of `signal` at `frequency`, over a whole number of cycles
+    fn amplitude(signal: &[f64], sample_rate: u32, frequency: f64) -> f64 {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (n, value) in signal.iter().enumerate() {
+            let phase = 2.0 * PI * frequency * n as f64 / sample_rate as f64;
+            re += value * phase.sin();
+            im += value * phase.cos();
+        }
+        2.0 * (re * re + im * im).sqrt() / signal.len() as f64
+    }
+
+    #[test]
+    fn output_count_follows_the_ratio() {
+        for &(input_rate, output_rate) in &[
+            (1000, 250),
+            (250, 125),
+            (1000, 300),
+            (500, 200),
+            (250, 1000),
+            (16000, 250),
+        ] {
+            let mut resampler = Resampler::new(input_rate, output_rate, 2).unwrap();
+            let mut out = Vec::new();
+            for n in 0..input_rate * 4 {
+                resampler.process_frame(&[n as f64, -(n as f64)], &mut out);
+            }
+            assert_eq!(out.len(), 2 * 4 * output_rate as usize);
+        }
+        assert!(Resampler::new(0, 250, 1).is_err());
+    }
+
+    #[test]
+    fn passband_has_unity_gain() {
+        for &(input_rate, output_rate, frequency) in &[
+            (1000, 250, 10.0),
+            (1000, 300, 20.0),
+            (16000, 250, 40.0),
+            (250, 1000, 10.0),
+        ] {
+            let out = resample_sine(input_rate, output_rate, frequency, 6);
+            // skip the first two seconds while the filter fills up
+            let settled = &out[2 * output_rate as usize..];
+            let gain = amplitude(settled, output_rate, frequency);
+            assert!(
+                (gain - 1.0).abs() < 0.01,
+                "gain {} at {} Hz",
+                gain,
+                frequency
+            );
+        }
+
+        let mut resampler = Resampler::new(1000, 250, 1).unwrap();
+        let mut out = Vec::new();
+        for _ in 0..1000 {
+            resampler.process_frame(&[1000.0], &mut out);
+        }
+        assert!(out.iter().all(|value| (value - 1000.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn frequencies_above_the_new_nyquist_are_removed() {
+        // 200 Hz would alias to 50 Hz at 250 Hz
+        let out = resample_sine(1000, 250, 200.0, 6);
+        let peak = out[2 * 250..]
+            .iter()
+            .fold(0.0f64, |peak, x| peak.max(x.abs()));
+        assert!(peak < 0.01, "peak {}", peak);
+    }
+
+    #[test]
+    fn resampled_samples_saturate() {
+        let mut resampler = Resampler::new(1000, 250, NUM_CHANNELS).unwrap();
+        let mut out = Vec::new();
+        for n in 0..1000 {
+            // the lowpass rings past the 24-bit range on a full-scale step
+            let mut sample = Sample::from_bytes(&[0; 11 + 3 * NUM_CHANNELS]);
+            for channel in sample.channels.iter_mut() {
+                channel.sample = if n < 500 { DIGITAL_MIN } else { DIGITAL_MAX };
+            }
+            resampler.process_sample(&sample, &mut out);
+        }
+        assert_eq!(out.len(), 250);
+        let values = out.iter().map(|sample| sample.channels[0].sample);
+        assert_eq!(values.clone().min(), Some(DIGITAL_MIN));
+        assert_eq!(values.max(), Some(DIGITAL_MAX));
+    }
+}
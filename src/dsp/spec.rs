@@ -0,0 +1,253 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use super::biquad::{Biquad, BiquadCascade};
+use super::fir::{self, Fir};
+use super::{DspError, DspResult, Filter};
+
+pub const DEFAULT_ORDER: usize = 4;
+pub const MAX_ORDER: usize = 16;
+// longer FIR filters cost more per sample than a live stream can spare
+pub const MAX_TAPS: usize = 8191;
+pub const DEFAULT_NOTCH_Q: f64 = 30.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterKind {
+    Highpass(f64),
+    Lowpass(f64),
+    Bandpass(f64, f64),
+    /// a notch at the frequency and its first `harmonics - 1` multiples
+    Notch {
+        frequency: f64,
+        harmonics: usize,
+    },
+}
+
+/// A filter as given on the command line, e.g. `highpass:0.5`, `bandpass:1-40,order=2`,
+/// `lowpass:40,taps=101` or `notch:50,harmonics=3`.
+///
+/// Highpass, lowpass and bandpass filters are Butterworth IIR filters of `order` (default 4),
+/// unless `taps` is given, which makes them linear-phase FIR filters instead.  Notches are
+/// biquads with a quality factor `q` (default 30), i.e. 2 Hz wide at 60 Hz.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FilterSpec {
+    pub kind: FilterKind,
+    pub order: usize,
+    pub taps: Option<usize>,
+    pub q: f64,
+}
+
+impl FilterSpec {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            order: DEFAULT_ORDER,
+            taps: None,
+            q: DEFAULT_NOTCH_Q,
+        }
+    }
+
+    fn check_frequency(frequency: f64, sample_rate: f64) -> DspResult<()> {
+        if frequency <= 0.0 || frequency >= sample_rate / 2.0 {
+            return Err(DspError::BadFilter(format!(
+                "Filter frequency {} Hz must be between 0 and {} Hz, half the sample rate",
+                frequency,
+                sample_rate / 2.0
+            )));
+        }
+        Ok(())
+    }
+
+    /// Designs the filter for `channels` channels at `sample_rate`
+    pub fn build(&self, sample_rate: u32, channels: usize) -> DspResult<Box<dyn Filter>> {
+        let sample_rate = sample_rate as f64;
+
+        // a bandpass is a highpass at its low edge and a lowpass at its high edge
+        let (highpass, lowpass) = match self.kind {
+            FilterKind::Highpass(frequency) => (Some(frequency), None),
+            FilterKind::Lowpass(frequency) => (None, Some(frequency)),
+            FilterKind::Bandpass(low, high) => {
+                if low >= high {
+                    return Err(DspError::BadFilter(format!(
+                        "Bandpass {}-{} Hz has its edges the wrong way round",
+                        low, high
+                    )));
+                }
+                (Some(low), Some(high))
+            }
+            FilterKind::Notch {
+                frequency,
+                harmonics,
+            } => {
+                Self::check_frequency(frequency, sample_rate)?;
+                // harmonics at or above Nyquist alias onto other frequencies, so there's
+                // nothing to remove there
+                let sections = (1..=harmonics.max(1))
+                    .map(|harmonic| frequency * harmonic as f64)
+                    .take_while(|frequency| *frequency < sample_rate / 2.0)
+                    .map(|frequency| Biquad::notch(frequency, self.q, sample_rate))
+                    .collect();
+                return Ok(Box::new(BiquadCascade::new(sections, channels)));
+            }
+        };
+        for frequency in highpass.iter().chain(lowpass.iter()) {
+            Self::check_frequency(*frequency, sample_rate)?;
+        }
+
+        if let Some(taps) = self.taps {
+            // an odd length keeps the delay a whole number of samples
+            let taps = taps.max(3) | 1;
+            let coefficients = match (highpass, lowpass) {
+                (Some(low), Some(high)) => fir::bandpass_taps(taps, low, high, sample_rate),
+                (Some(low), None) => fir::highpass_taps(taps, low, sample_rate),
+                (None, Some(high)) => fir::lowpass_taps(taps, high, sample_rate),
+                (None, None) => vec![1.0],
+            };
+            return Ok(Box::new(Fir::new(coefficients, channels)));
+        }
+
+        let mut sections = Vec::new();
+        if let Some(low) = highpass {
+            sections.extend(Biquad::butterworth(self.order, low, sample_rate, true));
+        }
+        if let Some(high) = lowpass {
+            sections.extend(Biquad::butterworth(self.order, high, sample_rate, false));
+        }
+        Ok(Box::new(BiquadCascade::new(sections, channels)))
+    }
+}
+
+fn parse_number<T: FromStr>(value: &str, what: &str, spec: &str) -> DspResult<T> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| DspError::BadFilter(format!("Bad {} '{}' in filter '{}'", what, value, spec)))
+}
+
+impl FromStr for FilterSpec {
+    type Err = DspError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let head = parts.next().unwrap_or("");
+        let (kind, frequencies) = match head.find(':') {
+            Some(colon) => (&head[..colon], &head[colon + 1..]),
+            None => {
+                return Err(DspError::BadFilter(format!(
+                    "Filter '{}' should look like highpass:0.5, bandpass:1-40 or notch:60",
+                    s
+                )))
+            }
+        };
+
+        let kind = match kind.trim().to_lowercase().as_str() {
+            "highpass" | "hp" => FilterKind::Highpass(parse_number(frequencies, "frequency", s)?),
+            "lowpass" | "lp" => FilterKind::Lowpass(parse_number(frequencies, "frequency", s)?),
+            "bandpass" | "bp" => {
+                let mut edges = frequencies.splitn(2, '-');
+                let low = parse_number(edges.next().unwrap_or(""), "frequency", s)?;
+                let high = parse_number(edges.next().unwrap_or(""), "frequency", s)?;
+                FilterKind::Bandpass(low, high)
+            }
+            "notch" => FilterKind::Notch {
+                frequency: parse_number(frequencies, "frequency", s)?,
+                harmonics: 1,
+            },
+            other => {
+                return Err(DspError::BadFilter(format!(
+                    "Unknown filter type '{}'",
+                    other
+                )))
+            }
+        };
+
+        let mut spec = FilterSpec::new(kind);
+        for option in parts {
+            let mut key_value = option.splitn(2, '=');
+            let key = key_value.next().unwrap_or("").trim();
+            let value = key_value.next().unwrap_or("");
+            match key {
+                "order" => {
+                    spec.order = parse_number(value, "order", s)?;
+                    if spec.order == 0 || spec.order > MAX_ORDER {
+                        return Err(DspError::BadFilter(format!(
+                            "Filter order must be from 1 to {}",
+                            MAX_ORDER
+                        )));
+                    }
+                }
+                "taps" => {
+                    let taps = parse_number(value, "number of taps", s)?;
+                    if taps == 0 || taps > MAX_TAPS {
+                        return Err(DspError::BadFilter(format!(
+                            "Number of taps must be from 1 to {}",
+                            MAX_TAPS
+                        )));
+                    }
+                    spec.taps = Some(taps);
+                }
+                "q" => {
+                    spec.q = parse_number(value, "q", s)?;
+                    if !(spec.q.is_finite() && spec.q > 0.0) {
+                        return Err(DspError::BadFilter(format!(
+                            "Filter q must be more than 0, in '{}'",
+                            s
+                        )));
+                    }
+                }
+                "harmonics" => match &mut spec.kind {
+                    FilterKind::Notch { harmonics, .. } => {
+                        *harmonics = parse_number(value, "number of harmonics", s)?
+                    }
+                    _ => {
+                        return Err(DspError::BadFilter(format!(
+                            "Only notch filters have harmonics, in '{}'",
+                            s
+                        )))
+                    }
+                },
+                _ => {
+                    return Err(DspError::BadFilter(format!(
+                        "Unknown filter option '{}' in '{}'",
+                        key, s
+                    )))
+                }
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// In the style of EDF's prefiltering field, e.g. `HP:0.5Hz` or `N:60Hz`
+impl std::fmt::Display for FilterSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            FilterKind::Highpass(frequency) => write!(f, "HP:{}Hz", frequency),
+            FilterKind::Lowpass(frequency) => write!(f, "LP:{}Hz", frequency),
+            FilterKind::Bandpass(low, high) => write!(f, "HP:{}Hz LP:{}Hz", low, high),
+            FilterKind::Notch {
+                frequency,
+                harmonics,
+            } => {
+                write!(f, "N:{}Hz", frequency)?;
+                if harmonics > 1 {
+                    write!(f, "x{}", harmonics)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Power spectral density by Welch's method, updated as samples arrive.
+//!
+//! Each channel's recent samples are cut into overlapping segments; each segment has its mean
+//! removed, is tapered and transformed, and the periodograms of the last few segments are
+//! averaged.  A new estimate is ready whenever another segment completes, i.e. every
+//! `segment * (1 - overlap)` seconds.
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::{DspError, DspResult};
+
+/// The window each segment is multiplied by before its transform
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Taper {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Taper {
+    /// The periodic form of the window, as spectral estimates use
+    pub fn weights(&self, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| {
+                let x = 2.0 * PI * n as f64 / len as f64;
+                match self {
+                    Taper::Rectangular => 1.0,
+                    Taper::Hann => 0.5 - 0.5 * x.cos(),
+                    Taper::Hamming => 0.54 - 0.46 * x.cos(),
+                    Taper::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Taper {
+    type Err = DspError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "rectangular" | "boxcar" | "none" => Ok(Taper::Rectangular),
+            "hann" | "hanning" => Ok(Taper::Hann),
+            "hamming" => Ok(Taper::Hamming),
+            "blackman" => Ok(Taper::Blackman),
+            other => Err(DspError::BadSpectrum(format!(
+                "Unknown taper '{}'; use rectangular, hann, hamming or blackman",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Taper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Taper::Rectangular => "rectangular",
+            Taper::Hann => "hann",
+            Taper::Hamming => "hamming",
+            Taper::Blackman => "blackman",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A frequency range whose power is reported, e.g. `alpha` or `smr:12-15`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Band {
+    pub name: String,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Band {
+    pub fn new(name: &str, low: f64, high: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            low,
+            high,
+        }
+    }
+
+    /// The usual EEG bands: delta, theta, alpha, beta and gamma
+    pub fn standard() -> Vec<Band> {
+        vec![
+            Band::new("delta", 1.0, 4.0),
+            Band::new("theta", 4.0, 8.0),
+            Band::new("alpha", 8.0, 13.0),
+            Band::new("beta", 13.0, 30.0),
+            Band::new("gamma", 30.0, 45.0),
+        ]
+    }
+
+    /// Parses a comma-separated list of bands
+    pub fn parse_list(list: &str) -> DspResult<Vec<Band>> {
+        list.split(',').map(str::parse).collect()
+    }
+}
+
+impl FromStr for Band {
+    type Err = DspError;
+
+    /// A standard band's name, or `name:low-high`, or just `low-high`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(band) = Band::standard().into_iter().find(|band| band.name == s) {
+            return Ok(band);
+        }
+        let (name, range) = match s.find(':') {
+            Some(colon) => (&s[..colon], &s[colon + 1..]),
+            None => (s, s),
+        };
+        let bad = || {
+            DspError::BadSpectrum(format!(
+                "Band '{}' should be delta, theta, alpha, beta, gamma or look like smr:12-15",
+                s
+            ))
+        };
+        let mut edges = range.splitn(2, '-');
+        let low = edges
+            .next()
+            .unwrap_or("")
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| bad())?;
+        let high = edges
+            .next()
+            .unwrap_or("")
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| bad())?;
+        if low < 0.0 || low >= high {
+            return Err(DspError::BadSpectrum(format!(
+                "Band '{}' needs a low edge below its high edge",
+                s
+            )));
+        }
+        Ok(Band::new(name.trim(), low, high))
+    }
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = format!("{}-{}", self.low, self.high);
+        if self.name == range {
+            write!(f, "{}", range)
+        } else {
+            write!(f, "{}:{}", self.name, range)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WelchOptions {
+    /// seconds per segment; the frequency resolution is its reciprocal
+    pub segment: f64,
+    /// fraction of each segment shared with the next, from 0 up to but not including 1
+    pub overlap: f64,
+    pub taper: Taper,
+    /// segments averaged into each estimate
+    pub average: usize,
+}
+
+impl Default for WelchOptions {
+    fn default() -> Self {
+        Self {
+            segment: 1.0,
+            overlap: 0.5,
+            taper: Taper::Hann,
+            average: 4,
+        }
+    }
+}
+
+pub struct Welch {
+    sample_rate: f64,
+    taper: Vec<f64>,
+    // sum of the squared taper, which normalizes the periodograms
+    taper_power: f64,
+    // samples between segments
+    hop: usize,
+    average: usize,
+    fft: Arc<dyn Fft<f64>>,
+    buffer: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+    // per-channel ring buffers of the last segment's samples
+    history: Vec<Vec<f64>>,
+    position: usize,
+    seen: usize,
+    since_segment: usize,
+    // per-channel periodograms of recent segments, and their sum
+    periodograms: Vec<VecDeque<Vec<f64>>>,
+    sums: Vec<Vec<f64>>,
+    psd: Vec<Vec<f64>>,
+}
+
+impl Welch {
+    pub fn new(sample_rate: u32, channels: usize, options: &WelchOptions) -> DspResult<Self> {
+        let len = (options.segment * sample_rate as f64).round() as usize;
+        if len < 2 {
+            return Err(DspError::BadSpectrum(format!(
+                "A {} s segment is too short at {} samples per second",
+                options.segment, sample_rate
+            )));
+        }
+        if !(0.0..1.0).contains(&options.overlap) {
+            return Err(DspError::BadSpectrum(format!(
+                "Overlap {} must be at least 0 and less than 1",
+                options.overlap
+            )));
+        }
+        let hop = ((len as f64 * (1.0 - options.overlap)).round() as usize).max(1);
+        let taper = options.taper.weights(len);
+        let taper_power = taper.iter().map(|w| w * w).sum();
+        let fft = FftPlanner::new().plan_fft_forward(len);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        let bins = len / 2 + 1;
+
+        Ok(Self {
+            sample_rate: sample_rate as f64,
+            taper,
+            taper_power,
+            hop,
+            average: options.average.max(1),
+            fft,
+            buffer: vec![Complex::default(); len],
+            scratch,
+            history: vec![vec![0.0; len]; channels],
+            position: 0,
+            seen: 0,
+            since_segment: 0,
+            periodograms: vec![VecDeque::new(); channels],
+            sums: vec![vec![0.0; bins]; channels],
+            psd: vec![vec![0.0; bins]; channels],
+        })
+    }
+
+    /// Hz between the frequency bins
+    pub fn resolution(&self) -> f64 {
+        self.sample_rate / self.taper.len() as f64
+    }
+
+    /// Estimates per second
+    pub fn update_rate(&self) -> f64 {
+        self.sample_rate / self.hop as f64
+    }
+
+    /// The frequency of each bin of `psd`, from 0 to half the sample rate
+    pub fn frequencies(&self) -> Vec<f64> {
+        let resolution = self.resolution();
+        (0..self.taper.len() / 2 + 1)
+            .map(|bin| bin as f64 * resolution)
+            .collect()
+    }
+
+    /// Takes one value per channel.  Returns true when a new estimate is ready.
+    pub fn push(&mut self, frame: &[f64]) -> bool {
+        let len = self.taper.len();
+        for (history, value) in self.history.iter_mut().zip(frame) {
+            history[self.position] = *value;
+        }
+        self.position = (self.position + 1) % len;
+        self.seen = (self.seen + 1).min(len);
+        self.since_segment += 1;
+        if self.seen < len || self.since_segment < self.hop {
+            return false;
+        }
+        self.since_segment = 0;
+
+        for channel in 0..self.history.len() {
+            let periodogram = self.periodogram(channel);
+            let sums = &mut self.sums[channel];
+            for (sum, power) in sums.iter_mut().zip(&periodogram) {
+                *sum += power;
+            }
+            let periodograms = &mut self.periodograms[channel];
+            periodograms.push_back(periodogram);
+            if periodograms.len() > self.average {
+                if let Some(oldest) = periodograms.pop_front() {
+                    for (sum, power) in sums.iter_mut().zip(&oldest) {
+                        *sum -= power;
+                    }
+                }
+            }
+            let count = periodograms.len() as f64;
+            for (psd, sum) in self.psd[channel].iter_mut().zip(sums.iter()) {
+                // the running sum can drift just below zero
+                *psd = (sum / count).max(0.0);
+            }
+        }
+        true
+    }
+
+    /// The one-sided power spectral density of the latest segment, in squared input units per Hz
+    fn periodogram(&mut self, channel: usize) -> Vec<f64> {
+        let len = self.taper.len();
+        let history = &self.history[channel];
+        let mean = history.iter().sum::<f64>() / len as f64;
+        // oldest sample first, so the taper lines up with time
+        let ordered = history[self.position..]
+            .iter()
+            .chain(&history[..self.position]);
+        for ((out, value), w) in self.buffer.iter_mut().zip(ordered).zip(&self.taper) {
+            *out = Complex::new((value - mean) * w, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let scale = 1.0 / (self.sample_rate * self.taper_power);
+        (0..len / 2 + 1)
+            .map(|bin| {
+                let power = self.buffer[bin].norm_sqr() * scale;
+                // the negative frequencies fold onto the positive ones, except at DC and at
+                // Nyquist, which have no mirror image
+                if bin == 0 || 2 * bin == len {
+                    power
+                } else {
+                    2.0 * power
+                }
+            })
+            .collect()
+    }
+
+    /// The latest estimate for a channel, once one is ready
+    pub fn psd(&self, channel: usize) -> Option<&[f64]> {
+        if self.periodograms[channel].is_empty() {
+            None
+        } else {
+            Some(&self.psd[channel])
+        }
+    }
+
+    /// Power in a band, summed over the bins from its low to its high edge, in squared input
+    /// units.  0 before the first estimate.
+    pub fn band_power(&self, channel: usize, band: &Band) -> f64 {
+        let resolution = self.resolution();
+        let first_bin = (band.low / resolution).ceil() as usize;
+        let last_bin = (band.high / resolution).floor() as usize;
+        match self.psd(channel) {
+            Some(psd) if first_bin < psd.len() => {
+                let last_bin = last_bin.min(psd.len() - 1);
+                psd[first_bin..=last_bin].iter().sum::<f64>() * resolution
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Appends the power in each band for each channel in turn, i.e. channel 1's bands first
+    pub fn band_powers(&self, bands: &[Band], out: &mut Vec<f64>) {
+        for channel in 0..self.history.len() {
+            out.extend(bands.iter().map(|band| self.band_power(channel, band)));
+        }
+    }
+
+    /// Checks that every band lies below half the sample rate
+    pub fn check_bands(&self, bands: &[Band]) -> DspResult<()> {
+        for band in bands {
+            if band.high > self.sample_rate / 2.0 {
+                return Err(DspError::BadSpectrum(format!(
+                    "Band {} reaches above {} Hz, half the sample rate",
+                    band,
+                    self.sample_rate / 2.0
+                )));
+            }
+            if band.high - band.low < self.resolution() {
+                return Err(DspError::BadSpectrum(format!(
+                    "Band {} is narrower than the {} Hz resolution; use longer segments",
+                    band,
+                    self.resolution()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
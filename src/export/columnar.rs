@@ -0,0 +1,229 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Apache Arrow IPC and Parquet writers, for recordings too large for row-oriented formats.
+//!
+//! Samples are buffered into record batches with a column per `Sample` field and per channel.
+//! The board configuration, units and start time are stored in the schema metadata, and each
+//! channel's gain and scaling factor in its field metadata.
+
+use arrow_array::{
+    ArrayRef, Float32Array, Float64Array, Int32Array, RecordBatch, UInt32Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use parquet::format::KeyValue;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{ExportOptions, ExportResult, SampleWriter, Units};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+
+// about 4 seconds at 16 kSPS
+const BATCH_ROWS: usize = 65536;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColumnarFormat {
+    /// the Arrow IPC file format, also known as Feather v2
+    Ipc,
+    Parquet,
+}
+
+enum Inner {
+    Ipc(arrow_ipc::writer::FileWriter<BufWriter<File>>),
+    Parquet(parquet::arrow::ArrowWriter<File>),
+}
+
+/// Column buffers for the batch being built
+#[derive(Default)]
+struct Columns {
+    host_time: Vec<f64>,
+    timestamp: Vec<u32>,
+    sample_number: Vec<u32>,
+    counts: Vec<Vec<i32>>,
+    microvolts: Vec<Vec<f32>>,
+    ads_gpio: Vec<u8>,
+    loff_statp: Vec<u8>,
+    loff_statn: Vec<u8>,
+}
+
+pub struct ColumnarWriter {
+    inner: Inner,
+    schema: SchemaRef,
+    units: Units,
+    config: BoardConfig,
+    columns: Columns,
+}
+
+impl ColumnarWriter {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: ColumnarFormat,
+        config: &BoardConfig,
+        options: &ExportOptions,
+    ) -> ExportResult<Self> {
+        let schema = Arc::new(Self::schema(config, options.units)?);
+        let file = File::create(path)?;
+
+        let inner = match format {
+            ColumnarFormat::Ipc => Inner::Ipc(arrow_ipc::writer::FileWriter::try_new(
+                BufWriter::new(file),
+                &schema,
+            )?),
+            ColumnarFormat::Parquet => {
+                // also store the metadata as plain key/values, where tools that don't decode the
+                // embedded Arrow schema can find it
+                let key_values = schema
+                    .metadata()
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+                    .collect();
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .set_key_value_metadata(Some(key_values))
+                    .build();
+                Inner::Parquet(parquet::arrow::ArrowWriter::try_new(
+                    file,
+                    schema.clone(),
+                    Some(properties),
+                )?)
+            }
+        };
+
+        let mut columns = Columns::default();
+        match options.units {
+            Units::Counts => columns.counts = vec![Vec::new(); NUM_CHANNELS],
+            Units::Microvolts => columns.microvolts = vec![Vec::new(); NUM_CHANNELS],
+        }
+
+        Ok(Self {
+            inner,
+            schema,
+            units: options.units,
+            config: config.clone(),
+            columns,
+        })
+    }
+
+    fn schema(config: &BoardConfig, units: Units) -> ExportResult<Schema> {
+        let mut fields = vec![
+            Field::new("host_time", DataType::Float64, false),
+            Field::new("timestamp", DataType::UInt32, false),
+            Field::new("sample_number", DataType::UInt32, false),
+        ];
+        for channel in &config.channels {
+            let data_type = match units {
+                Units::Counts => DataType::Int32,
+                Units::Microvolts => DataType::Float32,
+            };
+            let mut metadata = HashMap::new();
+            metadata.insert("gain".to_string(), channel.gain.to_string());
+            metadata.insert(
+                "scaling_factor".to_string(),
+                channel.microvolts_per_count().to_string(),
+            );
+            fields.push(Field::new(&channel.label, data_type, false).with_metadata(metadata));
+        }
+        fields.push(Field::new("ads_gpio", DataType::UInt8, false));
+        fields.push(Field::new("loff_statp", DataType::UInt8, false));
+        fields.push(Field::new("loff_statn", DataType::UInt8, false));
+
+        let mut metadata = HashMap::new();
+        metadata.insert("hackeeg.board".to_string(), serde_json::to_string(config)?);
+        metadata.insert("hackeeg.units".to_string(), units.to_string());
+        metadata.insert(
+            "hackeeg.created".to_string(),
+            chrono::Local::now().to_rfc3339(),
+        );
+        Ok(Schema::new_with_metadata(fields, metadata))
+    }
+
+    fn flush_batch(&mut self) -> ExportResult<()> {
+        if self.columns.host_time.is_empty() {
+            return Ok(());
+        }
+
+        let columns = &mut self.columns;
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(Float64Array::from(std::mem::take(&mut columns.host_time))),
+            Arc::new(UInt32Array::from(std::mem::take(&mut columns.timestamp))),
+            Arc::new(UInt32Array::from(std::mem::take(
+                &mut columns.sample_number,
+            ))),
+        ];
+        for channel in columns.counts.iter_mut() {
+            arrays.push(Arc::new(Int32Array::from(std::mem::take(channel))));
+        }
+        for channel in columns.microvolts.iter_mut() {
+            arrays.push(Arc::new(Float32Array::from(std::mem::take(channel))));
+        }
+        arrays.push(Arc::new(UInt8Array::from(std::mem::take(
+            &mut columns.ads_gpio,
+        ))));
+        arrays.push(Arc::new(UInt8Array::from(std::mem::take(
+            &mut columns.loff_statp,
+        ))));
+        arrays.push(Arc::new(UInt8Array::from(std::mem::take(
+            &mut columns.loff_statn,
+        ))));
+
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        match self.inner {
+            Inner::Ipc(ref mut writer) => writer.write(&batch)?,
+            Inner::Parquet(ref mut writer) => writer.write(&batch)?,
+        }
+        Ok(())
+    }
+}
+
+impl SampleWriter for ColumnarWriter {
+    fn write_sample(&mut self, sample: &Sample, host_time: f64) -> ExportResult<()> {
+        let columns = &mut self.columns;
+        columns.host_time.push(host_time);
+        columns.timestamp.push(sample.timestamp);
+        columns.sample_number.push(sample.sample_number);
+        for (chan_idx, channel) in sample.channels.iter().enumerate() {
+            match self.units {
+                Units::Counts => columns.counts[chan_idx].push(channel.sample),
+                Units::Microvolts => columns.microvolts[chan_idx]
+                    .push(self.config.channels[chan_idx].to_microvolts(channel.sample) as f32),
+            }
+        }
+        columns.ads_gpio.push(sample.ads_gpio);
+        columns.loff_statp.push(sample.loff_statp);
+        columns.loff_statn.push(sample.loff_statn);
+
+        if columns.host_time.len() >= BATCH_ROWS {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> ExportResult<()> {
+        self.flush_batch()?;
+        match self.inner {
+            Inner::Ipc(mut writer) => writer.finish()?,
+            Inner::Parquet(writer) => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CSV and TSV writer, one row per sample
+
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::{ExportError, ExportOptions, ExportResult, SampleWriter, Units};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+
+// a crash loses at most this much data
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Column {
+    SampleNumber,
+    /// board timestamp, in microseconds
+    Timestamp,
+    /// `clock::local_clock()` when the sample was received, in seconds
+    HostTime,
+    /// every channel, in order
+    Channels,
+    /// a single channel, numbered from 0
+    Channel(usize),
+    AdsGpio,
+    LoffStatp,
+    LoffStatn,
+}
+
+impl Column {
+    pub fn defaults() -> Vec<Column> {
+        vec![
+            Column::SampleNumber,
+            Column::Timestamp,
+            Column::HostTime,
+            Column::Channels,
+        ]
+    }
+
+    /// Parses a comma-separated list of column names
+    pub fn parse_list(list: &str) -> ExportResult<Vec<Column>> {
+        list.split(',').map(|name| name.trim().parse()).collect()
+    }
+}
+
+impl FromStr for Column {
+    type Err = ExportError;
+
+    /// Accepts `sample_number`, `timestamp`, `host_time`, `channels`, `ch1` to `ch8`, `ads_gpio`,
+    /// `loff_statp` and `loff_statn`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let column = match s.to_lowercase().as_str() {
+            "sample_number" => Column::SampleNumber,
+            "timestamp" => Column::Timestamp,
+            "host_time" => Column::HostTime,
+            "channels" => Column::Channels,
+            "ads_gpio" => Column::AdsGpio,
+            "loff_statp" => Column::LoffStatp,
+            "loff_statn" => Column::LoffStatn,
+            name => match name
+                .strip_prefix("ch")
+                .and_then(|num| num.parse::<usize>().ok())
+            {
+                Some(chan_num) if (1..=NUM_CHANNELS).contains(&chan_num) => {
+                    Column::Channel(chan_num - 1)
+                }
+                _ => return Err(ExportError::BadOption(format!("Unknown column '{}'", s))),
+            },
+        };
+        Ok(column)
+    }
+}
+
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    delimiter: char,
+    units: Units,
+    config: BoardConfig,
+    // the Channels column expanded, so every entry is a single value
+    columns: Vec<Column>,
+    row: String,
+    last_flush: Instant,
+}
+
+impl CsvWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        delimiter: char,
+        config: &BoardConfig,
+        options: &ExportOptions,
+    ) -> ExportResult<Self> {
+        Self::new(
+            BufWriter::new(File::create(path)?),
+            delimiter,
+            config,
+            options,
+        )
+    }
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(
+        writer: W,
+        delimiter: char,
+        config: &BoardConfig,
+        options: &ExportOptions,
+    ) -> ExportResult<Self> {
+        let mut columns = Vec::new();
+        for column in &options.columns {
+            match column {
+                Column::Channels => columns.extend((0..NUM_CHANNELS).map(Column::Channel)),
+                column => columns.push(*column),
+            }
+        }
+
+        let mut csv = Self {
+            writer,
+            delimiter,
+            units: options.units,
+            config: config.clone(),
+            columns,
+            row: String::new(),
+            last_flush: Instant::now(),
+        };
+        csv.write_header()?;
+        Ok(csv)
+    }
+
+    /// Quotes a header field if it contains the delimiter or a quote
+    fn quote(&self, field: &str) -> String {
+        if field.contains(self.delimiter) || field.contains('"') {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    fn write_header(&mut self) -> ExportResult<()> {
+        let names: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                Column::SampleNumber => "sample_number".to_string(),
+                Column::Timestamp => "timestamp".to_string(),
+                Column::HostTime => "host_time".to_string(),
+                Column::Channel(chan_idx) => {
+                    let label = &self.config.channels[*chan_idx].label;
+                    match self.units {
+                        Units::Counts => self.quote(label),
+                        Units::Microvolts => self.quote(&format!("{} (uV)", label)),
+                    }
+                }
+                Column::AdsGpio => "ads_gpio".to_string(),
+                Column::LoffStatp => "loff_statp".to_string(),
+                Column::LoffStatn => "loff_statn".to_string(),
+                Column::Channels => unreachable!("channels are expanded in new()"),
+            })
+            .collect();
+        writeln!(self.writer, "{}", names.join(&self.delimiter.to_string()))?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> SampleWriter for CsvWriter<W> {
+    fn write_sample(&mut self, sample: &Sample, host_time: f64) -> ExportResult<()> {
+        self.row.clear();
+        for (idx, column) in self.columns.iter().enumerate() {
+            if idx > 0 {
+                self.row.push(self.delimiter);
+            }
+            // writing to a String can't fail
+            let _ = match column {
+                Column::SampleNumber => write!(self.row, "{}", sample.sample_number),
+                Column::Timestamp => write!(self.row, "{}", sample.timestamp),
+                Column::HostTime => write!(self.row, "{:.6}", host_time),
+                Column::Channel(chan_idx) => {
+                    let counts = sample.channels[*chan_idx].sample;
+                    match self.units {
+                        Units::Counts => write!(self.row, "{}", counts),
+                        Units::Microvolts => write!(
+                            self.row,
+                            "{:.3}",
+                            self.config.channels[*chan_idx].to_microvolts(counts)
+                        ),
+                    }
+                }
+                Column::AdsGpio => write!(self.row, "{}", sample.ads_gpio),
+                Column::LoffStatp => write!(self.row, "{}", sample.loff_statp),
+                Column::LoffStatn => write!(self.row, "{}", sample.loff_statn),
+                Column::Channels => unreachable!("channels are expanded in new()"),
+            };
+        }
+        self.row.push('\n');
+        self.writer.write_all(self.row.as_bytes())?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> ExportResult<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,576 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! EDF+ and BDF+ writer.
+//!
+//! See https://www.edfplus.info/specs/edfplus.html.  BDF stores the 24-bit samples as they come
+//! from the ADS1299; EDF only has 16 bits, so the lowest 8 bits of each sample are dropped.
+//! Markers, lead-off changes and gaps in the sample numbers are written to the annotations
+//! signal.
+
+use chrono::{DateTime, Local};
+use log::warn;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{Annotation, ExportOptions, ExportResult, LeadOffTracker, SampleWriter};
+use crate::client::sample::Sample;
+use crate::common::config::{BoardConfig, DIGITAL_MAX, DIGITAL_MIN};
+use crate::common::constants::NUM_CHANNELS;
+
+const EDF_TAG: &str = "edf";
+
+// the spec recommends data records of at most 61440 bytes
+const MAX_SAMPLES_PER_RECORD: u32 = 1000;
+// room in each data record for the time-keeping annotation plus a few events
+const ANNOTATION_BYTES: usize = 240;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EdfFormat {
+    Edf,
+    Bdf,
+}
+
+impl EdfFormat {
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            EdfFormat::Edf => 2,
+            EdfFormat::Bdf => 3,
+        }
+    }
+
+    /// How far samples are shifted right to fit the format's digital range
+    fn shift(self) -> u32 {
+        match self {
+            EdfFormat::Edf => 8,
+            EdfFormat::Bdf => 0,
+        }
+    }
+
+    fn digital_min(self) -> i32 {
+        DIGITAL_MIN >> self.shift()
+    }
+
+    fn digital_max(self) -> i32 {
+        DIGITAL_MAX >> self.shift()
+    }
+
+    fn version(self) -> &'static [u8] {
+        match self {
+            EdfFormat::Edf => b"0       ",
+            EdfFormat::Bdf => b"\xffBIOSEMI",
+        }
+    }
+
+    fn reserved(self) -> &'static str {
+        match self {
+            EdfFormat::Edf => "EDF+C",
+            EdfFormat::Bdf => "BDF+C",
+        }
+    }
+
+    fn annotations_label(self) -> &'static str {
+        match self {
+            EdfFormat::Edf => "EDF Annotations",
+            EdfFormat::Bdf => "BDF Annotations",
+        }
+    }
+}
+
+/// Appends `value` to a header, truncated or space padded to `width` ASCII characters
+fn field(header: &mut Vec<u8>, value: &str, width: usize) {
+    let mut bytes: Vec<u8> = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c as u8
+            } else {
+                b'_'
+            }
+        })
+        .take(width)
+        .collect();
+    bytes.resize(width, b' ');
+    header.extend_from_slice(&bytes);
+}
+
+/// Formats a number in at most `width` characters, dropping decimals as needed
+fn format_number(value: f64, width: usize) -> String {
+    for precision in (0..=width).rev() {
+        let mut formatted = format!("{:.*}", precision, value);
+        if formatted.contains('.') {
+            formatted = formatted
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string();
+        }
+        if formatted.len() <= width {
+            return formatted;
+        }
+    }
+    format!("{:.0}", value)
+}
+
+/// Formats seconds to the microsecond, without trailing zeros
+fn format_seconds(seconds: f64) -> String {
+    let formatted = format!("{:.6}", seconds);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Formats an onset for a time-stamped annotation list, which always carries a sign
+fn format_onset(onset: f64) -> String {
+    let formatted = format_seconds(onset);
+    if onset < 0.0 {
+        formatted
+    } else {
+        format!("+{}", formatted)
+    }
+}
+
+/// Encodes an annotation as a TAL: +onset[\x15duration]\x14text\x14\x00
+fn annotation_tal(annotation: &Annotation, max_len: usize) -> Vec<u8> {
+    let mut tal = format_onset(annotation.onset).into_bytes();
+    if let Some(duration) = annotation.duration {
+        tal.push(0x15);
+        tal.extend_from_slice(format_seconds(duration).as_bytes());
+    }
+    tal.push(0x14);
+    // the delimiters can't appear in the text, and a single annotation must fit in a record
+    let room = max_len.saturating_sub(tal.len() + 2);
+    tal.extend(
+        annotation
+            .text
+            .bytes()
+            .map(|b| if b < 0x20 { b' ' } else { b })
+            .take(room),
+    );
+    tal.push(0x14);
+    tal.push(0x00);
+    tal
+}
+
+pub struct EdfWriter<W: Write + Seek> {
+    writer: W,
+    format: EdfFormat,
+    config: BoardConfig,
+    prefilter: String,
+    start: DateTime<Local>,
+    samples_per_record: usize,
+    record_duration: f64,
+    record: Vec<[i32; NUM_CHANNELS]>,
+    records: u64,
+    samples: u64,
+    last_sample_number: Option<u32>,
+    pending: VecDeque<Annotation>,
+    lead_off: LeadOffTracker,
+}
+
+impl EdfWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: EdfFormat,
+        config: &BoardConfig,
+        options: &ExportOptions,
+    ) -> ExportResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?), format, config, options)
+    }
+}
+
+impl<W: Write + Seek> EdfWriter<W> {
+    pub fn new(
+        writer: W,
+        format: EdfFormat,
+        config: &BoardConfig,
+        options: &ExportOptions,
+    ) -> ExportResult<Self> {
+        // split each second into records of equal length, as long as the sample rate divides evenly
+        let mut samples_per_record = config.sample_rate.max(1);
+        while samples_per_record > MAX_SAMPLES_PER_RECORD && samples_per_record & 1 == 0 {
+            samples_per_record /= 2;
+        }
+
+        let mut edf = Self {
+            writer,
+            format,
+            config: config.clone(),
+            prefilter: options.prefilter.clone(),
+            start: Local::now(),
+            samples_per_record: samples_per_record as usize,
+            record_duration: samples_per_record as f64 / config.sample_rate.max(1) as f64,
+            record: Vec::with_capacity(samples_per_record as usize),
+            records: 0,
+            samples: 0,
+            last_sample_number: None,
+            pending: VecDeque::new(),
+            lead_off: LeadOffTracker::default(),
+        };
+        // the number of records stays -1 (unknown) until the file is closed
+        edf.write_header(-1)?;
+        Ok(edf)
+    }
+
+    fn annotation_samples(&self) -> usize {
+        ANNOTATION_BYTES / self.format.bytes_per_sample()
+    }
+
+    fn write_header(&mut self, num_records: i64) -> ExportResult<()> {
+        let num_signals = NUM_CHANNELS + 1;
+        let mut header = Vec::with_capacity(256 * (num_signals + 1));
+
+        header.extend_from_slice(self.format.version());
+        // local patient and recording identification, with EDF+ subfields left unknown
+        field(&mut header, "X X X X", 80);
+        let startdate = self.start.format("%d-%b-%Y").to_string().to_uppercase();
+        // a software montage follows the equipment, as an extra subfield
+        let montage = match self.config.montage {
+            Some(ref montage) => format!(" Montage:{}", montage.name.replace(' ', "_")),
+            None => String::new(),
+        };
+        field(
+            &mut header,
+            &format!("Startdate {} X X HackEEG{}", startdate, montage),
+            80,
+        );
+        field(&mut header, &self.start.format("%d.%m.%y").to_string(), 8);
+        field(&mut header, &self.start.format("%H.%M.%S").to_string(), 8);
+        field(&mut header, &(256 * (num_signals + 1)).to_string(), 8);
+        field(&mut header, self.format.reserved(), 44);
+        field(&mut header, &num_records.to_string(), 8);
+        field(&mut header, &format_number(self.record_duration, 8), 8);
+        field(&mut header, &num_signals.to_string(), 4);
+
+        // each signal field is written for all signals before moving on to the next field
+        let channels = &self.config.channels;
+        let annotations = self.format.annotations_label();
+        let shift = self.format.shift();
+        for channel in channels {
+            field(&mut header, &channel.label, 16);
+        }
+        field(&mut header, annotations, 16);
+        for _ in channels {
+            field(&mut header, "", 80);
+        }
+        field(&mut header, "", 80);
+        for _ in channels {
+            field(&mut header, "uV", 8);
+        }
+        field(&mut header, "", 8);
+        for channel in channels {
+            let physical_min = channel.to_microvolts(self.format.digital_min() << shift);
+            field(&mut header, &format_number(physical_min, 8), 8);
+        }
+        field(&mut header, "-1", 8);
+        for channel in channels {
+            let physical_max = channel.to_microvolts(self.format.digital_max() << shift);
+            field(&mut header, &format_number(physical_max, 8), 8);
+        }
+        field(&mut header, "1", 8);
+        for _ in 0..num_signals {
+            field(&mut header, &self.format.digital_min().to_string(), 8);
+        }
+        for _ in 0..num_signals {
+            field(&mut header, &self.format.digital_max().to_string(), 8);
+        }
+        for _ in channels {
+            field(&mut header, &self.prefilter, 80);
+        }
+        field(&mut header, "", 80);
+        for _ in channels {
+            field(&mut header, &self.samples_per_record.to_string(), 8);
+        }
+        field(&mut header, &self.annotation_samples().to_string(), 8);
+        for _ in 0..num_signals {
+            field(&mut header, "", 32);
+        }
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Seconds from the first sample to the next one to be written
+    fn elapsed(&self) -> f64 {
+        self.samples as f64 / self.config.sample_rate.max(1) as f64
+    }
+
+    fn write_record(&mut self) -> ExportResult<()> {
+        let bytes_per_sample = self.format.bytes_per_sample();
+        let shift = self.format.shift();
+        let digital_min = self.format.digital_min();
+        let digital_max = self.format.digital_max();
+        let mut data = Vec::with_capacity(
+            (NUM_CHANNELS * self.samples_per_record + self.annotation_samples()) * bytes_per_sample,
+        );
+
+        for chan_idx in 0..NUM_CHANNELS {
+            for values in &self.record {
+                // samples from transforms can fall outside the ADC's range, and saturating
+                // them keeps the file within the digital range its header declares
+                let value = (values[chan_idx] >> shift)
+                    .max(digital_min)
+                    .min(digital_max)
+                    .to_le_bytes();
+                data.extend_from_slice(&value[..bytes_per_sample]);
+            }
+        }
+
+        // every record starts with a time-keeping annotation giving its onset
+        let record_onset = self.records as f64 * self.record_duration;
+        let mut tal = format_onset(record_onset).into_bytes();
+        tal.extend_from_slice(&[0x14, 0x14, 0x00]);
+        while let Some(annotation) = self.pending.front() {
+            let next = annotation_tal(annotation, ANNOTATION_BYTES - tal.len());
+            if tal.len() + next.len() > ANNOTATION_BYTES {
+                break;
+            }
+            tal.extend_from_slice(&next);
+            self.pending.pop_front();
+        }
+        tal.resize(self.annotation_samples() * bytes_per_sample, 0);
+        data.extend_from_slice(&tal);
+
+        self.writer.write_all(&data)?;
+        self.record.clear();
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Pads out the last record with the final sample, rewrites the header with the number of
+    /// records and returns the underlying writer
+    pub fn close(mut self) -> ExportResult<W> {
+        if let Some(&last) = self.record.last() {
+            self.record.resize(self.samples_per_record, last);
+            self.write_record()?;
+        }
+        if !self.pending.is_empty() {
+            warn!(
+                target: EDF_TAG,
+                "{} annotations didn't fit in the last data record and were dropped",
+                self.pending.len()
+            );
+        }
+
+        let records = self.records as i64;
+        self.write_header(records)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek + Send> SampleWriter for EdfWriter<W> {
+    fn write_sample(&mut self, sample: &Sample, _host_time: f64) -> ExportResult<()> {
+        let onset = self.elapsed();
+        if self.samples == 0 {
+            self.start = Local::now();
+        }
+
+        if let Some(last_sample_number) = self.last_sample_number {
+            let missing = sample
+                .sample_number
+                .wrapping_sub(last_sample_number)
+                .wrapping_sub(1);
+            if missing > 0 && missing < u32::MAX / 2 {
+                self.annotate(Annotation {
+                    onset,
+                    duration: None,
+                    text: format!("Gap of {} samples", missing),
+                })?;
+            }
+        }
+        self.last_sample_number = Some(sample.sample_number);
+
+        for text in self.lead_off.update(sample, &self.config) {
+            self.annotate(Annotation {
+                onset,
+                duration: None,
+                text,
+            })?;
+        }
+
+        let mut values = [0; NUM_CHANNELS];
+        for (value, channel) in values.iter_mut().zip(sample.channels.iter()) {
+            *value = channel.sample;
+        }
+        self.record.push(values);
+        self.samples += 1;
+
+        if self.record.len() == self.samples_per_record {
+            self.write_record()?;
+        }
+        Ok(())
+    }
+
+    fn annotate(&mut self, annotation: Annotation) -> ExportResult<()> {
+        self.pending.push_back(annotation);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> ExportResult<()> {
+        self.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::constants::ads1299;
+    use std::io::Cursor;
+
+    const SAMPLES: usize = 300;
+
+    /// The value written to every channel of sample `n`, which runs past both ends of the
+    /// 24-bit range
+    fn value(n: usize, chan_idx: usize) -> i32 {
+        (n as i32 - 150) * 70_000 + chan_idx as i32
+    }
+
+    fn write(format: EdfFormat, config: &BoardConfig) -> Vec<u8> {
+        let options = ExportOptions {
+            prefilter: "HP:1Hz".to_string(),
+            ..ExportOptions::default()
+        };
+        let mut edf = EdfWriter::new(Cursor::new(Vec::new()), format, config, &options).unwrap();
+        for n in 0..SAMPLES {
+            let mut sample = Sample::from_bytes(&[0; 11 + 3 * NUM_CHANNELS]);
+            sample.sample_number = n as u32;
+            for (chan_idx, channel) in sample.channels.iter_mut().enumerate() {
+                channel.sample = value(n, chan_idx);
+            }
+            if n == 100 {
+                edf.annotate(Annotation {
+                    onset: 0.4,
+                    duration: None,
+                    text: "Marker".to_string(),
+                })
+                .unwrap();
+            }
+            edf.write_sample(&sample, 0.0).unwrap();
+        }
+        edf.close().unwrap().into_inner()
+    }
+
+    fn text(bytes: &[u8]) -> &str {
+        std::str::from_utf8(bytes).unwrap().trim_end()
+    }
+
+    /// A per-signal header field, where `offset` is the total width of the fields before it
+    fn signal_field(header: &[u8], offset: usize, width: usize, signal: usize) -> &str {
+        let start = 256 + offset * (NUM_CHANNELS + 1) + signal * width;
+        text(&header[start..start + width])
+    }
+
+    fn round_trip(format: EdfFormat) {
+        assert!(value(0, 0) < DIGITAL_MIN && value(SAMPLES - 1, 0) > DIGITAL_MAX);
+        let config = BoardConfig::new(250, ads1299::Gain::X24);
+        let file = write(format, &config);
+        let num_signals = NUM_CHANNELS + 1;
+        let header_bytes = 256 * (num_signals + 1);
+        let bytes_per_sample = format.bytes_per_sample();
+
+        assert_eq!(&file[..8], format.version());
+        assert_eq!(text(&file[184..192]), header_bytes.to_string());
+        assert_eq!(text(&file[192..236]), format.reserved());
+        // the last record is padded out to a whole second
+        assert_eq!(text(&file[236..244]), "2");
+        assert_eq!(text(&file[244..252]), "1");
+        assert_eq!(text(&file[252..256]), num_signals.to_string());
+
+        // label, transducer and physical dimension come first, with widths 16, 80 and 8
+        let label = 0;
+        let (physical_min, physical_max, digital_min, digital_max) = (104, 112, 120, 128);
+        let (prefilter, samples) = (136, 216);
+        let number =
+            |offset, chan_idx| -> f64 { signal_field(&file, offset, 8, chan_idx).parse().unwrap() };
+        for (chan_idx, channel) in config.channels.iter().enumerate() {
+            assert_eq!(signal_field(&file, label, 16, chan_idx), channel.label);
+            // EDF's physical range covers the counts its 16 bits can reach
+            let shift = format.shift();
+            let expected_min = channel.to_microvolts(format.digital_min() << shift);
+            let expected_max = channel.to_microvolts(format.digital_max() << shift);
+            assert!((number(physical_min, chan_idx) - expected_min).abs() < 1.0);
+            assert!((number(physical_max, chan_idx) - expected_max).abs() < 1.0);
+            assert_eq!(
+                signal_field(&file, digital_min, 8, chan_idx),
+                format.digital_min().to_string()
+            );
+            assert_eq!(
+                signal_field(&file, digital_max, 8, chan_idx),
+                format.digital_max().to_string()
+            );
+            assert_eq!(signal_field(&file, prefilter, 80, chan_idx), "HP:1Hz");
+            assert_eq!(signal_field(&file, samples, 8, chan_idx), "250");
+        }
+        assert_eq!(
+            signal_field(&file, label, 16, NUM_CHANNELS),
+            format.annotations_label()
+        );
+
+        let annotation_bytes = ANNOTATION_BYTES / bytes_per_sample * bytes_per_sample;
+        let record_bytes = NUM_CHANNELS * 250 * bytes_per_sample + annotation_bytes;
+        assert_eq!(file.len(), header_bytes + 2 * record_bytes);
+
+        for record in 0..2 {
+            let data = &file[header_bytes + record * record_bytes..];
+            for chan_idx in 0..NUM_CHANNELS {
+                for i in 0..250 {
+                    let start = (chan_idx * 250 + i) * bytes_per_sample;
+                    let mut bytes = [0; 4];
+                    bytes[..bytes_per_sample]
+                        .copy_from_slice(&data[start..start + bytes_per_sample]);
+                    // sign extend from the format's width
+                    let padding = 32 - 8 * bytes_per_sample as u32;
+                    let read = i32::from_le_bytes(bytes) << padding >> padding;
+
+                    let n = (record * 250 + i).min(SAMPLES - 1);
+                    let expected = (value(n, chan_idx) >> format.shift())
+                        .max(format.digital_min())
+                        .min(format.digital_max());
+                    assert_eq!(
+                        read, expected,
+                        "record {} channel {} sample {}",
+                        record, chan_idx, i
+                    );
+                }
+            }
+
+            let annotations = &data[NUM_CHANNELS * 250 * bytes_per_sample..record_bytes];
+            let onset = format!("+{}\x14\x14\x00", record);
+            assert!(annotations.starts_with(onset.as_bytes()));
+            if record == 0 {
+                let marker = b"+0.4\x14Marker\x14\x00";
+                assert_eq!(
+                    &annotations[onset.len()..onset.len() + marker.len()],
+                    marker
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edf_round_trip() {
+        round_trip(EdfFormat::Edf);
+    }
+
+    #[test]
+    fn bdf_round_trip() {
+        round_trip(EdfFormat::Bdf);
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(Debug)]
+pub enum ExportError {
+    IOError(std::io::Error),
+    UnsupportedFormat(String),
+    BadOption(String),
+    SerializeError(serde_json::Error),
+    ArchiveError(zip::result::ZipError),
+    #[cfg(feature = "arrow")]
+    ArrowError(arrow_schema::ArrowError),
+    #[cfg(feature = "arrow")]
+    ParquetError(parquet::errors::ParquetError),
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::IOError(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::SerializeError(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ExportError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ExportError::ArchiveError(e)
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl From<arrow_schema::ArrowError> for ExportError {
+    fn from(e: arrow_schema::ArrowError) -> Self {
+        ExportError::ArrowError(e)
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl From<parquet::errors::ParquetError> for ExportError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        ExportError::ParquetError(e)
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ExportError::IOError(e) => write!(f, "I/O error: {}", e),
+            ExportError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported output format '{}'", format)
+            }
+            ExportError::BadOption(message) => write!(f, "{}", message),
+            ExportError::SerializeError(e) => write!(f, "Couldn't serialize metadata: {}", e),
+            ExportError::ArchiveError(e) => write!(f, "Couldn't write archive: {}", e),
+            #[cfg(feature = "arrow")]
+            ExportError::ArrowError(e) => write!(f, "Arrow error: {}", e),
+            #[cfg(feature = "arrow")]
+            ExportError::ParquetError(e) => write!(f, "Parquet error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writers that save decoded samples in file formats other tools can open.
+//!
+//! Unlike `record`, which keeps the raw frames for replay, these convert samples into physical
+//! units using the `BoardConfig` the session was acquired with.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "arrow")]
+mod columnar;
+mod csv;
+mod edf;
+mod err;
+mod npy;
+mod openbci;
+mod wav;
+mod xdf;
+
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+#[cfg(feature = "arrow")]
+pub use columnar::{ColumnarFormat, ColumnarWriter};
+pub use csv::{Column, CsvWriter};
+pub use edf::{EdfFormat, EdfWriter};
+pub use err::ExportError;
+pub use npy::{NpyWriter, NumpyWriter};
+pub use openbci::OpenBciTextWriter;
+pub use wav::{WavEncoding, WavWriter};
+pub use xdf::XdfWriter;
+
+pub type ExportResult<T> = Result<T, ExportError>;
+
+/// How channel values are written by formats that offer a choice
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// raw ADC counts, as in `Sample`
+    Counts,
+    Microvolts,
+}
+
+impl FromStr for Units {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "counts" => Ok(Units::Counts),
+            "uv" | "microvolts" => Ok(Units::Microvolts),
+            _ => Err(ExportError::BadOption(format!("Unknown units '{}'", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for Units {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Units::Counts => write!(f, "counts"),
+            Units::Microvolts => write!(f, "microvolts"),
+        }
+    }
+}
+
+/// Settings for the formats that have them; each writer ignores the ones that don't apply
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub units: Units,
+    /// columns written by CSV and TSV
+    pub columns: Vec<Column>,
+    /// the stream name in XDF files, which should match the LSL outlet's
+    pub stream_name: String,
+    pub wav_encoding: WavEncoding,
+    /// multiplies WAV samples, where 1 maps the ADC's full scale to the audio full scale
+    pub wav_scale: f64,
+    /// filters already applied to the samples, in the style of EDF's prefiltering field
+    pub prefilter: String,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            units: Units::Counts,
+            columns: Column::defaults(),
+            stream_name: "HackEEG".to_string(),
+            wav_encoding: WavEncoding::Pcm32,
+            wav_scale: 1.0,
+            prefilter: String::new(),
+        }
+    }
+}
+
+/// An event to be stored alongside the samples, such as a marker or lead-off change
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    /// Seconds since the first sample of the file
+    pub onset: f64,
+    pub duration: Option<f64>,
+    pub text: String,
+}
+
+/// Writers are `Send` so they can run on their own thread in a `Pipeline`
+pub trait SampleWriter: Send {
+    /// Appends a sample.  `host_time` is when its frame was received, from `clock::local_clock()`
+    fn write_sample(&mut self, sample: &Sample, host_time: f64) -> ExportResult<()>;
+
+    /// Stores an event.  Formats that have nowhere to put events ignore it.
+    fn annotate(&mut self, annotation: Annotation) -> ExportResult<()> {
+        let _ = annotation;
+        Ok(())
+    }
+
+    /// Completes the file, e.g. filling in header fields that depend on the number of samples
+    fn finish(self: Box<Self>) -> ExportResult<()>;
+}
+
+/// Opens a writer for `path`, choosing the format from its extension
+pub fn open_writer<P: AsRef<Path>>(
+    path: P,
+    config: &BoardConfig,
+    options: &ExportOptions,
+) -> ExportResult<Box<dyn SampleWriter>> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match extension.as_str() {
+        "edf" => Ok(Box::new(EdfWriter::create(
+            path,
+            EdfFormat::Edf,
+            config,
+            options,
+        )?)),
+        "bdf" => Ok(Box::new(EdfWriter::create(
+            path,
+            EdfFormat::Bdf,
+            config,
+            options,
+        )?)),
+        "csv" => Ok(Box::new(CsvWriter::create(path, ',', config, options)?)),
+        "tsv" => Ok(Box::new(CsvWriter::create(path, '\t', config, options)?)),
+        "xdf" => Ok(Box::new(XdfWriter::create(
+            path,
+            config,
+            &options.stream_name,
+        )?)),
+        "txt" => Ok(Box::new(OpenBciTextWriter::create(path, config)?)),
+        "wav" | "rf64" => Ok(Box::new(WavWriter::create(
+            path,
+            config,
+            options.wav_encoding,
+            options.wav_scale,
+            extension == "rf64",
+        )?)),
+        "npy" | "npz" => Ok(Box::new(NumpyWriter::create(path, config, options)?)),
+        #[cfg(feature = "arrow")]
+        "arrow" | "arrows" | "feather" => Ok(Box::new(ColumnarWriter::create(
+            path,
+            ColumnarFormat::Ipc,
+            config,
+            options,
+        )?)),
+        #[cfg(feature = "arrow")]
+        "parquet" => Ok(Box::new(ColumnarWriter::create(
+            path,
+            ColumnarFormat::Parquet,
+            config,
+            options,
+        )?)),
+        #[cfg(not(feature = "arrow"))]
+        "arrow" | "arrows" | "feather" | "parquet" => Err(ExportError::UnsupportedFormat(format!(
+            "{} (rebuild with the `arrow` feature)",
+            extension
+        ))),
+        _ => Err(ExportError::UnsupportedFormat(extension)),
+    }
+}
+
+/// Turns changes in the lead-off status bits of successive samples into annotation text
+#[derive(Default)]
+pub(crate) struct LeadOffTracker {
+    statp: u8,
+    statn: u8,
+}
+
+impl LeadOffTracker {
+    pub fn update(&mut self, sample: &Sample, config: &BoardConfig) -> Vec<String> {
+        let mut events = Vec::new();
+        for chan_idx in 0..NUM_CHANNELS {
+            let bit = 1 << chan_idx;
+            let label = &config.channels[chan_idx].label;
+            for (input, previous, current) in &[
+                ("P", self.statp, sample.loff_statp),
+                ("N", self.statn, sample.loff_statn),
+            ] {
+                if (previous ^ current) & bit != 0 {
+                    let state = if current & bit != 0 { "off" } else { "on" };
+                    events.push(format!("Lead {} {}{}", state, label, input));
+                }
+            }
+        }
+        self.statp = sample.loff_statp;
+        self.statn = sample.loff_statn;
+        events
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NumPy `.npy` and `.npz` writers.
+//!
+//! See https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html.  Arrays are
+//! streamed to disk as samples arrive, with room left in the header for the final shape, which is
+//! filled in when the file is closed.  An `.npz` is assembled from temporary `.npy` files at the
+//! end, so until then the data is in `<name>.npz.<array>.npy.tmp` files next to it.
+
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::{ExportOptions, ExportResult, SampleWriter, Units};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+// the whole header, padded so the data that follows is 64-byte aligned, with room for any shape
+const HEADER_LEN: usize = 128;
+
+/// Streams rows of a C-ordered array to a `.npy` file
+pub struct NpyWriter<W: Write + Seek> {
+    writer: W,
+    descr: &'static str,
+    row_shape: Vec<usize>,
+    rows: u64,
+}
+
+impl NpyWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        descr: &'static str,
+        row_shape: &[usize],
+    ) -> ExportResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?), descr, row_shape)
+    }
+}
+
+impl<W: Write + Seek> NpyWriter<W> {
+    /// `descr` is the numpy dtype string, e.g. `<i4`, and `row_shape` the shape of each row,
+    /// e.g. `[8]` for a row per sample of eight channels, or `[]` for a one dimensional array
+    pub fn new(writer: W, descr: &'static str, row_shape: &[usize]) -> ExportResult<Self> {
+        let mut npy = Self {
+            writer,
+            descr,
+            row_shape: row_shape.to_vec(),
+            rows: 0,
+        };
+        npy.write_header()?;
+        Ok(npy)
+    }
+
+    fn write_header(&mut self) -> ExportResult<()> {
+        let mut shape = self.rows.to_string();
+        for dim in &self.row_shape {
+            shape.push_str(&format!(", {}", dim));
+        }
+        if self.row_shape.is_empty() {
+            shape.push(',');
+        }
+        let dict = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}",
+            self.descr, shape
+        );
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&[1, 0]);
+        header.extend_from_slice(&((HEADER_LEN - 10) as u16).to_le_bytes());
+        header.extend_from_slice(dict.as_bytes());
+        header.resize(HEADER_LEN - 1, b' ');
+        header.push(b'\n');
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Appends one row, already encoded in the array's dtype
+    pub fn write_row(&mut self, row: &[u8]) -> ExportResult<()> {
+        self.writer.write_all(row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Fills in the final shape and returns the underlying writer
+    pub fn close(mut self) -> ExportResult<W> {
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Describes the arrays, so they can be scaled and labelled without the board
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    units: Units,
+    board: &'a BoardConfig,
+}
+
+/// An array of an `.npz` being written, and the temporary file holding it
+struct Member {
+    name: &'static str,
+    path: PathBuf,
+    npy: NpyWriter<BufWriter<File>>,
+}
+
+impl Member {
+    fn create(
+        npz_path: &Path,
+        name: &'static str,
+        descr: &'static str,
+        row_shape: &[usize],
+    ) -> ExportResult<Self> {
+        let mut tmp_name = npz_path.as_os_str().to_owned();
+        tmp_name.push(format!(".{}.npy.tmp", name));
+        let path = PathBuf::from(tmp_name);
+        let npy = NpyWriter::create(&path, descr, row_shape)?;
+        Ok(Self { name, path, npy })
+    }
+}
+
+enum Layout {
+    /// a single `.npy` of channel data, with the sidecar in a `.json` next to it
+    Npy(NpyWriter<BufWriter<File>>),
+    /// data, host_timestamps, board_timestamps, sample_numbers and lead_off arrays
+    Npz(Vec<Member>),
+}
+
+pub struct NumpyWriter {
+    path: PathBuf,
+    units: Units,
+    config: BoardConfig,
+    layout: Layout,
+    row: Vec<u8>,
+}
+
+impl NumpyWriter {
+    /// Writes a `.npz` bundle if `path` ends in `.npz`, and otherwise a `.npy` of channel data
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        config: &BoardConfig,
+        options: &ExportOptions,
+    ) -> ExportResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let data_descr = match options.units {
+            Units::Counts => "<i4",
+            Units::Microvolts => "<f4",
+        };
+
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let is_npz = matches!(extension, Some(ext) if ext.eq_ignore_ascii_case("npz"));
+        let layout = if is_npz {
+            Layout::Npz(vec![
+                Member::create(&path, "data", data_descr, &[NUM_CHANNELS])?,
+                Member::create(&path, "host_timestamps", "<f8", &[])?,
+                Member::create(&path, "board_timestamps", "<u4", &[])?,
+                Member::create(&path, "sample_numbers", "<u4", &[])?,
+                Member::create(&path, "lead_off", "|u1", &[2])?,
+            ])
+        } else {
+            Layout::Npy(NpyWriter::create(&path, data_descr, &[NUM_CHANNELS])?)
+        };
+
+        Ok(Self {
+            path,
+            units: options.units,
+            config: config.clone(),
+            layout,
+            row: Vec::with_capacity(NUM_CHANNELS * 4),
+        })
+    }
+
+    fn sidecar_json(&self) -> ExportResult<Vec<u8>> {
+        let sidecar = Sidecar {
+            units: self.units,
+            board: &self.config,
+        };
+        Ok(serde_json::to_vec_pretty(&sidecar)?)
+    }
+}
+
+impl SampleWriter for NumpyWriter {
+    fn write_sample(&mut self, sample: &Sample, host_time: f64) -> ExportResult<()> {
+        self.row.clear();
+        for (channel, config) in sample.channels.iter().zip(&self.config.channels) {
+            match self.units {
+                Units::Counts => self.row.extend_from_slice(&channel.sample.to_le_bytes()),
+                Units::Microvolts => {
+                    let microvolts = config.to_microvolts(channel.sample) as f32;
+                    self.row.extend_from_slice(&microvolts.to_le_bytes())
+                }
+            }
+        }
+
+        match self.layout {
+            Layout::Npy(ref mut npy) => npy.write_row(&self.row)?,
+            Layout::Npz(ref mut members) => {
+                members[0].npy.write_row(&self.row)?;
+                members[1].npy.write_row(&host_time.to_le_bytes())?;
+                members[2].npy.write_row(&sample.timestamp.to_le_bytes())?;
+                members[3]
+                    .npy
+                    .write_row(&sample.sample_number.to_le_bytes())?;
+                members[4]
+                    .npy
+                    .write_row(&[sample.loff_statp, sample.loff_statn])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> ExportResult<()> {
+        let sidecar = self.sidecar_json()?;
+        match self.layout {
+            Layout::Npy(npy) => {
+                npy.close()?;
+                fs::write(self.path.with_extension("json"), sidecar)?;
+            }
+            Layout::Npz(members) => {
+                // the arrays are already as large as the recording, so they're stored uncompressed
+                let options = zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored)
+                    .large_file(true);
+                let mut zip = zip::ZipWriter::new(BufWriter::new(File::create(&self.path)?));
+                for member in members {
+                    let Member { name, path, npy } = member;
+                    npy.close()?;
+                    zip.start_file(format!("{}.npy", name), options)?;
+                    std::io::copy(&mut BufReader::new(File::open(&path)?), &mut zip)?;
+                    fs::remove_file(&path)?;
+                }
+                zip.start_file("config.json", options)?;
+                zip.write_all(&sidecar)?;
+                zip.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
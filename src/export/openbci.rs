@@ -0,0 +1,126 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenBCI GUI raw text recordings, as the GUI writes for a Cyton, so they can be played back in
+//! the GUI or loaded by tools that read its files.
+//!
+//! Channels are written in microvolts at their actual gain.  There's no accelerometer or analog
+//! input, so those columns are zero, and the timestamps are Unix time as the GUI writes them.
+
+use chrono::TimeZone;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::{ExportResult, SampleWriter};
+use crate::client::sample::Sample;
+use crate::clock;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// the columns after the channels: 3 accelerometer, 7 other, 3 analog
+const ACCEL_COLUMNS: usize = 3;
+const OTHER_COLUMNS: usize = 7;
+const ANALOG_COLUMNS: usize = 3;
+
+pub struct OpenBciTextWriter<W: Write> {
+    writer: W,
+    config: BoardConfig,
+    // Unix time minus `clock::local_clock()`, fixed at the first sample
+    unix_offset: Option<f64>,
+    row: String,
+    last_flush: Instant,
+}
+
+impl OpenBciTextWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, config: &BoardConfig) -> ExportResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?), config)
+    }
+}
+
+impl<W: Write> OpenBciTextWriter<W> {
+    pub fn new(mut writer: W, config: &BoardConfig) -> ExportResult<Self> {
+        writeln!(writer, "%OpenBCI Raw EXG Data")?;
+        writeln!(writer, "%Number of channels = {}", NUM_CHANNELS)?;
+        writeln!(writer, "%Sample Rate = {} Hz", config.sample_rate)?;
+        writeln!(writer, "%Board = OpenBCI_GUI$BoardCytonSerial")?;
+
+        let mut names = vec!["Sample Index".to_string()];
+        names.extend((0..NUM_CHANNELS).map(|chan_idx| format!("EXG Channel {}", chan_idx)));
+        names.extend((0..ACCEL_COLUMNS).map(|idx| format!("Accel Channel {}", idx)));
+        names.extend((0..OTHER_COLUMNS).map(|_| "Other".to_string()));
+        names.extend((0..ANALOG_COLUMNS).map(|idx| format!("Analog Channel {}", idx)));
+        names.push("Timestamp".to_string());
+        // the GUI's marker column
+        names.push("Other".to_string());
+        names.push("Timestamp (Formatted)".to_string());
+        writeln!(writer, "{}", names.join(", "))?;
+
+        Ok(Self {
+            writer,
+            config: config.clone(),
+            unix_offset: None,
+            row: String::new(),
+            last_flush: Instant::now(),
+        })
+    }
+}
+
+impl<W: Write + Send> SampleWriter for OpenBciTextWriter<W> {
+    fn write_sample(&mut self, sample: &Sample, host_time: f64) -> ExportResult<()> {
+        let unix_offset = *self.unix_offset.get_or_insert_with(|| {
+            let now = chrono::Utc::now();
+            now.timestamp() as f64 + now.timestamp_subsec_nanos() as f64 * 1e-9
+                - clock::local_clock()
+        });
+        let unix_time = host_time + unix_offset;
+
+        self.row.clear();
+        // writing to a String can't fail
+        let _ = write!(self.row, "{}", sample.sample_number as u8);
+        for (channel, config) in sample.channels.iter().zip(&self.config.channels) {
+            let _ = write!(self.row, ", {:.3}", config.to_microvolts(channel.sample));
+        }
+        for _ in 0..ACCEL_COLUMNS {
+            self.row.push_str(", 0.000");
+        }
+        for _ in 0..OTHER_COLUMNS + ANALOG_COLUMNS {
+            self.row.push_str(", 0");
+        }
+        let _ = write!(self.row, ", {:.6}, 0.0", unix_time);
+
+        let secs = unix_time.floor();
+        let nanos = ((unix_time - secs) * 1e9) as u32;
+        if let Some(time) = chrono::Local.timestamp_opt(secs as i64, nanos).single() {
+            let _ = write!(self.row, ", {}", time.format("%Y-%m-%d %H:%M:%S%.3f"));
+        }
+        self.row.push('\n');
+        self.writer.write_all(self.row.as_bytes())?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> ExportResult<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WAV and RF64 writer, for inspecting and listening to signals in audio tools.
+//!
+//! Channels are written at the board sample rate as 32-bit PCM or float, in a
+//! WAVE_FORMAT_EXTENSIBLE file.  A scale of 1 maps the ADC's full scale to the audio full scale;
+//! EEG is far smaller than that, so a scale in the hundreds or thousands is usually needed to hear
+//! it, and louder samples are clipped.  A `JUNK` chunk is reserved after the header, so a `.wav`
+//! that grows past 4 GB is turned into RF64 (EBU Tech 3306) when it's closed.  `.rf64` files are
+//! always RF64.
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{ExportError, ExportResult, SampleWriter};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+// KSDATAFORMAT_SUBTYPE_PCM and KSDATAFORMAT_SUBTYPE_IEEE_FLOAT differ only in the first byte
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+// offsets into the header
+const DS64_OFFSET: u64 = 12;
+const DS64_LEN: u32 = 28;
+const DATA_SIZE_OFFSET: u64 = DS64_OFFSET + 8 + DS64_LEN as u64 + 8 + 40 + 4;
+const HEADER_LEN: u64 = DATA_SIZE_OFFSET + 4;
+
+const BYTES_PER_SAMPLE: u16 = 4;
+// the ADC's full scale, as a float
+const FULL_SCALE: f64 = (1 << 23) as f64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WavEncoding {
+    Pcm32,
+    Float32,
+}
+
+impl FromStr for WavEncoding {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pcm32" | "pcm" => Ok(WavEncoding::Pcm32),
+            "float32" | "float" => Ok(WavEncoding::Float32),
+            _ => Err(ExportError::BadOption(format!(
+                "Unknown WAV encoding '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    encoding: WavEncoding,
+    scale: f64,
+    always_rf64: bool,
+    data_len: u64,
+    frames: u64,
+    frame: Vec<u8>,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        config: &BoardConfig,
+        encoding: WavEncoding,
+        scale: f64,
+        always_rf64: bool,
+    ) -> ExportResult<Self> {
+        Self::new(
+            BufWriter::new(File::create(path)?),
+            config,
+            encoding,
+            scale,
+            always_rf64,
+        )
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(
+        mut writer: W,
+        config: &BoardConfig,
+        encoding: WavEncoding,
+        scale: f64,
+        always_rf64: bool,
+    ) -> ExportResult<Self> {
+        let channels = NUM_CHANNELS as u16;
+        let block_align = channels * BYTES_PER_SAMPLE;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_all(b"WAVE")?;
+
+        // room for the ds64 chunk, should the file need to become RF64
+        writer.write_all(b"JUNK")?;
+        writer.write_u32::<LittleEndian>(DS64_LEN)?;
+        writer.write_all(&[0; DS64_LEN as usize])?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_u32::<LittleEndian>(40)?;
+        writer.write_u16::<LittleEndian>(WAVE_FORMAT_EXTENSIBLE)?;
+        writer.write_u16::<LittleEndian>(channels)?;
+        writer.write_u32::<LittleEndian>(config.sample_rate)?;
+        writer.write_u32::<LittleEndian>(config.sample_rate * block_align as u32)?;
+        writer.write_u16::<LittleEndian>(block_align)?;
+        writer.write_u16::<LittleEndian>(BYTES_PER_SAMPLE * 8)?;
+        writer.write_u16::<LittleEndian>(22)?;
+        // valid bits per sample
+        writer.write_u16::<LittleEndian>(BYTES_PER_SAMPLE * 8)?;
+        // no speaker positions
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u16::<LittleEndian>(match encoding {
+            WavEncoding::Pcm32 => 1,
+            WavEncoding::Float32 => 3,
+        })?;
+        writer.write_all(&SUBFORMAT_GUID_TAIL)?;
+
+        writer.write_all(b"data")?;
+        writer.write_u32::<LittleEndian>(0)?;
+
+        Ok(Self {
+            writer,
+            encoding,
+            scale,
+            always_rf64,
+            data_len: 0,
+            frames: 0,
+            frame: Vec::with_capacity(block_align as usize),
+        })
+    }
+
+    /// Fills in the chunk sizes, switching to RF64 if the file is too large for RIFF, and returns
+    /// the underlying writer
+    pub fn close(mut self) -> ExportResult<W> {
+        // the data chunk is padded to an even length, which 4-byte samples always are
+        let riff_len = HEADER_LEN - 8 + self.data_len;
+
+        if self.always_rf64 || riff_len > u32::MAX as u64 {
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.writer.write_all(b"RF64")?;
+            self.writer.write_u32::<LittleEndian>(u32::MAX)?;
+
+            self.writer.seek(SeekFrom::Start(DS64_OFFSET))?;
+            self.writer.write_all(b"ds64")?;
+            self.writer.write_u32::<LittleEndian>(DS64_LEN)?;
+            self.writer.write_u64::<LittleEndian>(riff_len)?;
+            self.writer.write_u64::<LittleEndian>(self.data_len)?;
+            self.writer.write_u64::<LittleEndian>(self.frames)?;
+            // no table of other chunk sizes
+            self.writer.write_u32::<LittleEndian>(0)?;
+
+            self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+            self.writer.write_u32::<LittleEndian>(u32::MAX)?;
+        } else {
+            self.writer.seek(SeekFrom::Start(4))?;
+            self.writer.write_u32::<LittleEndian>(riff_len as u32)?;
+            self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+            self.writer
+                .write_u32::<LittleEndian>(self.data_len as u32)?;
+        }
+
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek + Send> SampleWriter for WavWriter<W> {
+    fn write_sample(&mut self, sample: &Sample, _host_time: f64) -> ExportResult<()> {
+        self.frame.clear();
+        for channel in sample.channels.iter() {
+            // louder samples clip, as they would in an audio editor
+            let value = (channel.sample as f64 / FULL_SCALE * self.scale).clamp(-1.0, 1.0);
+            match self.encoding {
+                WavEncoding::Pcm32 => {
+                    let pcm = (value * i32::MAX as f64).round() as i32;
+                    self.frame.write_i32::<LittleEndian>(pcm)?;
+                }
+                WavEncoding::Float32 => self.frame.write_f32::<LittleEndian>(value as f32)?,
+            }
+        }
+        self.writer.write_all(&self.frame)?;
+        self.data_len += self.frame.len() as u64;
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> ExportResult<()> {
+        self.close()?;
+        Ok(())
+    }
+}
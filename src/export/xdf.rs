@@ -0,0 +1,305 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! XDF writer, laid out the way LabRecorder writes files.
+//!
+//! See https://github.com/sccn/xdf/wiki/Specifications.  The samples go in stream 1 with the
+//! same metadata as the LSL outlet, and annotations go in an irregular string stream 2, created
+//! when the first one arrives.  Timestamps come from `ClockSync`, so they're on the same clock
+//! as the LSL outlet and the clock offsets are always zero.
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{Annotation, ExportResult, LeadOffTracker, SampleWriter};
+use crate::client::sample::Sample;
+use crate::clock::{self, ClockSync};
+use crate::common::config::BoardConfig;
+use crate::common::metadata::{StreamMetadata, XmlNode};
+
+const MAGIC: &[u8; 4] = b"XDF:";
+
+const TAG_FILE_HEADER: u16 = 1;
+const TAG_STREAM_HEADER: u16 = 2;
+const TAG_SAMPLES: u16 = 3;
+const TAG_CLOCK_OFFSET: u16 = 4;
+const TAG_BOUNDARY: u16 = 5;
+const TAG_STREAM_FOOTER: u16 = 6;
+
+// lets readers resynchronise after a damaged section of the file
+const BOUNDARY_UUID: [u8; 16] = [
+    0x43, 0xA5, 0x46, 0xDC, 0xCB, 0xF5, 0x41, 0x0F, 0xB3, 0x0E, 0xD5, 0x46, 0x73, 0x83, 0xCB, 0xE4,
+];
+
+const EEG_STREAM_ID: u32 = 1;
+const MARKER_STREAM_ID: u32 = 2;
+
+// the same intervals as LabRecorder
+const CLOCK_OFFSET_INTERVAL: f64 = 5.0;
+const BOUNDARY_INTERVAL: f64 = 10.0;
+const CHUNK_SECS: f64 = 0.1;
+
+/// Appends an XDF variable length integer: a byte giving the width (1, 4 or 8), then the value
+fn write_varlen(buf: &mut Vec<u8>, value: u64) {
+    if value <= u8::MAX as u64 {
+        buf.push(1);
+        buf.push(value as u8);
+    } else if value <= u32::MAX as u64 {
+        buf.push(4);
+        buf.extend_from_slice(&(value as u32).to_le_bytes());
+    } else {
+        buf.push(8);
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Per-stream bookkeeping for the stream footer
+#[derive(Default)]
+struct StreamStats {
+    first_timestamp: Option<f64>,
+    last_timestamp: f64,
+    sample_count: u64,
+}
+
+impl StreamStats {
+    fn add(&mut self, timestamp: f64) {
+        self.first_timestamp.get_or_insert(timestamp);
+        self.last_timestamp = timestamp;
+        self.sample_count += 1;
+    }
+}
+
+pub struct XdfWriter<W: Write> {
+    writer: W,
+    config: BoardConfig,
+    metadata: StreamMetadata,
+    clock_sync: ClockSync,
+    chunk_samples: usize,
+    chunk: Vec<u8>,
+    chunk_len: usize,
+    eeg: StreamStats,
+    markers: Option<StreamStats>,
+    clock_offsets: Vec<(f64, f64)>,
+    last_clock_offset: Option<f64>,
+    last_boundary: Option<f64>,
+    lead_off: LeadOffTracker,
+}
+
+impl XdfWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        config: &BoardConfig,
+        stream_name: &str,
+    ) -> ExportResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?), config, stream_name)
+    }
+}
+
+impl<W: Write> XdfWriter<W> {
+    pub fn new(mut writer: W, config: &BoardConfig, stream_name: &str) -> ExportResult<Self> {
+        writer.write_all(MAGIC)?;
+        let mut xdf = Self {
+            writer,
+            config: config.clone(),
+            metadata: StreamMetadata::new(stream_name, config),
+            clock_sync: ClockSync::default(),
+            chunk_samples: ((config.sample_rate as f64 * CHUNK_SECS) as usize).max(1),
+            chunk: Vec::new(),
+            chunk_len: 0,
+            eeg: StreamStats::default(),
+            markers: None,
+            clock_offsets: Vec::new(),
+            last_clock_offset: None,
+            last_boundary: None,
+            lead_off: LeadOffTracker::default(),
+        };
+
+        let mut file_header = String::from("<?xml version=\"1.0\"?>");
+        XmlNode::new("info")
+            .child(XmlNode::with_value("version", "1.0"))
+            .child(XmlNode::with_value(
+                "datetime",
+                chrono::Local::now().to_rfc3339(),
+            ))
+            .write_xml(&mut file_header);
+        xdf.write_chunk(TAG_FILE_HEADER, file_header.as_bytes())?;
+
+        let stream_header = xdf.metadata.info_xml(clock::local_clock());
+        xdf.write_stream_chunk(TAG_STREAM_HEADER, EEG_STREAM_ID, stream_header.as_bytes())?;
+        Ok(xdf)
+    }
+
+    /// Writes a chunk: its length (as a variable length integer), its tag and its content
+    fn write_chunk(&mut self, tag: u16, content: &[u8]) -> ExportResult<()> {
+        let mut header = Vec::with_capacity(11);
+        write_varlen(&mut header, content.len() as u64 + 2);
+        header.write_u16::<LittleEndian>(tag)?;
+        self.writer.write_all(&header)?;
+        self.writer.write_all(content)?;
+        Ok(())
+    }
+
+    fn write_stream_chunk(&mut self, tag: u16, stream_id: u32, content: &[u8]) -> ExportResult<()> {
+        let mut header = Vec::with_capacity(11);
+        write_varlen(&mut header, content.len() as u64 + 6);
+        header.write_u16::<LittleEndian>(tag)?;
+        header.write_u32::<LittleEndian>(stream_id)?;
+        self.writer.write_all(&header)?;
+        self.writer.write_all(content)?;
+        Ok(())
+    }
+
+    fn flush_samples(&mut self) -> ExportResult<()> {
+        if self.chunk_len == 0 {
+            return Ok(());
+        }
+        let mut content = Vec::with_capacity(self.chunk.len() + 9);
+        write_varlen(&mut content, self.chunk_len as u64);
+        content.extend_from_slice(&self.chunk);
+        self.write_stream_chunk(TAG_SAMPLES, EEG_STREAM_ID, &content)?;
+        self.chunk.clear();
+        self.chunk_len = 0;
+        Ok(())
+    }
+
+    /// Writes the periodic clock offset and boundary chunks when they're due
+    fn write_periodic(&mut self, host_time: f64) -> ExportResult<()> {
+        let due = |last: Option<f64>, interval| match last {
+            Some(last) => host_time - last >= interval,
+            None => true,
+        };
+
+        if due(self.last_clock_offset, CLOCK_OFFSET_INTERVAL) {
+            // samples are timestamped on the local clock, so there's no offset to correct
+            let mut content = Vec::with_capacity(16);
+            content.write_f64::<LittleEndian>(host_time)?;
+            content.write_f64::<LittleEndian>(0.0)?;
+            self.write_stream_chunk(TAG_CLOCK_OFFSET, EEG_STREAM_ID, &content)?;
+            if self.markers.is_some() {
+                self.write_stream_chunk(TAG_CLOCK_OFFSET, MARKER_STREAM_ID, &content)?;
+            }
+            self.clock_offsets.push((host_time, 0.0));
+            self.last_clock_offset = Some(host_time);
+        }
+
+        if due(self.last_boundary, BOUNDARY_INTERVAL) {
+            self.write_chunk(TAG_BOUNDARY, &BOUNDARY_UUID)?;
+            self.last_boundary = Some(host_time);
+        }
+        Ok(())
+    }
+
+    fn write_marker(&mut self, timestamp: f64, text: &str) -> ExportResult<()> {
+        if self.markers.is_none() {
+            let metadata = StreamMetadata::markers(&self.metadata.name);
+            let stream_header = metadata.info_xml(clock::local_clock());
+            self.write_stream_chunk(
+                TAG_STREAM_HEADER,
+                MARKER_STREAM_ID,
+                stream_header.as_bytes(),
+            )?;
+            self.markers = Some(StreamStats::default());
+        }
+
+        let mut content = Vec::with_capacity(text.len() + 20);
+        write_varlen(&mut content, 1);
+        content.push(8);
+        content.write_f64::<LittleEndian>(timestamp)?;
+        write_varlen(&mut content, text.len() as u64);
+        content.extend_from_slice(text.as_bytes());
+        self.write_stream_chunk(TAG_SAMPLES, MARKER_STREAM_ID, &content)?;
+
+        if let Some(ref mut markers) = self.markers {
+            markers.add(timestamp);
+        }
+        Ok(())
+    }
+
+    fn footer_xml(&self, stats: &StreamStats) -> String {
+        let mut clock_offsets = XmlNode::new("clock_offsets");
+        for (time, value) in &self.clock_offsets {
+            clock_offsets = clock_offsets.child(
+                XmlNode::new("offset")
+                    .child(XmlNode::with_value("time", time))
+                    .child(XmlNode::with_value("value", value)),
+            );
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\"?>");
+        XmlNode::new("info")
+            .child(XmlNode::with_value(
+                "first_timestamp",
+                stats.first_timestamp.unwrap_or(0.0),
+            ))
+            .child(XmlNode::with_value("last_timestamp", stats.last_timestamp))
+            .child(XmlNode::with_value("sample_count", stats.sample_count))
+            .child(clock_offsets)
+            .write_xml(&mut xml);
+        xml
+    }
+
+    /// Writes any buffered samples and the stream footers, and returns the underlying writer
+    pub fn close(mut self) -> ExportResult<W> {
+        self.flush_samples()?;
+
+        let footer = self.footer_xml(&self.eeg);
+        self.write_stream_chunk(TAG_STREAM_FOOTER, EEG_STREAM_ID, footer.as_bytes())?;
+        if let Some(markers) = self.markers.take() {
+            let footer = self.footer_xml(&markers);
+            self.write_stream_chunk(TAG_STREAM_FOOTER, MARKER_STREAM_ID, footer.as_bytes())?;
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Send> SampleWriter for XdfWriter<W> {
+    fn write_sample(&mut self, sample: &Sample, host_time: f64) -> ExportResult<()> {
+        let timestamp = self.clock_sync.update(sample.timestamp, host_time);
+        self.write_periodic(host_time)?;
+
+        for text in self.lead_off.update(sample, &self.config) {
+            self.write_marker(timestamp, &text)?;
+        }
+
+        self.chunk.push(8);
+        self.chunk.write_f64::<LittleEndian>(timestamp)?;
+        for channel in sample.channels.iter() {
+            self.chunk.write_i32::<LittleEndian>(channel.sample)?;
+        }
+        self.chunk_len += 1;
+        self.eeg.add(timestamp);
+
+        if self.chunk_len >= self.chunk_samples {
+            self.flush_samples()?;
+        }
+        Ok(())
+    }
+
+    fn annotate(&mut self, annotation: Annotation) -> ExportResult<()> {
+        let start = match self.eeg.first_timestamp {
+            Some(first_timestamp) => first_timestamp,
+            None => clock::local_clock(),
+        };
+        self.write_marker(start + annotation.onset, &annotation.text)
+    }
+
+    fn finish(self: Box<Self>) -> ExportResult<()> {
+        self.close()?;
+        Ok(())
+    }
+}
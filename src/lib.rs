@@ -12,5 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod artifact;
 pub mod client;
+pub mod clock;
 pub mod common;
+pub mod dsp;
+pub mod export;
+#[cfg(feature = "lsl-sys")]
+pub mod lsl;
+pub mod montage;
+pub mod net;
+pub mod openbci;
+pub mod pipeline;
+pub mod quality;
+pub mod record;
+pub mod tui;
@@ -0,0 +1,194 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use log::debug;
+use std::time::{Duration, Instant};
+
+use crate::client::sample::Sample;
+use crate::common::constants::NUM_CHANNELS;
+pub use crate::common::metadata::STREAM_TYPE;
+use crate::common::metadata::{StreamMetadata, XmlNode};
+
+const LSL_TAG: &str = "lsl";
+
+/// Creates an outlet for the board's channels, described by `metadata`.  `chunk_size` is the
+/// number of samples LSL groups into each network transmission, and `max_buffered` is how many
+/// seconds of data the outlet holds for slow consumers.
+pub fn create_outlet(
+    metadata: &StreamMetadata,
+    chunk_size: usize,
+    max_buffered: u32,
+) -> Result<lsl_sys::Outlet<i32>, lsl_sys::Error> {
+    let mut stream_info = lsl_sys::StreamInfo::<i32>::new(
+        &metadata.name,
+        &metadata.stream_type,
+        metadata.channel_count as i32,
+        metadata.sample_rate,
+        &metadata.source_id,
+    )?;
+    describe(&mut stream_info, metadata)?;
+    lsl_sys::Outlet::new(stream_info, chunk_size as i32, max_buffered as i32)
+}
+
+/// Creates an outlet for float features such as band powers, described by `metadata`, which
+/// are pushed a sample at a time
+pub fn create_feature_outlet(
+    metadata: &StreamMetadata,
+    max_buffered: u32,
+) -> Result<lsl_sys::Outlet<f32>, lsl_sys::Error> {
+    let mut stream_info = lsl_sys::StreamInfo::<f32>::new(
+        &metadata.name,
+        &metadata.stream_type,
+        metadata.channel_count as i32,
+        metadata.sample_rate,
+        &metadata.source_id,
+    )?;
+    describe(&mut stream_info, metadata)?;
+    lsl_sys::Outlet::new(stream_info, 1, max_buffered as i32)
+}
+
+/// Creates an outlet for the text markers accompanying the stream `name`, such as detected
+/// artifacts
+pub fn create_marker_outlet(
+    name: &str,
+    max_buffered: u32,
+) -> Result<lsl_sys::Outlet<String>, lsl_sys::Error> {
+    let metadata = StreamMetadata::markers(name);
+    let stream_info = lsl_sys::StreamInfo::<String>::new(
+        &metadata.name,
+        &metadata.stream_type,
+        metadata.channel_count as i32,
+        metadata.sample_rate,
+        &metadata.source_id,
+    )?;
+    lsl_sys::Outlet::new(stream_info, 1, max_buffered as i32)
+}
+
+fn describe<Format>(
+    stream_info: &mut lsl_sys::StreamInfo<Format>,
+    metadata: &StreamMetadata,
+) -> Result<(), lsl_sys::Error> {
+    let desc = stream_info.desc();
+    for node in &metadata.desc.children {
+        append_desc(&desc, node)?;
+    }
+    Ok(())
+}
+
+fn append_desc(element: &lsl_sys::XmlElement, node: &XmlNode) -> Result<(), lsl_sys::Error> {
+    match &node.value {
+        Some(value) => {
+            element.append_child_value(&node.name, value)?;
+        }
+        None => {
+            let child = element.append_child(&node.name)?;
+            for grandchild in &node.children {
+                append_desc(&child, grandchild)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Buffers samples and pushes them to an outlet in chunks, which costs one FFI call and one
+/// outlet lock per chunk instead of per sample.  A chunk is pushed when it holds `chunk_size`
+/// samples, or when its oldest sample has waited `max_latency`, whichever comes first.  The wait
+/// is only checked as samples are pushed, so when they stop coming whoever feeds the outlet has
+/// to call `flush` once `due_in` has passed.
+pub struct ChunkedOutlet {
+    outlet: lsl_sys::Outlet<i32>,
+    chunk_size: usize,
+    max_latency: Option<Duration>,
+    data: Vec<i32>,
+    timestamps: Vec<f64>,
+    oldest: Option<Instant>,
+}
+
+impl ChunkedOutlet {
+    pub fn new(
+        outlet: lsl_sys::Outlet<i32>,
+        chunk_size: usize,
+        max_latency: Option<Duration>,
+    ) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            outlet,
+            chunk_size,
+            max_latency,
+            data: Vec::with_capacity(chunk_size * NUM_CHANNELS),
+            timestamps: Vec::with_capacity(chunk_size),
+            oldest: None,
+        }
+    }
+
+    pub fn push(&mut self, sample: &Sample, timestamp: f64) {
+        self.data
+            .extend(sample.channels.iter().map(|channel| channel.sample));
+        self.timestamps.push(timestamp);
+        let oldest = *self.oldest.get_or_insert_with(Instant::now);
+
+        let latency_exceeded = match self.max_latency {
+            Some(max_latency) => oldest.elapsed() >= max_latency,
+            None => false,
+        };
+        if self.timestamps.len() >= self.chunk_size || latency_exceeded {
+            self.flush();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// How long until the buffered samples have waited `max_latency`, or `None` if nothing is
+    /// buffered or there's no limit
+    pub fn due_in(&self) -> Option<Duration> {
+        let oldest = self.oldest?;
+        let max_latency = self.max_latency?;
+        Some(
+            max_latency
+                .checked_sub(oldest.elapsed())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Pushes whatever is buffered, even if it's less than a full chunk
+    pub fn flush(&mut self) {
+        if self.timestamps.is_empty() {
+            return;
+        }
+
+        let result = self
+            .outlet
+            .push_chunk_with_timestamps(&self.data, &self.timestamps);
+        if result != 0 {
+            debug!(
+                target: LSL_TAG,
+                "Pushing chunk of {} samples returned {}",
+                self.timestamps.len(),
+                result
+            );
+        }
+
+        self.data.clear();
+        self.timestamps.clear();
+        self.oldest = None;
+    }
+}
+
+impl Drop for ChunkedOutlet {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -12,58 +12,130 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use log::info;
-use std::time::Duration;
+use std::error::Error;
+use std::ffi::OsString;
 
-use clap::{App, AppSettings, Arg};
-use serialport::prelude::SerialPortSettings;
+use clap::{App, AppSettings, ArgMatches};
 
-use hackeeg::{client::HackEEGClient, common};
+use hackeeg::common;
 
-const MAIN_TAG: &str = "main";
+mod cli;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let matches = App::new("Serialport Example - Receive Data")
-        .about("Reads data from a serial port and echoes it to stdout")
-        .setting(AppSettings::DisableVersion)
-        .arg(
-            Arg::with_name("verbosity")
-                .short("v")
-                .multiple(true)
-                .help("Sets the level of verbosity"),
-        )
-        .arg(
-            Arg::with_name("port")
-                .help("The device path to a serial port")
-                .use_delimiter(false)
-                .required(true),
-        )
-        .arg(
-            Arg::with_name("baud")
-                .help("The baud rate to connect at")
-                .use_delimiter(false)
-                .default_value("115200")
-                .required(true),
-        )
-        .get_matches();
+use cli::config::Config;
+use cli::Connection;
 
-    let log_level = match matches.occurrences_of("verbosity") {
+// the subcommands, which are also the sections the config file may have besides [connection]
+const COMMANDS: [&str; 10] = [
+    "stream",
+    "record",
+    "replay",
+    "info",
+    "regs",
+    "impedance",
+    "selftest",
+    "blink",
+    "ports",
+    "bench",
+];
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    cli::global_args(
+        App::new("hackeeg")
+            .about("Streams, records and checks data from the HackEEG board")
+            .setting(AppSettings::DisableVersion)
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .setting(AppSettings::VersionlessSubcommands),
+    )
+    .subcommand(cli::stream::subcommand("stream", false))
+    .subcommand(cli::stream::subcommand("record", true))
+    .subcommand(cli::replay::subcommand())
+    .subcommand(cli::board::info_subcommand())
+    .subcommand(cli::board::regs_subcommand())
+    .subcommand(cli::impedance::subcommand())
+    .subcommand(cli::selftest::subcommand())
+    .subcommand(cli::board::blink_subcommand())
+    .subcommand(cli::ports::subcommand())
+    .subcommand(cli::bench::subcommand())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let matches = app().get_matches();
+    let config = {
+        let (_, sub) = matches.subcommand();
+        Config::load(sub.and_then(|sub| sub.value_of("config")))?
+    };
+    config.check_commands(&COMMANDS)?;
+    let matches = with_config_defaults(matches, &config)?;
+
+    let (command, sub) = match matches.subcommand() {
+        (command, Some(sub)) => (command, sub),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+    let log_level = match sub.occurrences_of("verbosity") {
         0 => log::LevelFilter::Info,
         1 => log::LevelFilter::Debug,
         _ => log::LevelFilter::Trace,
     };
+    if sub.is_present("tui") {
+        common::log::setup_logger_with_output(log_level, None, hackeeg::tui::log_message)?;
+    } else {
+        common::log::setup_logger(log_level, None)?;
+    }
 
-    let port_name = matches.value_of("port").unwrap();
-    let baud_rate = matches.value_of("baud").unwrap().parse::<u32>()?;
-
-    common::log::setup_logger(log_level, None)?;
-
-    let mut settings = SerialPortSettings::default();
-    settings.baud_rate = baud_rate;
-    settings.timeout = Duration::from_millis(10);
+    match command {
+        "replay" => return cli::replay::run(sub),
+        "ports" => return cli::ports::run(sub, &config.connection),
+        _ => {}
+    }
+    let connection = Connection::from_matches(sub, &config.connection)?;
+    match command {
+        "stream" | "record" => cli::stream::run(sub, &connection),
+        "info" => cli::board::info(&connection),
+        "regs" => cli::board::regs(sub, &connection),
+        "impedance" => cli::impedance::run(sub, &connection),
+        "selftest" => cli::selftest::run(sub, &connection),
+        "blink" => cli::board::blink(sub, &connection),
+        "bench" => cli::bench::run(sub, &connection),
+        _ => unreachable!("unknown subcommand {}", command),
+    }
+}
 
-    let client = HackEEGClient::new(port_name, &settings)?;
-    client.blink_test(10)?;
+/// Parses the command line again with the config file's defaults for the command added, if it
+/// has any
+fn with_config_defaults<'a>(
+    matches: ArgMatches<'a>,
+    config: &Config,
+) -> Result<ArgMatches<'a>, Box<dyn Error>> {
+    let defaults = match matches.subcommand() {
+        (command, Some(sub)) => config.default_args(command, sub)?,
+        _ => Vec::new(),
+    };
+    if defaults.is_empty() {
+        return Ok(matches);
+    }
 
-    Ok(())
+    // the defaults go before any `--`, after which everything is positional
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    args.splice(end..end, defaults.into_iter().map(OsString::from));
+    match app().get_matches_from_safe(args) {
+        Ok(matches) => Ok(matches),
+        Err(mut e) => {
+            if let Some(path) = &config.path {
+                e.message
+                    .push_str(&format!("\n(with defaults from {})", path.display()));
+            }
+            e.exit()
+        }
+    }
 }
@@ -0,0 +1,44 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(Debug)]
+pub enum MontageError {
+    IOError(std::io::Error),
+    ParseError(toml::de::Error),
+    BadMontage(String),
+}
+
+impl From<std::io::Error> for MontageError {
+    fn from(e: std::io::Error) -> Self {
+        MontageError::IOError(e)
+    }
+}
+
+impl From<toml::de::Error> for MontageError {
+    fn from(e: toml::de::Error) -> Self {
+        MontageError::ParseError(e)
+    }
+}
+
+impl std::error::Error for MontageError {}
+
+impl std::fmt::Display for MontageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            MontageError::IOError(e) => write!(f, "I/O error: {}", e),
+            MontageError::ParseError(e) => write!(f, "Invalid montage file: {}", e),
+            MontageError::BadMontage(message) => write!(f, "{}", message),
+        }
+    }
+}
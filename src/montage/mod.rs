@@ -0,0 +1,348 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Software re-referencing and montages.
+//!
+//! The board measures each channel against SRB1 or its own negative input; a `Montage`
+//! re-expresses the channels in microvolts against another reference, or as differences
+//! between pairs of electrodes.  Montages are read from TOML files:
+//!
+//! ```toml
+//! name = "linked ears"
+//! # none (the default), average, channel or linked
+//! reference = "linked"
+//! # the channel for a channel reference, or those averaged for a linked reference
+//! reference_channels = ["A1", "A2"]
+//! # channels left out of a common average
+//! exclude = []
+//!
+//! # optionally, the channels to output, up to 8; without any, every channel is re-referenced
+//! [[derivation]]
+//! channel = "Fp1"
+//! # defaults to the reference
+//! minus = "F3"
+//! # defaults to e.g. Fp1-F3
+//! label = "Fp1-F3"
+//! ```
+//!
+//! Channels are named by their labels or by their numbers from 1.  Output channels carry the
+//! derivation labels, and slots a bipolar montage leaves empty are zero and disabled.
+
+use serde::Deserialize;
+use std::path::Path;
+
+mod err;
+
+use crate::client::sample::Sample;
+use crate::common::config::{BoardConfig, MontageInfo, DIGITAL_MAX, DIGITAL_MIN};
+use crate::common::constants::NUM_CHANNELS;
+pub use err::MontageError;
+
+pub type MontageResult<T> = Result<T, MontageError>;
+
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ReferenceKind {
+    #[default]
+    None,
+    Average,
+    Channel,
+    Linked,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum ChannelRef {
+    Number(usize),
+    Label(String),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct DerivationSpec {
+    channel: ChannelRef,
+    minus: Option<ChannelRef>,
+    label: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct MontageFile {
+    name: Option<String>,
+    #[serde(default)]
+    reference: ReferenceKind,
+    #[serde(default)]
+    reference_channels: Vec<ChannelRef>,
+    #[serde(default)]
+    exclude: Vec<ChannelRef>,
+    #[serde(default, rename = "derivation")]
+    derivations: Vec<DerivationSpec>,
+}
+
+/// One output channel, as a weighted sum of the input channels in microvolts
+struct Derivation {
+    weights: [f64; NUM_CHANNELS],
+    // the output's scale, that of its first channel
+    microvolts_per_count: f64,
+}
+
+pub struct Montage {
+    name: String,
+    input_scale: [f64; NUM_CHANNELS],
+    derivations: Vec<Derivation>,
+    config: BoardConfig,
+}
+
+impl Montage {
+    /// Reads a montage file for the channels of `config`, which names them
+    pub fn load<P: AsRef<Path>>(path: P, config: &BoardConfig) -> MontageResult<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let default_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::parse(&text, &default_name, config)
+    }
+
+    /// Parses the TOML text of a montage, named `default_name` unless it has a name of its own
+    pub fn parse(text: &str, default_name: &str, config: &BoardConfig) -> MontageResult<Self> {
+        let file: MontageFile = toml::from_str(text)?;
+        let name = file
+            .name
+            .clone()
+            .unwrap_or_else(|| default_name.to_string());
+        let find = |channel: &ChannelRef| find_channel(channel, config);
+
+        let references = file
+            .reference_channels
+            .iter()
+            .map(find)
+            .collect::<MontageResult<Vec<_>>>()?;
+        let excluded = file
+            .exclude
+            .iter()
+            .map(find)
+            .collect::<MontageResult<Vec<_>>>()?;
+        let label = |chan_idx: usize| config.channels[chan_idx].label.clone();
+
+        // the reference as weights on the inputs, and its name in labels
+        let mut reference = [0.0; NUM_CHANNELS];
+        let reference_name = match file.reference {
+            ReferenceKind::None => {
+                if !references.is_empty() {
+                    return Err(bad(
+                        "reference_channels needs a channel or linked reference",
+                    ));
+                }
+                None
+            }
+            ReferenceKind::Average => {
+                if !references.is_empty() {
+                    return Err(bad(
+                        "Use exclude, not reference_channels, with an average reference",
+                    ));
+                }
+                // powered-down channels would only drag the average towards 0
+                let averaged: Vec<usize> = (0..NUM_CHANNELS)
+                    .filter(|chan_idx| {
+                        config.channels[*chan_idx].enabled && !excluded.contains(chan_idx)
+                    })
+                    .collect();
+                if averaged.len() < 2 {
+                    return Err(bad("An average reference needs at least 2 channels"));
+                }
+                for chan_idx in &averaged {
+                    reference[*chan_idx] = 1.0 / averaged.len() as f64;
+                }
+                Some("AVG".to_string())
+            }
+            ReferenceKind::Channel => {
+                if references.len() != 1 {
+                    return Err(bad("A channel reference needs exactly 1 reference channel"));
+                }
+                reference[references[0]] = 1.0;
+                Some(label(references[0]))
+            }
+            ReferenceKind::Linked => {
+                if references.len() < 2 {
+                    return Err(bad(
+                        "A linked reference needs at least 2 reference channels",
+                    ));
+                }
+                for chan_idx in &references {
+                    reference[*chan_idx] += 1.0 / references.len() as f64;
+                }
+                let labels: Vec<String> =
+                    references.iter().map(|chan_idx| label(*chan_idx)).collect();
+                Some(labels.join("+"))
+            }
+        };
+        if !excluded.is_empty() && file.reference != ReferenceKind::Average {
+            return Err(bad("exclude only applies to an average reference"));
+        }
+        if file.derivations.len() > NUM_CHANNELS {
+            return Err(bad(&format!(
+                "A montage can have at most {} derivations",
+                NUM_CHANNELS
+            )));
+        }
+
+        let scale = |chan_idx: usize| config.channels[chan_idx].microvolts_per_count();
+        let mut input_scale = [0.0; NUM_CHANNELS];
+        for (chan_idx, input) in input_scale.iter_mut().enumerate() {
+            *input = scale(chan_idx);
+        }
+
+        let mut output = config.clone();
+        let mut derivations = Vec::with_capacity(NUM_CHANNELS);
+        if file.derivations.is_empty() {
+            // every channel against the reference
+            for chan_idx in 0..NUM_CHANNELS {
+                let mut weights = reference;
+                for weight in weights.iter_mut() {
+                    *weight = -*weight;
+                }
+                weights[chan_idx] += 1.0;
+                derivations.push(Derivation {
+                    weights,
+                    microvolts_per_count: scale(chan_idx),
+                });
+                if let Some(ref reference_name) = reference_name {
+                    output.channels[chan_idx].label =
+                        format!("{}-{}", label(chan_idx), reference_name);
+                }
+            }
+        } else {
+            for (slot, spec) in file.derivations.iter().enumerate() {
+                let chan_idx = find(&spec.channel)?;
+                let mut weights = [0.0; NUM_CHANNELS];
+                weights[chan_idx] = 1.0;
+                let minus_name = match spec.minus {
+                    Some(ref minus) => {
+                        let minus_idx = find(minus)?;
+                        weights[minus_idx] -= 1.0;
+                        Some(label(minus_idx))
+                    }
+                    None => {
+                        for (weight, reference) in weights.iter_mut().zip(&reference) {
+                            *weight -= reference;
+                        }
+                        reference_name.clone()
+                    }
+                };
+                derivations.push(Derivation {
+                    weights,
+                    microvolts_per_count: scale(chan_idx),
+                });
+                let channel = &mut output.channels[slot];
+                channel.gain = config.channels[chan_idx].gain;
+                channel.enabled = config.channels[chan_idx].enabled;
+                channel.input = config.channels[chan_idx].input;
+                channel.label = match (&spec.label, minus_name) {
+                    (Some(label), _) => label.clone(),
+                    (None, Some(minus_name)) => format!("{}-{}", label(chan_idx), minus_name),
+                    (None, None) => label(chan_idx),
+                };
+            }
+            for channel in &mut output.channels[file.derivations.len()..] {
+                channel.label = "unused".to_string();
+                channel.enabled = false;
+            }
+        }
+
+        let bipolar = !file.derivations.is_empty()
+            && file.derivations.iter().all(|spec| spec.minus.is_some());
+        let reference_label = if bipolar {
+            "bipolar".to_string()
+        } else {
+            match file.reference {
+                ReferenceKind::None => "none".to_string(),
+                ReferenceKind::Average => "average".to_string(),
+                _ => reference_name.unwrap_or_default(),
+            }
+        };
+        output.montage = Some(MontageInfo {
+            name: name.clone(),
+            reference: reference_label,
+            common_average: file.reference == ReferenceKind::Average && !bipolar,
+        });
+
+        Ok(Self {
+            name,
+            input_scale,
+            derivations,
+            config: output,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The configuration of the output channels: their labels, scales and the montage itself
+    pub fn config(&self) -> &BoardConfig {
+        &self.config
+    }
+
+    /// Replaces a sample's channels with the montage's, rounded back to counts and clipped to
+    /// the 24-bit range
+    pub fn apply(&self, sample: &mut Sample) {
+        let mut microvolts = [0.0; NUM_CHANNELS];
+        for ((value, channel), scale) in microvolts
+            .iter_mut()
+            .zip(&sample.channels)
+            .zip(&self.input_scale)
+        {
+            *value = channel.sample as f64 * scale;
+        }
+        for (slot, channel) in sample.channels.iter_mut().enumerate() {
+            channel.sample = match self.derivations.get(slot) {
+                Some(derivation) => {
+                    let value: f64 = derivation
+                        .weights
+                        .iter()
+                        .zip(&microvolts)
+                        .map(|(weight, value)| weight * value)
+                        .sum();
+                    let counts = (value / derivation.microvolts_per_count).round();
+                    counts.max(DIGITAL_MIN as f64).min(DIGITAL_MAX as f64) as i32
+                }
+                None => 0,
+            };
+        }
+    }
+}
+
+fn bad(message: &str) -> MontageError {
+    MontageError::BadMontage(message.to_string())
+}
+
+/// A channel's index from its label or its number from 1
+fn find_channel(channel: &ChannelRef, config: &BoardConfig) -> MontageResult<usize> {
+    let found = match channel {
+        ChannelRef::Number(number) => number.checked_sub(1).filter(|idx| *idx < NUM_CHANNELS),
+        ChannelRef::Label(label) => config.channel_index(label),
+    };
+    found.ok_or_else(|| {
+        let name = match channel {
+            ChannelRef::Number(number) => number.to_string(),
+            ChannelRef::Label(label) => label.clone(),
+        };
+        MontageError::BadMontage(format!(
+            "No channel '{}'; use a channel label or a number from 1 to {}",
+            name, NUM_CHANNELS
+        ))
+    })
+}
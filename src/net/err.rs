@@ -0,0 +1,44 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(Debug)]
+pub enum NetError {
+    IOError(std::io::Error),
+    WebSocketError(Box<tungstenite::Error>),
+    BadOption(String),
+}
+
+impl From<std::io::Error> for NetError {
+    fn from(e: std::io::Error) -> Self {
+        NetError::IOError(e)
+    }
+}
+
+impl From<tungstenite::Error> for NetError {
+    fn from(e: tungstenite::Error) -> Self {
+        NetError::WebSocketError(Box::new(e))
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl std::fmt::Display for NetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            NetError::IOError(e) => write!(f, "I/O error: {}", e),
+            NetError::WebSocketError(e) => write!(f, "WebSocket error: {}", e),
+            NetError::BadOption(message) => write!(f, "{}", message),
+        }
+    }
+}
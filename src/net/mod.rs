@@ -0,0 +1,229 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Network outputs, for watching a session from another program or machine.
+//!
+//! Samples are sent either as JSON objects or as fixed-size little-endian binary records:
+//!
+//! ```text
+//! sample_number  u32
+//! timestamp      u32        board time, in microseconds
+//! host_time      f64        clock::local_clock() when the sample was received, in seconds
+//! channels       8 x i32    counts, or 8 x f32 microvolts
+//! loff_statp     u8
+//! loff_statn     u8
+//! ads_gpio       u8
+//! reserved       u8
+//! ```
+//!
+//! The record is `BINARY_SAMPLE_LEN` bytes, and the channels start 4-byte aligned, so they can be
+//! viewed directly as an `Int32Array` or `Float32Array` in a browser.  The raw TCP and UDP
+//! outputs wrap these records in packets, described in `packet`.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+mod err;
+pub mod osc;
+pub mod packet;
+mod tcp;
+mod udp;
+mod websocket;
+
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+use crate::export::Units;
+pub use err::NetError;
+pub use osc::OscSender;
+pub use packet::{PacketOptions, Packetizer};
+pub use tcp::TcpServer;
+pub use udp::UdpSender;
+pub use websocket::WebSocketServer;
+
+pub type NetResult<T> = Result<T, NetError>;
+
+pub const BINARY_SAMPLE_LEN: usize = 20 + 4 * NUM_CHANNELS;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    Json,
+    Binary,
+}
+
+impl FromStr for FrameFormat {
+    type Err = NetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(FrameFormat::Json),
+            "binary" => Ok(FrameFormat::Binary),
+            _ => Err(NetError::BadOption(format!("Unknown frame format '{}'", s))),
+        }
+    }
+}
+
+/// How samples are sent to a client
+#[derive(Serialize, Clone, Debug)]
+pub struct StreamOptions {
+    pub format: FrameFormat,
+    pub units: Units,
+    /// send every nth sample.  Samples in between are skipped without filtering, which is fine
+    /// for a display but aliases anything above the reduced Nyquist frequency.
+    pub decimate: u32,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            format: FrameFormat::Json,
+            units: Units::Counts,
+            decimate: 1,
+        }
+    }
+}
+
+/// Requests from network clients for the acquisition loop
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Start,
+    Stop,
+}
+
+/// Counters kept by the acquisition loop, which clients can query
+pub struct AcquisitionStats {
+    started: Instant,
+    samples: AtomicU64,
+    errors: AtomicU64,
+    acquiring: AtomicBool,
+}
+
+impl Default for AcquisitionStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            samples: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            acquiring: AtomicBool::new(true),
+        }
+    }
+}
+
+impl AcquisitionStats {
+    pub fn add_sample(&self) {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_acquiring(&self, acquiring: bool) {
+        self.acquiring.store(acquiring, Ordering::Relaxed);
+    }
+
+    pub fn acquiring(&self) -> bool {
+        self.acquiring.load(Ordering::Relaxed)
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Seconds since the server started
+    pub fn elapsed(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonChannels {
+    Counts(Vec<i32>),
+    Microvolts(Vec<f64>),
+}
+
+#[derive(Serialize)]
+struct JsonSample {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    sample_number: u32,
+    timestamp: u32,
+    host_time: f64,
+    channels: JsonChannels,
+    lead_off: [u8; 2],
+    ads_gpio: u8,
+}
+
+/// Encodes a sample as a JSON object with `"type": "sample"`
+pub fn encode_json(sample: &Sample, host_time: f64, units: Units, config: &BoardConfig) -> String {
+    let channels = match units {
+        Units::Counts => JsonChannels::Counts(
+            sample
+                .channels
+                .iter()
+                .map(|channel| channel.sample)
+                .collect(),
+        ),
+        Units::Microvolts => JsonChannels::Microvolts(
+            sample
+                .channels
+                .iter()
+                .zip(&config.channels)
+                .map(|(channel, config)| config.to_microvolts(channel.sample))
+                .collect(),
+        ),
+    };
+    let json_sample = JsonSample {
+        kind: "sample",
+        sample_number: sample.sample_number,
+        timestamp: sample.timestamp,
+        host_time,
+        channels,
+        lead_off: [sample.loff_statp, sample.loff_statn],
+        ads_gpio: sample.ads_gpio,
+    };
+    // a struct of numbers always serializes
+    serde_json::to_string(&json_sample).unwrap_or_default()
+}
+
+/// Appends a sample to `buf` as a `BINARY_SAMPLE_LEN` byte record
+pub fn encode_binary(
+    buf: &mut Vec<u8>,
+    sample: &Sample,
+    host_time: f64,
+    units: Units,
+    config: &BoardConfig,
+) {
+    buf.extend_from_slice(&sample.sample_number.to_le_bytes());
+    buf.extend_from_slice(&sample.timestamp.to_le_bytes());
+    buf.extend_from_slice(&host_time.to_le_bytes());
+    for (channel, config) in sample.channels.iter().zip(&config.channels) {
+        match units {
+            Units::Counts => buf.extend_from_slice(&channel.sample.to_le_bytes()),
+            Units::Microvolts => {
+                let microvolts = config.to_microvolts(channel.sample) as f32;
+                buf.extend_from_slice(&microvolts.to_le_bytes())
+            }
+        }
+    }
+    buf.extend_from_slice(&[sample.loff_statp, sample.loff_statn, sample.ads_gpio, 0]);
+}
@@ -0,0 +1,239 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Open Sound Control output over UDP, for Max, Pure Data, SuperCollider, TouchDesigner and the
+//! like.  Messages sent:
+//!
+//! ```text
+//! /hackeeg/eeg               8 floats     channel values in microvolts, one message per sample
+//! /hackeeg/bandpower/alpha   8 floats     8-12 Hz power over the last second, in µV², 4 times a second
+//! /hackeeg/leadoff           8 ints       1 for channels with an electrode off, sent when it changes
+//! ```
+//!
+//! With a chunk size above 1, the `/hackeeg/eeg` messages for that many samples are sent
+//! together in one OSC bundle.
+
+use log::warn;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use super::udp::open_socket;
+use super::NetResult;
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::common::constants::NUM_CHANNELS;
+use crate::dsp::{Band, Taper, Welch, WelchOptions};
+
+const OSC_TAG: &str = "osc";
+
+pub const EEG_ADDRESS: &str = "/hackeeg/eeg";
+pub const ALPHA_ADDRESS: &str = "/hackeeg/bandpower/alpha";
+pub const LEADOFF_ADDRESS: &str = "/hackeeg/leadoff";
+
+const ALPHA_BAND: (f64, f64) = (8.0, 12.0);
+// one-second segments, overlapping so the band power updates 4 times a second
+const BANDPOWER_OPTIONS: WelchOptions = WelchOptions {
+    segment: 1.0,
+    overlap: 0.75,
+    taper: Taper::Hann,
+    average: 1,
+};
+
+// "#bundle", then a time tag of 1, meaning "immediately"
+const BUNDLE_HEADER: &[u8; 16] = b"#bundle\0\0\0\0\0\0\0\0\x01";
+// the size of one /hackeeg/eeg message, as a bundle element with its length prefix
+const EEG_ELEMENT_LEN: usize = 4 + 16 + 12 + 4 * NUM_CHANNELS;
+
+/// The most samples a chunk can hold, keeping its bundle within one UDP datagram
+pub const MAX_CHUNK: usize = (65507 - BUNDLE_HEADER.len()) / EEG_ELEMENT_LEN;
+
+enum OscArg {
+    Float(f32),
+    Int(i32),
+}
+
+fn write_padded_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    // strings end with a null, then are padded to a multiple of 4 bytes
+    let padding = 4 - s.len() % 4;
+    buf.resize(buf.len() + padding, 0);
+}
+
+/// Appends an OSC message to `buf`
+fn write_message<I: IntoIterator<Item = OscArg>>(buf: &mut Vec<u8>, address: &str, args: I) {
+    let args: Vec<OscArg> = args.into_iter().collect();
+    let type_tags: String = std::iter::once(',')
+        .chain(args.iter().map(|arg| match arg {
+            OscArg::Float(_) => 'f',
+            OscArg::Int(_) => 'i',
+        }))
+        .collect();
+    write_padded_str(buf, address);
+    write_padded_str(buf, &type_tags);
+    for arg in args {
+        match arg {
+            OscArg::Float(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Int(value) => buf.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+}
+
+pub struct OscSender {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    config: BoardConfig,
+    chunk: usize,
+    bundle: Vec<u8>,
+    chunk_samples: usize,
+    alpha: Band,
+    spectrum: Option<Welch>,
+    lead_off: Option<[bool; NUM_CHANNELS]>,
+    errors: u64,
+}
+
+impl OscSender {
+    /// `chunk` is the number of samples sent per bundle, or 1 to send each sample as its own
+    /// message; `ttl` is the time-to-live for multicast destinations
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        config: &BoardConfig,
+        chunk: usize,
+        ttl: u32,
+    ) -> NetResult<Self> {
+        let (socket, destination) = open_socket(addr, ttl)?;
+        Ok(Self {
+            socket,
+            destination,
+            config: config.clone(),
+            chunk: chunk.clamp(1, MAX_CHUNK),
+            bundle: Vec::new(),
+            chunk_samples: 0,
+            alpha: Band::new("alpha", ALPHA_BAND.0, ALPHA_BAND.1),
+            // too low a sample rate for a one-second segment just goes without
+            spectrum: Welch::new(config.sample_rate, NUM_CHANNELS, &BANDPOWER_OPTIONS).ok(),
+            lead_off: None,
+            errors: 0,
+        })
+    }
+
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// Packets that couldn't be sent
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn send(&mut self, sample: &Sample) {
+        let microvolts: Vec<f64> = sample
+            .channels
+            .iter()
+            .zip(&self.config.channels)
+            .map(|(channel, config)| config.to_microvolts(channel.sample))
+            .collect();
+
+        let mut message = Vec::with_capacity(EEG_ELEMENT_LEN);
+        write_message(
+            &mut message,
+            EEG_ADDRESS,
+            microvolts.iter().map(|value| OscArg::Float(*value as f32)),
+        );
+        if self.chunk == 1 {
+            self.send_packet(&message);
+        } else {
+            if self.chunk_samples == 0 {
+                self.bundle.clear();
+                self.bundle.extend_from_slice(BUNDLE_HEADER);
+            }
+            self.bundle
+                .extend_from_slice(&(message.len() as i32).to_be_bytes());
+            self.bundle.extend_from_slice(&message);
+            self.chunk_samples += 1;
+            if self.chunk_samples == self.chunk {
+                self.flush();
+            }
+        }
+
+        let band = &self.alpha;
+        let alpha = match self.spectrum {
+            Some(ref mut spectrum) => {
+                if spectrum.push(&microvolts) {
+                    Some(
+                        (0..NUM_CHANNELS)
+                            .map(|channel| spectrum.band_power(channel, band))
+                            .collect::<Vec<_>>(),
+                    )
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        if let Some(powers) = alpha {
+            let mut message = Vec::new();
+            write_message(
+                &mut message,
+                ALPHA_ADDRESS,
+                powers.iter().map(|power| OscArg::Float(*power as f32)),
+            );
+            self.send_packet(&message);
+        }
+
+        let mut lead_off = [false; NUM_CHANNELS];
+        for (channel, off) in lead_off.iter_mut().enumerate() {
+            *off = (sample.loff_statp | sample.loff_statn) & (1 << channel) != 0;
+        }
+        if self.lead_off != Some(lead_off) {
+            self.lead_off = Some(lead_off);
+            let mut message = Vec::new();
+            write_message(
+                &mut message,
+                LEADOFF_ADDRESS,
+                lead_off.iter().map(|off| OscArg::Int(*off as i32)),
+            );
+            self.send_packet(&message);
+        }
+    }
+
+    /// Sends any partly filled bundle
+    pub fn flush(&mut self) {
+        if self.chunk_samples == 0 {
+            return;
+        }
+        self.chunk_samples = 0;
+        let bundle = std::mem::take(&mut self.bundle);
+        self.send_packet(&bundle);
+        self.bundle = bundle;
+    }
+
+    fn send_packet(&mut self, packet: &[u8]) {
+        match self.socket.send_to(packet, self.destination) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => self.errors += 1,
+            Err(e) => {
+                if self.errors == 0 {
+                    warn!(target: OSC_TAG, "Error sending to {}: {}", self.destination, e);
+                }
+                self.errors += 1;
+            }
+        }
+    }
+}
+
+impl Drop for OscSender {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
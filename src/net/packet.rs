@@ -0,0 +1,176 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Packet framing for the raw TCP and UDP outputs.
+//!
+//! Every packet starts with a 12-byte header, all integers little-endian:
+//!
+//! ```text
+//! magic        4 bytes   "HEEG"
+//! version      u8        PACKET_VERSION
+//! kind         u8        PACKET_HEADER (1) or PACKET_SAMPLES (2)
+//! payload_len  u16       bytes of payload that follow
+//! sequence     u32       numbers the sample packets of an output from 0, so receivers can
+//!                        detect loss; always 0 in header packets
+//! ```
+//!
+//! A header packet's payload is a JSON object describing the stream (`StreamHeader`): sample
+//! rate, units, record length and the channels' labels, gains and microvolts per count.  It's
+//! sent when a TCP client connects, and every second over UDP so receivers can join at any time.
+//!
+//! A sample packet's payload is one or more consecutive binary records, each `BINARY_SAMPLE_LEN`
+//! bytes in the layout described in `net`.
+
+use serde::Serialize;
+
+use super::{encode_binary, BINARY_SAMPLE_LEN};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::export::Units;
+
+pub const PACKET_MAGIC: &[u8; 4] = b"HEEG";
+pub const PACKET_VERSION: u8 = 1;
+pub const PACKET_HEADER: u8 = 1;
+pub const PACKET_SAMPLES: u8 = 2;
+pub const PACKET_HEADER_LEN: usize = 12;
+
+/// The most records a sample packet can hold, keeping it within one UDP datagram
+pub const MAX_RECORDS_PER_PACKET: usize = (65507 - PACKET_HEADER_LEN) / BINARY_SAMPLE_LEN;
+
+#[derive(Serialize)]
+struct ChannelHeader<'a> {
+    label: &'a str,
+    gain: u32,
+    scaling_factor: f64,
+}
+
+/// The payload of a header packet
+#[derive(Serialize)]
+struct StreamHeader<'a> {
+    sample_rate: u32,
+    channel_count: usize,
+    units: Units,
+    record_len: usize,
+    channels: Vec<ChannelHeader<'a>>,
+}
+
+/// How samples are packed for the TCP and UDP outputs
+#[derive(Clone, Debug)]
+pub struct PacketOptions {
+    pub units: Units,
+    /// records per sample packet; more means fewer packets but more latency
+    pub records_per_packet: usize,
+}
+
+impl Default for PacketOptions {
+    fn default() -> Self {
+        Self {
+            units: Units::Counts,
+            records_per_packet: 1,
+        }
+    }
+}
+
+/// Accumulates samples into packets
+pub struct Packetizer {
+    config: BoardConfig,
+    options: PacketOptions,
+    packet: Vec<u8>,
+    records: usize,
+    sequence: u32,
+}
+
+impl Packetizer {
+    pub fn new(config: &BoardConfig, options: &PacketOptions) -> Self {
+        let mut options = options.clone();
+        options.records_per_packet = options.records_per_packet.clamp(1, MAX_RECORDS_PER_PACKET);
+        Self {
+            config: config.clone(),
+            options,
+            packet: Vec::new(),
+            records: 0,
+            sequence: 0,
+        }
+    }
+
+    fn write_packet_header(buf: &mut Vec<u8>, kind: u8, payload_len: usize, sequence: u32) {
+        buf.extend_from_slice(PACKET_MAGIC);
+        buf.push(PACKET_VERSION);
+        buf.push(kind);
+        buf.extend_from_slice(&(payload_len as u16).to_le_bytes());
+        buf.extend_from_slice(&sequence.to_le_bytes());
+    }
+
+    /// A header packet describing the stream
+    pub fn header_packet(&self) -> Vec<u8> {
+        let header = StreamHeader {
+            sample_rate: self.config.sample_rate,
+            channel_count: self.config.channels.len(),
+            units: self.options.units,
+            record_len: BINARY_SAMPLE_LEN,
+            channels: self
+                .config
+                .channels
+                .iter()
+                .map(|channel| ChannelHeader {
+                    label: &channel.label,
+                    gain: channel.gain,
+                    scaling_factor: channel.microvolts_per_count(),
+                })
+                .collect(),
+        };
+        // plain data always serializes
+        let payload = serde_json::to_vec(&header).unwrap_or_default();
+
+        let mut packet = Vec::with_capacity(PACKET_HEADER_LEN + payload.len());
+        Self::write_packet_header(&mut packet, PACKET_HEADER, payload.len(), 0);
+        packet.extend_from_slice(&payload);
+        packet
+    }
+
+    /// Adds a sample, returning a sample packet once it's full
+    pub fn push(&mut self, sample: &Sample, host_time: f64) -> Option<Vec<u8>> {
+        if self.records == 0 {
+            self.packet.clear();
+            // the payload length is filled in when the packet is complete
+            Self::write_packet_header(&mut self.packet, PACKET_SAMPLES, 0, self.sequence);
+        }
+        encode_binary(
+            &mut self.packet,
+            sample,
+            host_time,
+            self.options.units,
+            &self.config,
+        );
+        self.records += 1;
+
+        if self.records >= self.options.records_per_packet {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the packet being built, if it has any samples
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        if self.records == 0 {
+            return None;
+        }
+        let payload_len = (self.records * BINARY_SAMPLE_LEN) as u16;
+        self.packet[6..8].copy_from_slice(&payload_len.to_le_bytes());
+        self.records = 0;
+        self.sequence = self.sequence.wrapping_add(1);
+        Some(std::mem::take(&mut self.packet))
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Raw TCP server: each client is sent a header packet, then sample packets as they're built.
+//! Clients only receive; anything they send is ignored.
+
+use log::{info, warn};
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::packet::{PacketOptions, Packetizer};
+use super::NetResult;
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+
+const TCP_TAG: &str = "tcp";
+
+// how long threads wait before checking for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// a client that can't take a packet for this long is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// packets waiting for a client's thread
+const CLIENT_QUEUE_LEN: usize = 1024;
+
+struct ClientHandle {
+    packets: SyncSender<Arc<Vec<u8>>>,
+}
+
+struct Shared {
+    header_packet: Vec<u8>,
+    clients: Mutex<Vec<ClientHandle>>,
+    dropped: AtomicU64,
+    shutdown: AtomicBool,
+}
+
+pub struct TcpServer {
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+    packetizer: Packetizer,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl TcpServer {
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        config: &BoardConfig,
+        options: &PacketOptions,
+    ) -> NetResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let packetizer = Packetizer::new(config, options);
+        let shared = Arc::new(Shared {
+            header_packet: packetizer.header_packet(),
+            clients: Mutex::new(Vec::new()),
+            dropped: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+        });
+        let accept_thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || accept_clients(listener, shared))
+        };
+
+        Ok(Self {
+            local_addr,
+            shared,
+            packetizer,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn clients(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+
+    /// Packets not sent to a client because it had fallen behind
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn send(&mut self, sample: &Sample, host_time: f64) {
+        if let Some(packet) = self.packetizer.push(sample, host_time) {
+            self.broadcast(packet);
+        }
+    }
+
+    /// Sends any partly filled packet
+    pub fn flush(&mut self) {
+        if let Some(packet) = self.packetizer.flush() {
+            self.broadcast(packet);
+        }
+    }
+
+    fn broadcast(&self, packet: Vec<u8>) {
+        let packet = Arc::new(packet);
+        let mut clients = self.shared.clients.lock().unwrap();
+        clients.retain(|client| match client.packets.try_send(packet.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+impl Drop for TcpServer {
+    fn drop(&mut self) {
+        self.flush();
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let _ = accept_thread.join();
+        }
+    }
+}
+
+fn accept_clients(listener: TcpListener, shared: Arc<Shared>) {
+    let mut client_threads = Vec::new();
+    while !shared.shutdown.load(Ordering::Relaxed) {
+        let (stream, addr) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                warn!(target: TCP_TAG, "Error accepting connection: {}", e);
+                continue;
+            }
+        };
+
+        let (packets, receiver) = mpsc::sync_channel(CLIENT_QUEUE_LEN);
+        shared
+            .clients
+            .lock()
+            .unwrap()
+            .push(ClientHandle { packets });
+        let shared = shared.clone();
+        client_threads.push(std::thread::spawn(move || {
+            info!(target: TCP_TAG, "Client {} connected", addr);
+            match serve_client(stream, &shared, receiver) {
+                Ok(()) => info!(target: TCP_TAG, "Client {} disconnected", addr),
+                Err(e) => info!(target: TCP_TAG, "Client {} disconnected: {}", addr, e),
+            }
+        }));
+    }
+
+    // let the clients finish sending what they have
+    for client_thread in client_threads {
+        let _ = client_thread.join();
+    }
+}
+
+fn serve_client(
+    mut stream: TcpStream,
+    shared: &Shared,
+    packets: Receiver<Arc<Vec<u8>>>,
+) -> NetResult<()> {
+    // on some platforms the stream inherits the listener's non-blocking mode
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    stream.write_all(&shared.header_packet)?;
+
+    loop {
+        match packets.recv_timeout(POLL_INTERVAL) {
+            Ok(packet) => stream.write_all(&packet)?,
+            Err(RecvTimeoutError::Timeout) => {
+                if shared.shutdown.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
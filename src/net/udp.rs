@@ -0,0 +1,151 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! UDP output to a unicast, broadcast or multicast address.  Nothing is resent, so receivers
+//! should watch the packet sequence numbers for loss.
+
+use log::warn;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::packet::{PacketOptions, Packetizer};
+use super::{NetError, NetResult};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+
+const UDP_TAG: &str = "udp";
+
+// how often the header packet is repeated for receivers that join late
+const HEADER_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct UdpSender {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    packetizer: Packetizer,
+    header_packet: Vec<u8>,
+    header_sent: Option<Instant>,
+    errors: u64,
+}
+
+impl UdpSender {
+    /// `ttl` limits how many hops multicast packets travel; 1 keeps them on the local network
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        config: &BoardConfig,
+        options: &PacketOptions,
+        ttl: u32,
+    ) -> NetResult<Self> {
+        let (socket, destination) = open_socket(addr, ttl)?;
+
+        let packetizer = Packetizer::new(config, options);
+        let header_packet = packetizer.header_packet();
+        Ok(Self {
+            socket,
+            destination,
+            packetizer,
+            header_packet,
+            header_sent: None,
+            errors: 0,
+        })
+    }
+
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// Packets that couldn't be sent
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn send(&mut self, sample: &Sample, host_time: f64) {
+        let header_due = match self.header_sent {
+            Some(sent) => sent.elapsed() >= HEADER_INTERVAL,
+            None => true,
+        };
+        if header_due {
+            self.header_sent = Some(Instant::now());
+            let header_packet = std::mem::take(&mut self.header_packet);
+            self.send_packet(&header_packet);
+            self.header_packet = header_packet;
+        }
+
+        if let Some(packet) = self.packetizer.push(sample, host_time) {
+            self.send_packet(&packet);
+        }
+    }
+
+    /// Sends any partly filled packet
+    pub fn flush(&mut self) {
+        if let Some(packet) = self.packetizer.flush() {
+            self.send_packet(&packet);
+        }
+    }
+
+    fn send_packet(&mut self, packet: &[u8]) {
+        match self.socket.send_to(packet, self.destination) {
+            Ok(_) => {}
+            // the socket buffer is full; the packet is lost, as it could be on the wire
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => self.errors += 1,
+            Err(e) => {
+                // don't flood the log if the destination stays unreachable
+                if self.errors == 0 {
+                    warn!(target: UDP_TAG, "Error sending to {}: {}", self.destination, e);
+                }
+                self.errors += 1;
+            }
+        }
+    }
+}
+
+/// Opens a non-blocking socket for sending to `addr`, set up for multicast or broadcast if
+/// that's what the address is
+pub(super) fn open_socket<A: ToSocketAddrs>(
+    addr: A,
+    ttl: u32,
+) -> NetResult<(UdpSocket, SocketAddr)> {
+    let destination = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| NetError::BadOption("UDP address didn't resolve to anything".to_string()))?;
+
+    let socket = match destination.ip() {
+        IpAddr::V4(ip) => {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            if ip.is_multicast() {
+                socket.set_multicast_ttl_v4(ttl)?;
+                socket.set_multicast_loop_v4(true)?;
+            } else if ip.is_broadcast() {
+                socket.set_broadcast(true)?;
+            }
+            socket
+        }
+        IpAddr::V6(ip) => {
+            let socket = UdpSocket::bind("[::]:0")?;
+            if ip.is_multicast() {
+                socket.set_multicast_loop_v6(true)?;
+            }
+            socket
+        }
+    };
+    socket.set_nonblocking(true)?;
+    Ok((socket, destination))
+}
+
+impl Drop for UdpSender {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,472 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WebSocket server for browser dashboards.
+//!
+//! Each client is sent a `hello` text message describing the board and how samples will be sent,
+//! then a message per sample: a JSON text message, or a binary message holding one record in the
+//! layout described in `net`.  Clients can send JSON text messages to control the session:
+//!
+//! ```text
+//! {"command": "start"}        resume acquisition, answered with {"type": "ack", ...}
+//! {"command": "stop"}         pause acquisition
+//! {"command": "stats"}        answered with {"type": "stats", ...}
+//! {"command": "configure", "format": "binary", "units": "microvolts", "decimate": 4}
+//!                             change how this client is sent samples; every field is optional,
+//!                             answered with {"type": "options", ...}
+//! ```
+//!
+//! Errors are answered with `{"type": "error", "message": ...}`.  A client that can't keep up
+//! has samples dropped rather than slowing acquisition; `stats` reports how many.  Replies are
+//! never dropped: while one waits for room, samples are dropped in its place.
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+use super::{
+    encode_binary, encode_json, AcquisitionStats, Command, FrameFormat, NetResult, StreamOptions,
+    BINARY_SAMPLE_LEN,
+};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+use crate::export::Units;
+
+const WEBSOCKET_TAG: &str = "websocket";
+
+// how long client and accept threads wait before checking for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// samples waiting for a client's thread, and messages waiting for its socket
+const CLIENT_QUEUE_LEN: usize = 4096;
+const SEND_QUEUE_LEN: usize = 4096;
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Request {
+    Start,
+    Stop,
+    Stats,
+    Configure {
+        format: Option<FrameFormat>,
+        units: Option<Units>,
+        decimate: Option<u32>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Reply<'a> {
+    Hello {
+        board: &'a BoardConfig,
+        options: &'a StreamOptions,
+        acquiring: bool,
+    },
+    Options {
+        options: &'a StreamOptions,
+    },
+    Ack {
+        command: &'static str,
+    },
+    Stats {
+        acquiring: bool,
+        samples: u64,
+        errors: u64,
+        elapsed: f64,
+        samples_per_second: f64,
+        clients: usize,
+        /// samples this client missed because it fell behind
+        dropped: u64,
+    },
+    Error {
+        message: String,
+    },
+}
+
+struct ClientHandle {
+    samples: SyncSender<(Sample, f64)>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// State shared by the server and its threads
+struct Shared {
+    config: BoardConfig,
+    defaults: StreamOptions,
+    stats: AcquisitionStats,
+    clients: Mutex<Vec<ClientHandle>>,
+    shutdown: AtomicBool,
+}
+
+pub struct WebSocketServer {
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+    // behind a mutex so the server can be shared with a pipeline sink's thread
+    commands: Mutex<Receiver<Command>>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl WebSocketServer {
+    /// Listens on `addr`, sending samples to clients with `defaults` until they configure
+    /// otherwise
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        config: &BoardConfig,
+        defaults: &StreamOptions,
+    ) -> NetResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let shared = Arc::new(Shared {
+            config: config.clone(),
+            defaults: defaults.clone(),
+            stats: AcquisitionStats::default(),
+            clients: Mutex::new(Vec::new()),
+            shutdown: AtomicBool::new(false),
+        });
+        let (command_sender, commands) = mpsc::channel();
+        let accept_thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || accept_clients(listener, shared, command_sender))
+        };
+
+        Ok(Self {
+            local_addr,
+            shared,
+            commands: Mutex::new(commands),
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The counters reported to clients; the acquisition loop records errors and pauses here
+    pub fn stats(&self) -> &AcquisitionStats {
+        &self.shared.stats
+    }
+
+    pub fn clients(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+
+    /// Returns the next start or stop request from a client, if there is one
+    pub fn poll_command(&self) -> Option<Command> {
+        self.commands.lock().unwrap().try_recv().ok()
+    }
+
+    /// Queues a sample for every connected client
+    pub fn send(&self, sample: &Sample, host_time: f64) {
+        self.shared.stats.add_sample();
+        let mut clients = self.shared.clients.lock().unwrap();
+        clients.retain(
+            |client| match client.samples.try_send((sample.clone(), host_time)) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    client.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                // the client's thread has finished
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        );
+    }
+}
+
+impl Drop for WebSocketServer {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let _ = accept_thread.join();
+        }
+    }
+}
+
+fn accept_clients(listener: TcpListener, shared: Arc<Shared>, commands: Sender<Command>) {
+    let mut client_threads = Vec::new();
+    while !shared.shutdown.load(Ordering::Relaxed) {
+        let (stream, addr) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                warn!(target: WEBSOCKET_TAG, "Error accepting connection: {}", e);
+                continue;
+            }
+        };
+
+        let (samples, receiver) = mpsc::sync_channel(CLIENT_QUEUE_LEN);
+        let dropped = Arc::new(AtomicU64::new(0));
+        shared.clients.lock().unwrap().push(ClientHandle {
+            samples,
+            dropped: dropped.clone(),
+        });
+
+        let client = Client {
+            shared: shared.clone(),
+            commands: commands.clone(),
+            samples: receiver,
+            dropped,
+            options: shared.defaults.clone(),
+            skipped: 0,
+            replies: VecDeque::new(),
+        };
+        client_threads.push(std::thread::spawn(move || {
+            info!(target: WEBSOCKET_TAG, "Client {} connected", addr);
+            match client.serve(stream) {
+                Ok(()) => info!(target: WEBSOCKET_TAG, "Client {} disconnected", addr),
+                Err(e) => warn!(target: WEBSOCKET_TAG, "Client {} failed: {}", addr, e),
+            }
+        }));
+    }
+
+    // let clients see the close frames
+    for client_thread in client_threads {
+        let _ = client_thread.join();
+    }
+}
+
+/// One connection, served on its own thread
+struct Client {
+    shared: Arc<Shared>,
+    commands: Sender<Command>,
+    samples: Receiver<(Sample, f64)>,
+    dropped: Arc<AtomicU64>,
+    options: StreamOptions,
+    // samples skipped since the last one sent, for decimation
+    skipped: u32,
+    // replies the socket's send queue had no room for yet
+    replies: VecDeque<Message>,
+}
+
+impl Client {
+    fn serve(mut self, stream: TcpStream) -> NetResult<()> {
+        // on some platforms the stream inherits the listener's non-blocking mode
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let config = WebSocketConfig {
+            max_send_queue: Some(SEND_QUEUE_LEN),
+            ..WebSocketConfig::default()
+        };
+        let mut ws =
+            tungstenite::server::accept_with_config(stream, Some(config)).map_err(|e| {
+                std::io::Error::new(ErrorKind::InvalidData, format!("handshake failed: {}", e))
+            })?;
+        ws.get_mut().set_read_timeout(None)?;
+        ws.get_mut().set_nonblocking(true)?;
+
+        let shared = self.shared.clone();
+        let options = self.options.clone();
+        let hello = Reply::Hello {
+            board: &shared.config,
+            options: &options,
+            acquiring: shared.stats.acquiring(),
+        };
+        self.send_reply(&mut ws, &hello)?;
+
+        loop {
+            if self.shared.shutdown.load(Ordering::Relaxed) {
+                let _ = ws.close(None);
+                let _ = ws.write_pending();
+                return Ok(());
+            }
+
+            match self.samples.recv_timeout(POLL_INTERVAL) {
+                Ok((sample, host_time)) => {
+                    self.send_sample(&mut ws, &sample, host_time)?;
+                    while let Ok((sample, host_time)) = self.samples.try_recv() {
+                        self.send_sample(&mut ws, &sample, host_time)?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            loop {
+                match ws.read_message() {
+                    Ok(Message::Text(text)) => self.handle_request(&mut ws, &text)?,
+                    Ok(Message::Close(_)) => {
+                        let _ = ws.write_pending();
+                        return Ok(());
+                    }
+                    Ok(_) => {}
+                    Err(tungstenite::Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => {
+                        break
+                    }
+                    Err(tungstenite::Error::ConnectionClosed)
+                    | Err(tungstenite::Error::AlreadyClosed) => return Ok(()),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            self.write_replies(&mut ws)?;
+            match ws.write_pending() {
+                Err(tungstenite::Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                result => result?,
+            }
+        }
+    }
+
+    /// Queues a message, treating a full socket as success since the message stays queued.
+    /// Returns the message if the send queue is full.
+    fn write(ws: &mut WebSocket<TcpStream>, message: Message) -> NetResult<Option<Message>> {
+        match ws.write_message(message) {
+            Ok(()) => Ok(None),
+            Err(tungstenite::Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(tungstenite::Error::SendQueueFull(message)) => Ok(Some(message)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Queues the replies waiting for room, in order, until the send queue is full
+    fn write_replies(&mut self, ws: &mut WebSocket<TcpStream>) -> NetResult<()> {
+        while let Some(message) = self.replies.pop_front() {
+            if let Some(message) = Self::write(ws, message)? {
+                self.replies.push_front(message);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_reply(&mut self, ws: &mut WebSocket<TcpStream>, reply: &Reply) -> NetResult<()> {
+        // replies are plain data and always serialize
+        let text = serde_json::to_string(reply).unwrap_or_default();
+        self.replies.push_back(Message::Text(text));
+        self.write_replies(ws)
+    }
+
+    fn send_sample(
+        &mut self,
+        ws: &mut WebSocket<TcpStream>,
+        sample: &Sample,
+        host_time: f64,
+    ) -> NetResult<()> {
+        if self.skipped + 1 < self.options.decimate {
+            self.skipped += 1;
+            return Ok(());
+        }
+        self.skipped = 0;
+
+        // replies go first, so a sample that would be queued ahead of one is dropped instead
+        self.write_replies(ws)?;
+        if !self.replies.is_empty() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let message = match self.options.format {
+            FrameFormat::Json => Message::Text(encode_json(
+                sample,
+                host_time,
+                self.options.units,
+                &self.shared.config,
+            )),
+            FrameFormat::Binary => {
+                let mut record = Vec::with_capacity(BINARY_SAMPLE_LEN);
+                encode_binary(
+                    &mut record,
+                    sample,
+                    host_time,
+                    self.options.units,
+                    &self.shared.config,
+                );
+                Message::Binary(record)
+            }
+        };
+        if Self::write(ws, message)?.is_some() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Passes a command to the acquisition loop
+    fn forward(
+        &mut self,
+        ws: &mut WebSocket<TcpStream>,
+        command: Command,
+        name: &'static str,
+    ) -> NetResult<()> {
+        let reply = match self.commands.send(command) {
+            Ok(()) => Reply::Ack { command: name },
+            Err(_) => Reply::Error {
+                message: "Acquisition has finished".to_string(),
+            },
+        };
+        self.send_reply(ws, &reply)
+    }
+
+    fn handle_request(&mut self, ws: &mut WebSocket<TcpStream>, text: &str) -> NetResult<()> {
+        let request = match serde_json::from_str::<Request>(text) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!(target: WEBSOCKET_TAG, "Bad request {:?}: {}", text, e);
+                let reply = Reply::Error {
+                    message: format!("Bad request: {}", e),
+                };
+                return self.send_reply(ws, &reply);
+            }
+        };
+
+        match request {
+            Request::Start => self.forward(ws, Command::Start, "start"),
+            Request::Stop => self.forward(ws, Command::Stop, "stop"),
+            Request::Stats => {
+                let stats = &self.shared.stats;
+                let elapsed = stats.elapsed();
+                let reply = Reply::Stats {
+                    acquiring: stats.acquiring(),
+                    samples: stats.samples(),
+                    errors: stats.errors(),
+                    elapsed,
+                    samples_per_second: stats.samples() as f64 / elapsed,
+                    clients: self.shared.clients.lock().unwrap().len(),
+                    dropped: self.dropped.load(Ordering::Relaxed),
+                };
+                self.send_reply(ws, &reply)
+            }
+            Request::Configure {
+                format,
+                units,
+                decimate,
+            } => {
+                if let Some(format) = format {
+                    self.options.format = format;
+                }
+                if let Some(units) = units {
+                    self.options.units = units;
+                }
+                if let Some(decimate) = decimate {
+                    self.options.decimate = decimate.max(1);
+                }
+                let options = self.options.clone();
+                self.send_reply(ws, &Reply::Options { options: &options })
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(Debug)]
+pub enum OpenBciError {
+    IOError(std::io::Error),
+    #[cfg(unix)]
+    PtyError(nix::Error),
+}
+
+impl From<std::io::Error> for OpenBciError {
+    fn from(e: std::io::Error) -> Self {
+        OpenBciError::IOError(e)
+    }
+}
+
+#[cfg(unix)]
+impl From<nix::Error> for OpenBciError {
+    fn from(e: nix::Error) -> Self {
+        OpenBciError::PtyError(e)
+    }
+}
+
+impl std::error::Error for OpenBciError {}
+
+impl std::fmt::Display for OpenBciError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            OpenBciError::IOError(e) => write!(f, "I/O error: {}", e),
+            #[cfg(unix)]
+            OpenBciError::PtyError(e) => write!(f, "Pseudo-terminal error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenBCI Cyton compatibility, so software written for OpenBCI boards can read HackEEG data.
+//!
+//! Samples are re-encoded as Cyton binary packets, see
+//! https://docs.openbci.com/Cyton/CytonDataFormat/:
+//!
+//! ```text
+//! 0xA0         header
+//! sample       u8, the low byte of the HackEEG sample number
+//! channels     8 x 24-bit big-endian two's complement counts
+//! aux          6 bytes, three i16 accelerometer axes, always zero
+//! 0xC0         footer, "standard with accel"
+//! ```
+//!
+//! Cyton software converts counts to microvolts assuming a gain of 24, so counts taken at other
+//! gains are rescaled to what the Cyton would have read.  The HackEEG uses the same ADS1299 and
+//! reference voltage, so at gain 24 they're unchanged.  On Unix, `PtyBridge` serves the packets on
+//! a pseudo-terminal that behaves enough like a Cyton's serial port for the OpenBCI GUI and
+//! BrainFlow to connect to it.
+
+use crate::client::sample::Sample;
+use crate::common::config::{BoardConfig, DIGITAL_MAX, DIGITAL_MIN};
+use crate::common::constants::NUM_CHANNELS;
+
+mod err;
+#[cfg(unix)]
+mod pty;
+
+pub use err::OpenBciError;
+#[cfg(unix)]
+pub use pty::PtyBridge;
+
+pub const PACKET_LEN: usize = 33;
+pub const PACKET_HEADER: u8 = 0xA0;
+pub const PACKET_FOOTER: u8 = 0xC0;
+
+/// The gain the OpenBCI software assumes for every channel
+pub const CYTON_GAIN: u32 = 24;
+
+/// The sample rate Cyton software expects unless the board is told otherwise
+pub const CYTON_SAMPLE_RATE: u32 = 250;
+
+/// Firmware version reported to clients
+pub const FIRMWARE_VERSION: &str = "v3.1.2";
+
+/// Ends every text response from the Cyton firmware
+pub const EOT: &str = "$$$";
+
+pub type OpenBciResult<T> = Result<T, OpenBciError>;
+
+/// Converts samples into Cyton packets for a fixed board configuration
+#[derive(Clone, Debug)]
+pub struct PacketEncoder {
+    gains: Vec<u32>,
+}
+
+impl PacketEncoder {
+    pub fn new(config: &BoardConfig) -> Self {
+        Self {
+            gains: config.channels.iter().map(|channel| channel.gain).collect(),
+        }
+    }
+
+    /// Rescales a count taken at `gain` to the count a Cyton would report at `CYTON_GAIN`
+    fn rescale(counts: i32, gain: u32) -> i32 {
+        if gain == CYTON_GAIN {
+            return counts;
+        }
+        let cyton_counts = counts as i64 * CYTON_GAIN as i64 / gain as i64;
+        cyton_counts.max(DIGITAL_MIN as i64).min(DIGITAL_MAX as i64) as i32
+    }
+
+    pub fn encode(&self, sample: &Sample) -> [u8; PACKET_LEN] {
+        let mut packet = [0u8; PACKET_LEN];
+        packet[0] = PACKET_HEADER;
+        packet[1] = sample.sample_number as u8;
+        for chan_idx in 0..NUM_CHANNELS {
+            let counts = Self::rescale(sample.channels[chan_idx].sample, self.gains[chan_idx]);
+            let start = 2 + chan_idx * 3;
+            packet[start..start + 3].copy_from_slice(&counts.to_be_bytes()[1..4]);
+        }
+        packet[PACKET_LEN - 1] = PACKET_FOOTER;
+        packet
+    }
+}
+
+/// The reply to a soft reset (`v`), which clients wait for before streaming
+pub fn reset_banner() -> String {
+    format!(
+        "OpenBCI V3 8-16 channel\nOn Board ADS1299 Device ID: 0x3E\nLIS3DH Device ID: 0x33\nFirmware: {}\n{}",
+        FIRMWARE_VERSION, EOT
+    )
+}
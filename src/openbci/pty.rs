@@ -0,0 +1,356 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use log::{debug, info, warn};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::pty::{grantpt, posix_openpt, unlockpt, PtyMaster};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use super::{reset_banner, OpenBciResult, PacketEncoder, EOT, FIRMWARE_VERSION};
+use crate::client::sample::Sample;
+use crate::common::config::BoardConfig;
+
+const OPENBCI_TAG: &str = "openbci";
+
+// how often the command thread checks whether it should stop
+const POLL_TIMEOUT_MS: i32 = 100;
+
+// the reply to `D`, a Cyton's default channel settings
+const DEFAULT_CHANNEL_SETTINGS: &str = "060110";
+
+/// Flags shared between the acquisition loop and the command thread
+#[derive(Default)]
+struct BridgeState {
+    streaming: AtomicBool,
+    shutdown: AtomicBool,
+}
+
+/// Writes to the master side.  The command thread and `send` share it, so a reply can never land
+/// in the middle of a packet.
+struct Output {
+    master: Arc<PtyMaster>,
+    // bytes that have to go out before anything else: the unwritten end of a packet, and any
+    // replies queued behind it
+    backlog: Vec<u8>,
+}
+
+impl Output {
+    /// Writes as much of `data` as the terminal will take, returning how much that was
+    fn write_some(&self, data: &[u8]) -> nix::Result<usize> {
+        match nix::unistd::write(self.master.as_raw_fd(), data) {
+            Ok(len) => Ok(len),
+            Err(Errno::EAGAIN) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes what it can of the backlog, returning whether all of it has gone
+    fn flush(&mut self) -> nix::Result<bool> {
+        if !self.backlog.is_empty() {
+            let written = self.write_some(&self.backlog)?;
+            self.backlog.drain(..written);
+        }
+        Ok(self.backlog.is_empty())
+    }
+
+    /// Writes `data` after the backlog, keeping whatever doesn't fit to write later
+    fn write_queued(&mut self, data: &[u8]) -> nix::Result<()> {
+        let written = if self.flush()? {
+            self.write_some(data)?
+        } else {
+            0
+        };
+        self.backlog.extend_from_slice(&data[written..]);
+        Ok(())
+    }
+}
+
+/// Splits the bytes a client sends into Cyton commands and answers them.
+///
+/// Only streaming control and identification are acted on; the board is configured by
+/// `hackeeg stream`, so requests to change channel settings or the sample rate are refused.
+struct CommandHandler {
+    state: Arc<BridgeState>,
+    sample_rate: u32,
+    // a multi-byte command being collected: `x...X`, `z...Z` or `~?`
+    pending: Vec<u8>,
+}
+
+impl CommandHandler {
+    /// Returns the text to send back, if the command has a reply
+    fn handle_byte(&mut self, byte: u8) -> Option<String> {
+        if let Some(&first) = self.pending.first() {
+            self.pending.push(byte);
+            return match (first, byte) {
+                (b'x', b'X') | (b'z', b'Z') | (b'~', _) => {
+                    let command = std::mem::take(&mut self.pending);
+                    Some(self.handle_command(&command))
+                }
+                _ => None,
+            };
+        }
+
+        match byte {
+            b'x' | b'z' | b'~' => {
+                self.pending.push(byte);
+                None
+            }
+            b'\r' | b'\n' => None,
+            _ => match self.handle_simple(byte) {
+                Some(reply) => Some(reply),
+                None => {
+                    debug!(target: OPENBCI_TAG, "Ignoring command {:?}", byte as char);
+                    None
+                }
+            },
+        }
+    }
+
+    fn handle_simple(&mut self, byte: u8) -> Option<String> {
+        match byte {
+            b'b' => {
+                info!(target: OPENBCI_TAG, "Client started streaming");
+                self.state.streaming.store(true, Ordering::Relaxed);
+                None
+            }
+            b's' => {
+                info!(target: OPENBCI_TAG, "Client stopped streaming");
+                self.state.streaming.store(false, Ordering::Relaxed);
+                None
+            }
+            b'v' => {
+                self.state.streaming.store(false, Ordering::Relaxed);
+                Some(reset_banner())
+            }
+            b'V' => Some(format!("{}{}", FIRMWARE_VERSION, EOT)),
+            b'd' => Some(format!("updating channel settings to default{}", EOT)),
+            b'D' => Some(format!("{}{}", DEFAULT_CHANNEL_SETTINGS, EOT)),
+            b'?' => Some(EOT.to_string()),
+            _ => None,
+        }
+    }
+
+    fn handle_command(&mut self, command: &[u8]) -> String {
+        match command {
+            [b'~', b'~'] => format!("Success: Sample rate is {}Hz{}", self.sample_rate, EOT),
+            [b'~', _] => {
+                warn!(target: OPENBCI_TAG, "Refusing to change the sample rate");
+                format!(
+                    "Failure: sample rate is {}Hz, set by hackeeg stream{}",
+                    self.sample_rate, EOT
+                )
+            }
+            _ => {
+                warn!(
+                    target: OPENBCI_TAG,
+                    "Refusing to change channel settings: {}",
+                    String::from_utf8_lossy(command)
+                );
+                format!("Failure: channels are configured by hackeeg stream{}", EOT)
+            }
+        }
+    }
+}
+
+/// Serves Cyton packets on a pseudo-terminal.
+///
+/// A thread answers the commands clients send, and samples passed to `send` are written once a
+/// client has sent `b`.  The master side never blocks: if no client is reading, packets that
+/// don't fit in the terminal's buffer are dropped rather than holding up acquisition.  Replies
+/// are never dropped, but wait for any packet being written to finish.
+pub struct PtyBridge {
+    output: Arc<Mutex<Output>>,
+    // held open so the master doesn't see a hangup while no client is connected
+    _slave: File,
+    slave_name: String,
+    link: Option<PathBuf>,
+    encoder: PacketEncoder,
+    state: Arc<BridgeState>,
+    handler_thread: Option<JoinHandle<()>>,
+    dropped: u64,
+}
+
+impl PtyBridge {
+    /// Opens the pseudo-terminal, and if `link` is given, creates a symlink to it there, e.g. a
+    /// stable path to configure clients with
+    pub fn open<P: AsRef<Path>>(config: &BoardConfig, link: Option<P>) -> OpenBciResult<Self> {
+        let master = posix_openpt(OFlag::O_RDWR | OFlag::O_NOCTTY)?;
+        grantpt(&master)?;
+        unlockpt(&master)?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let slave_name = nix::pty::ptsname_r(&master)?;
+        // ptsname isn't thread safe, but no other thread opens pseudo-terminals
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let slave_name = unsafe { nix::pty::ptsname(&master)? };
+
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&slave_name)?;
+        let mut termios = tcgetattr(slave.as_raw_fd())?;
+        cfmakeraw(&mut termios);
+        tcsetattr(slave.as_raw_fd(), SetArg::TCSANOW, &termios)?;
+        fcntl(master.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+
+        let link = match link {
+            Some(link) => {
+                let link = link.as_ref().to_path_buf();
+                // replace a link left behind by an earlier run, but never anything else
+                let is_symlink = link
+                    .symlink_metadata()
+                    .map(|metadata| metadata.file_type().is_symlink())
+                    .unwrap_or(false);
+                if is_symlink {
+                    std::fs::remove_file(&link)?;
+                }
+                std::os::unix::fs::symlink(&slave_name, &link)?;
+                Some(link)
+            }
+            None => None,
+        };
+
+        let master = Arc::new(master);
+        let state = Arc::new(BridgeState::default());
+        let handler = CommandHandler {
+            state: state.clone(),
+            sample_rate: config.sample_rate,
+            pending: Vec::new(),
+        };
+        let output = Arc::new(Mutex::new(Output {
+            master: master.clone(),
+            backlog: Vec::new(),
+        }));
+        let handler_thread = {
+            let output = output.clone();
+            let state = state.clone();
+            std::thread::spawn(move || Self::handle_commands(master, output, state, handler))
+        };
+
+        Ok(Self {
+            output,
+            _slave: slave,
+            slave_name,
+            link,
+            encoder: PacketEncoder::new(config),
+            state,
+            handler_thread: Some(handler_thread),
+            dropped: 0,
+        })
+    }
+
+    /// The path clients should open
+    pub fn path(&self) -> &str {
+        match self.link {
+            Some(ref link) => link.to_str().unwrap_or(&self.slave_name),
+            None => &self.slave_name,
+        }
+    }
+
+    /// True once a client has started streaming
+    pub fn streaming(&self) -> bool {
+        self.state.streaming.load(Ordering::Relaxed)
+    }
+
+    /// Packets dropped because no client was reading fast enough
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn handle_commands(
+        master: Arc<PtyMaster>,
+        output: Arc<Mutex<Output>>,
+        state: Arc<BridgeState>,
+        mut handler: CommandHandler,
+    ) {
+        let fd = master.as_raw_fd();
+        let mut buf = [0u8; 64];
+        while !state.shutdown.load(Ordering::Relaxed) {
+            // replies queued behind a packet go out once the client reads, even if no samples
+            // are arriving to send them along
+            if let Err(e) = output.lock().unwrap().flush() {
+                debug!(target: OPENBCI_TAG, "Couldn't send queued output: {}", e);
+            }
+
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            match poll(&mut fds, POLL_TIMEOUT_MS) {
+                Ok(0) | Err(Errno::EINTR) => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(target: OPENBCI_TAG, "Error waiting for commands: {}", e);
+                    return;
+                }
+            }
+
+            let len = match nix::unistd::read(fd, &mut buf) {
+                Ok(len) => len,
+                Err(Errno::EAGAIN) | Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    warn!(target: OPENBCI_TAG, "Error reading commands: {}", e);
+                    return;
+                }
+            };
+            for &byte in &buf[..len] {
+                if let Some(reply) = handler.handle_byte(byte) {
+                    if let Err(e) = output.lock().unwrap().write_queued(reply.as_bytes()) {
+                        warn!(target: OPENBCI_TAG, "Couldn't send reply: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a sample to the client, if it's streaming
+    pub fn send(&mut self, sample: &Sample) -> OpenBciResult<()> {
+        let mut output = self.output.lock().unwrap();
+        // the end of a packet still goes out after streaming stops, so the client never sees a
+        // truncated one
+        let flushed = output.flush()?;
+        if !self.streaming() {
+            return Ok(());
+        }
+        if !flushed {
+            self.dropped += 1;
+            return Ok(());
+        }
+
+        let packet = self.encoder.encode(sample);
+        let written = output.write_some(&packet)?;
+        if written == 0 {
+            self.dropped += 1;
+        } else {
+            output.backlog.extend_from_slice(&packet[written..]);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PtyBridge {
+    fn drop(&mut self) {
+        self.state.shutdown.store(true, Ordering::Relaxed);
+        if let Some(handler_thread) = self.handler_thread.take() {
+            let _ = handler_thread.join();
+        }
+        if let Some(ref link) = self.link {
+            let _ = std::fs::remove_file(link);
+        }
+    }
+}
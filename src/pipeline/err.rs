@@ -0,0 +1,35 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// An error from a sink, which has to cross from the sink's thread
+pub type SinkError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum PipelineError {
+    SourceError(Box<dyn std::error::Error>),
+    SinkError { sink: String, error: SinkError },
+    SinkPanicked(String),
+}
+
+impl std::error::Error for PipelineError {}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            PipelineError::SourceError(e) => write!(f, "Source error: {}", e),
+            PipelineError::SinkError { sink, error } => write!(f, "Error in {}: {}", sink, error),
+            PipelineError::SinkPanicked(sink) => write!(f, "The {} sink's thread panicked", sink),
+        }
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Connects a source of samples to any number of outputs.
+//!
+//! A `Pipeline` takes samples from a `Source` and passes them through its `Transform`s in order
+//! on the calling thread.  A `Sink` receives the samples as they leave the transforms added
+//! before it, so e.g. a file can be written at the full rate ahead of a decimator whose output
+//! goes to the network.  Each sink runs on its own thread behind a bounded queue, so a slow
+//! file system or terminal doesn't hold up the others; its `Overflow` policy decides what
+//! happens when it falls behind.
+
+use log::warn;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+mod err;
+mod queue;
+mod sinks;
+mod transforms;
+
+use crate::client::sample::Sample;
+pub use err::{PipelineError, SinkError};
+use queue::SinkQueue;
+#[cfg(feature = "lsl-sys")]
+pub use sinks::{BandPowerSink, LslSink};
+pub use sinks::{PrintSink, QualitySink, WriterSink};
+pub use transforms::ZeroPhaseFilter;
+
+pub type PipelineResult<T> = Result<T, PipelineError>;
+
+const PIPELINE_TAG: &str = "pipeline";
+
+/// A sample and the host time its frame was received, from `clock::local_clock()`
+#[derive(Clone)]
+pub struct TimedSample {
+    pub host_time: f64,
+    pub sample: Sample,
+    /// events noted about the stream up to this sample, such as detected artifacts
+    pub events: Vec<Event>,
+}
+
+impl TimedSample {
+    pub fn new(host_time: f64, sample: Sample) -> Self {
+        Self {
+            host_time,
+            sample,
+            events: Vec::new(),
+        }
+    }
+}
+
+/// Something that happened in the stream, carried to the sinks by a later sample
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// seconds from the carrying sample to the start of the event, negative when it's earlier
+    pub onset: f64,
+    pub duration: Option<f64>,
+    pub text: String,
+}
+
+/// Anything that can be part of a pipeline
+pub trait Stage {
+    /// A short name for log messages
+    fn name(&self) -> &str;
+}
+
+/// Produces samples, e.g. from the board or a recording
+pub trait Source: Stage {
+    /// The next sample, or `None` at the end of the stream
+    fn next(&mut self) -> Result<Option<TimedSample>, Box<dyn std::error::Error>>;
+}
+
+/// Changes samples on their way to the sinks, e.g. filtering or decimating them
+pub trait Transform: Stage + Send {
+    /// Processes one sample, appending whatever it produces to `out`, which may be nothing or
+    /// several samples.  The pipeline moves the input's events onto the first sample produced,
+    /// or a later one if there are none.
+    fn process(&mut self, input: TimedSample, out: &mut Vec<TimedSample>);
+
+    /// Emits anything still held back at the end of the stream
+    fn flush(&mut self, out: &mut Vec<TimedSample>) {
+        let _ = out;
+    }
+}
+
+/// Consumes samples on its own thread
+pub trait Sink: Stage + Send {
+    fn consume(&mut self, sample: &TimedSample) -> Result<(), SinkError>;
+
+    /// How long the sink can wait for a sample before `idle` is called, if it holds anything
+    /// that shouldn't wait until the next one, e.g. when acquisition pauses
+    fn idle_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Called when no sample has arrived within `idle_timeout`
+    fn idle(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Called once every sample has been consumed, e.g. to complete a file
+    fn finish(self: Box<Self>) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// What a sink's queue does when the sink falls behind
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Overflow {
+    /// wait for the sink, holding up the source; for outputs that must be complete
+    Block,
+    /// discard the sample that didn't fit
+    DropNewest,
+    /// discard the longest-waiting sample; for displays, which would rather be current
+    DropOldest,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SinkOptions {
+    /// samples the queue holds before overflowing
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 4096,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+/// How a sink fared over the session
+#[derive(Clone, Debug)]
+pub struct SinkReport {
+    pub name: String,
+    pub consumed: u64,
+    pub dropped: u64,
+}
+
+struct SinkHandle {
+    name: String,
+    // the number of transforms its samples have been through
+    stage: usize,
+    queue: Arc<SinkQueue>,
+    thread: JoinHandle<Result<u64, SinkError>>,
+}
+
+pub struct Pipeline {
+    transforms: Vec<Box<dyn Transform>>,
+    // per transform, events whose samples it held back or dropped, with the board timestamps
+    // of those samples
+    waiting_events: Vec<Vec<(u32, Event)>>,
+    sinks: Vec<SinkHandle>,
+    samples: u64,
+    // scratch space for transform output
+    batch: Vec<TimedSample>,
+    next_batch: Vec<TimedSample>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self {
+            transforms: Vec::new(),
+            waiting_events: Vec::new(),
+            sinks: Vec::new(),
+            samples: 0,
+            batch: Vec::new(),
+            next_batch: Vec::new(),
+        }
+    }
+
+    /// Adds a transform after the ones already added
+    pub fn add_transform<T: Transform + 'static>(&mut self, transform: T) {
+        self.transforms.push(Box::new(transform));
+        self.waiting_events.push(Vec::new());
+    }
+
+    /// Adds a sink, which receives the output of the transforms added so far, and starts its
+    /// thread
+    pub fn add_sink<S: Sink + 'static>(&mut self, sink: S, options: SinkOptions) {
+        let name = sink.name().to_string();
+        let queue = Arc::new(SinkQueue::new(options.capacity, options.overflow));
+        let thread = {
+            let queue = queue.clone();
+            std::thread::Builder::new()
+                .name(format!("sink-{}", name))
+                .spawn(move || run_sink(Box::new(sink), &queue))
+                .expect("couldn't spawn a sink thread")
+        };
+        self.sinks.push(SinkHandle {
+            name,
+            stage: self.transforms.len(),
+            queue,
+            thread,
+        });
+    }
+
+    /// Samples taken from the source so far
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Sends a sample through the transforms to the sinks.  Fails if a sink has failed, in
+    /// which case `finish` returns its error.
+    pub fn push(&mut self, sample: TimedSample) -> PipelineResult<()> {
+        self.samples += 1;
+        self.batch.push(sample);
+        self.advance(0)
+    }
+
+    /// Delivers the batch, which has been through `stage` transforms, and passes it through the
+    /// rest
+    fn advance(&mut self, mut stage: usize) -> PipelineResult<()> {
+        loop {
+            if let Err(e) = self.deliver(stage) {
+                self.batch.clear();
+                return Err(e);
+            }
+            let transform = match self.transforms.get_mut(stage) {
+                Some(transform) => transform,
+                None => break,
+            };
+            let waiting = &mut self.waiting_events[stage];
+            for mut sample in self.batch.drain(..) {
+                let timestamp = sample.sample.timestamp;
+                waiting.extend(sample.events.drain(..).map(|event| (timestamp, event)));
+                let produced = self.next_batch.len();
+                transform.process(sample, &mut self.next_batch);
+                if let Some(first) = self.next_batch.get_mut(produced) {
+                    carry_events(waiting, first);
+                }
+            }
+            std::mem::swap(&mut self.batch, &mut self.next_batch);
+            stage += 1;
+        }
+        self.batch.clear();
+        Ok(())
+    }
+
+    fn deliver(&self, stage: usize) -> PipelineResult<()> {
+        for sample in &self.batch {
+            for sink in self.sinks.iter().filter(|sink| sink.stage == stage) {
+                if !sink.queue.push(sample.clone()) {
+                    return Err(PipelineError::SinkError {
+                        sink: sink.name.clone(),
+                        error: "the sink stopped".into(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes samples from `source` until it ends or a sink fails, then finishes the sinks
+    pub fn run<S: Source + ?Sized>(mut self, source: &mut S) -> PipelineResult<Vec<SinkReport>> {
+        loop {
+            let sample = match source.next() {
+                Ok(Some(sample)) => sample,
+                Ok(None) => break,
+                Err(e) => {
+                    // the sinks still get to finish with what they have
+                    let _ = self.finish();
+                    return Err(PipelineError::SourceError(e));
+                }
+            };
+            if self.push(sample).is_err() {
+                break;
+            }
+        }
+        self.finish()
+    }
+
+    /// Flushes the transforms, waits for every sink to consume what's queued and finish, and
+    /// reports on them.  Returns the first error a sink had, if any.
+    pub fn finish(mut self) -> PipelineResult<Vec<SinkReport>> {
+        // what each transform holds back still goes through the ones after it
+        for index in 0..self.transforms.len() {
+            self.transforms[index].flush(&mut self.batch);
+            if let Some(first) = self.batch.first_mut() {
+                carry_events(&mut self.waiting_events[index], first);
+            }
+            // a failed sink shows up below
+            let _ = self.advance(index + 1);
+        }
+
+        for sink in &self.sinks {
+            sink.queue.close();
+        }
+        let mut reports = Vec::new();
+        let mut first_error = None;
+        for sink in self.sinks.drain(..) {
+            let dropped = sink.queue.dropped();
+            match sink.thread.join() {
+                Ok(Ok(consumed)) => reports.push(SinkReport {
+                    name: sink.name,
+                    consumed,
+                    dropped,
+                }),
+                Ok(Err(error)) => {
+                    first_error.get_or_insert(PipelineError::SinkError {
+                        sink: sink.name,
+                        error,
+                    });
+                }
+                Err(_) => {
+                    first_error.get_or_insert(PipelineError::SinkPanicked(sink.name));
+                }
+            }
+        }
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(reports),
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        // only reached without `finish`, e.g. when unwinding; don't leave the threads waiting
+        for sink in &self.sinks {
+            sink.queue.close();
+        }
+        for sink in self.sinks.drain(..) {
+            let _ = sink.thread.join();
+        }
+    }
+}
+
+/// Moves events onto `sample`, shifting their onsets by the board time between the samples that
+/// carried them and it
+fn carry_events(waiting: &mut Vec<(u32, Event)>, sample: &mut TimedSample) {
+    let timestamp = sample.sample.timestamp;
+    for (carried_at, mut event) in waiting.drain(..) {
+        // the difference is signed, so it survives the counter wrapping
+        let micros = carried_at.wrapping_sub(timestamp) as i32;
+        event.onset += micros as f64 / 1e6;
+        sample.events.push(event);
+    }
+}
+
+fn run_sink(mut sink: Box<dyn Sink>, queue: &SinkQueue) -> Result<u64, SinkError> {
+    let mut consumed = 0;
+    let mut batch = Vec::new();
+    while queue.take_all(&mut batch, sink.idle_timeout()) {
+        let result = if batch.is_empty() {
+            sink.idle()
+        } else {
+            batch.drain(..).try_for_each(|sample| {
+                sink.consume(&sample)?;
+                consumed += 1;
+                Ok(())
+            })
+        };
+        if let Err(e) = result {
+            warn!(target: PIPELINE_TAG, "{} failed: {}", sink.name(), e);
+            queue.fail();
+            return Err(e);
+        }
+    }
+    sink.finish()?;
+    Ok(consumed)
+}
@@ -0,0 +1,134 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The bounded queue between the acquisition thread and a sink's thread.  `std::sync::mpsc`
+//! can't drop the oldest item when full, so this is a `VecDeque` behind a mutex.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Overflow, TimedSample};
+
+struct State {
+    items: VecDeque<TimedSample>,
+    // no more samples are coming
+    closed: bool,
+    // the sink has stopped taking samples
+    failed: bool,
+    dropped: u64,
+}
+
+pub(super) struct SinkQueue {
+    capacity: usize,
+    overflow: Overflow,
+    state: Mutex<State>,
+    // signalled when samples arrive or the queue closes
+    filled: Condvar,
+    // signalled when samples are taken or the sink fails
+    emptied: Condvar,
+}
+
+impl SinkQueue {
+    pub fn new(capacity: usize, overflow: Overflow) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            overflow,
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+                failed: false,
+                dropped: 0,
+            }),
+            filled: Condvar::new(),
+            emptied: Condvar::new(),
+        }
+    }
+
+    /// Adds a sample, applying the overflow policy if the queue is full.  Returns false if the
+    /// sink has failed.
+    pub fn push(&self, sample: TimedSample) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.failed {
+            return false;
+        }
+        if state.items.len() >= self.capacity {
+            match self.overflow {
+                Overflow::Block => {
+                    while state.items.len() >= self.capacity && !state.failed {
+                        state = self.emptied.wait(state).unwrap();
+                    }
+                    if state.failed {
+                        return false;
+                    }
+                }
+                Overflow::DropNewest => {
+                    state.dropped += 1;
+                    return true;
+                }
+                Overflow::DropOldest => {
+                    state.items.pop_front();
+                    state.dropped += 1;
+                }
+            }
+        }
+        state.items.push_back(sample);
+        self.filled.notify_one();
+        true
+    }
+
+    /// Moves every queued sample into `batch`, waiting for one if there are none, for at most
+    /// `timeout` if it's given, after which `batch` is left empty.  Returns false once the queue
+    /// is closed and empty.
+    pub fn take_all(&self, batch: &mut Vec<TimedSample>, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        while state.items.is_empty() && !state.closed {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return true;
+                    }
+                    self.filled.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.filled.wait(state).unwrap(),
+            };
+        }
+        if state.items.is_empty() {
+            return false;
+        }
+        batch.extend(state.items.drain(..));
+        self.emptied.notify_one();
+        true
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.filled.notify_one();
+    }
+
+    /// Stops accepting samples, releasing a producer blocked on a full queue
+    pub fn fail(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failed = true;
+        state.items.clear();
+        self.emptied.notify_one();
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+}
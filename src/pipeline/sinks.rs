@@ -0,0 +1,429 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The outputs of the crate as pipeline sinks
+
+use log::warn;
+#[cfg(feature = "lsl-sys")]
+use log::{debug, info};
+use std::io::Write;
+use std::sync::Arc;
+#[cfg(feature = "lsl-sys")]
+use std::time::{Duration, Instant};
+
+use super::{Sink, SinkError, Stage, TimedSample, PIPELINE_TAG};
+#[cfg(feature = "lsl-sys")]
+use crate::clock::ClockSync;
+#[cfg(feature = "lsl-sys")]
+use crate::common::config::BoardConfig;
+#[cfg(feature = "lsl-sys")]
+use crate::dsp::{Band, Welch};
+use crate::export::{Annotation, SampleWriter};
+#[cfg(feature = "lsl-sys")]
+use crate::lsl::ChunkedOutlet;
+use crate::net::{OscSender, TcpServer, UdpSender, WebSocketServer};
+#[cfg(unix)]
+use crate::openbci::PtyBridge;
+use crate::quality::QualityMonitor;
+
+#[cfg(feature = "lsl-sys")]
+const JITTER_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+// a stream that's sent nothing for this long has paused or stalled, so a partial LSL chunk is
+// pushed even without a latency limit
+#[cfg(feature = "lsl-sys")]
+const LSL_IDLE_FLUSH: Duration = Duration::from_millis(100);
+
+/// Prints each sample's number, timestamp and channel counts on a line of stdout, followed by a
+/// line for each of its events
+#[derive(Default)]
+pub struct PrintSink;
+
+impl Stage for PrintSink {
+    fn name(&self) -> &str {
+        "terminal"
+    }
+}
+
+impl Sink for PrintSink {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        let sample = &timed.sample;
+        let ch = sample.channels;
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        writeln!(
+            out,
+            "{} @ {}: [{}, {}, {}, {}, {}, {}, {}, {}]",
+            sample.sample_number,
+            sample.timestamp,
+            ch[0].sample,
+            ch[1].sample,
+            ch[2].sample,
+            ch[3].sample,
+            ch[4].sample,
+            ch[5].sample,
+            ch[6].sample,
+            ch[7].sample
+        )?;
+        for event in &timed.events {
+            writeln!(out, "{} ({:+.3} s)", event.text, event.onset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints a signal quality report on stdout at the end of each of the monitor's windows
+pub struct QualitySink {
+    monitor: QualityMonitor,
+}
+
+impl QualitySink {
+    pub fn new(monitor: QualityMonitor) -> Self {
+        Self { monitor }
+    }
+}
+
+impl Stage for QualitySink {
+    fn name(&self) -> &str {
+        "quality"
+    }
+}
+
+impl Sink for QualitySink {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        if let Some(report) = self.monitor.push(&timed.sample) {
+            write!(std::io::stdout().lock(), "{}", report)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes samples to a file with a `SampleWriter`, and their events as annotations
+pub struct WriterSink {
+    writer: Box<dyn SampleWriter>,
+    sample_rate: f64,
+    written: u64,
+}
+
+impl WriterSink {
+    /// `sample_rate` is that of the samples it's given, which places their events in the file
+    pub fn new(writer: Box<dyn SampleWriter>, sample_rate: u32) -> Self {
+        Self {
+            writer,
+            sample_rate: sample_rate as f64,
+            written: 0,
+        }
+    }
+}
+
+impl Stage for WriterSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+}
+
+impl Sink for WriterSink {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.writer.write_sample(&timed.sample, timed.host_time)?;
+        let elapsed = self.written as f64 / self.sample_rate;
+        for event in &timed.events {
+            self.writer.annotate(Annotation {
+                // an event from before the file started is noted at its start
+                onset: (elapsed + event.onset).max(0.0),
+                duration: event.duration,
+                text: event.text.clone(),
+            })?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), SinkError> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Pushes samples to an LSL outlet, with timestamps from the board clock mapped onto the LSL
+/// clock, and reports how well the clocks agree.  Events go to a marker outlet, if it has one.
+#[cfg(feature = "lsl-sys")]
+pub struct LslSink {
+    outlet: ChunkedOutlet,
+    markers: Option<lsl_sys::Outlet<String>>,
+    clock_sync: ClockSync,
+    last_jitter_report: Instant,
+}
+
+#[cfg(feature = "lsl-sys")]
+impl LslSink {
+    pub fn new(outlet: ChunkedOutlet, clock_sync: ClockSync) -> Self {
+        Self {
+            outlet,
+            markers: None,
+            clock_sync,
+            last_jitter_report: Instant::now(),
+        }
+    }
+
+    /// Pushes the samples' events to `markers`, on the same clock as the samples
+    pub fn with_markers(mut self, markers: lsl_sys::Outlet<String>) -> Self {
+        self.markers = Some(markers);
+        self
+    }
+}
+
+#[cfg(feature = "lsl-sys")]
+impl Stage for LslSink {
+    fn name(&self) -> &str {
+        "lsl"
+    }
+}
+
+#[cfg(feature = "lsl-sys")]
+impl Sink for LslSink {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        let timestamp = self
+            .clock_sync
+            .update(timed.sample.timestamp, timed.host_time);
+        self.outlet.push(&timed.sample, timestamp);
+        if let Some(ref markers) = self.markers {
+            for event in &timed.events {
+                markers.push_sample(&event.text, timestamp + event.onset);
+            }
+        }
+
+        if self.last_jitter_report.elapsed() >= JITTER_REPORT_INTERVAL {
+            info!(
+                target: PIPELINE_TAG,
+                "Clock offset {:.6} s, drift {:.2} ppm, jitter {}",
+                self.clock_sync.offset(),
+                self.clock_sync.drift_ppm(),
+                self.clock_sync.take_jitter()
+            );
+            self.last_jitter_report = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn idle_timeout(&self) -> Option<Duration> {
+        if self.outlet.is_empty() {
+            return None;
+        }
+        Some(match self.outlet.due_in() {
+            Some(due_in) => due_in.min(LSL_IDLE_FLUSH),
+            None => LSL_IDLE_FLUSH,
+        })
+    }
+
+    fn idle(&mut self) -> Result<(), SinkError> {
+        self.outlet.flush();
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), SinkError> {
+        self.outlet.flush();
+        info!(
+            target: PIPELINE_TAG,
+            "Clock drift {:.2} ppm, session jitter {}",
+            self.clock_sync.drift_ppm(),
+            self.clock_sync.total_jitter()
+        );
+        Ok(())
+    }
+}
+
+/// Estimates each channel's band powers and pushes them to a second LSL outlet whenever a new
+/// estimate is ready, stamped with the host time of the latest sample
+#[cfg(feature = "lsl-sys")]
+pub struct BandPowerSink {
+    outlet: lsl_sys::Outlet<f32>,
+    config: BoardConfig,
+    bands: Vec<Band>,
+    welch: Welch,
+    microvolts: Vec<f64>,
+    powers: Vec<f64>,
+    values: Vec<f32>,
+}
+
+#[cfg(feature = "lsl-sys")]
+impl BandPowerSink {
+    pub fn new(
+        outlet: lsl_sys::Outlet<f32>,
+        config: &BoardConfig,
+        bands: Vec<Band>,
+        welch: Welch,
+    ) -> Self {
+        Self {
+            outlet,
+            config: config.clone(),
+            bands,
+            welch,
+            microvolts: Vec::new(),
+            powers: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "lsl-sys")]
+impl Stage for BandPowerSink {
+    fn name(&self) -> &str {
+        "band power"
+    }
+}
+
+#[cfg(feature = "lsl-sys")]
+impl Sink for BandPowerSink {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.microvolts.clear();
+        self.microvolts.extend(
+            timed
+                .sample
+                .channels
+                .iter()
+                .zip(&self.config.channels)
+                .map(|(channel, config)| config.to_microvolts(channel.sample)),
+        );
+        if !self.welch.push(&self.microvolts) {
+            return Ok(());
+        }
+
+        self.powers.clear();
+        self.welch.band_powers(&self.bands, &mut self.powers);
+        self.values.clear();
+        self.values
+            .extend(self.powers.iter().map(|power| *power as f32));
+        let result = self.outlet.push_chunk(&self.values, timed.host_time);
+        if result != 0 {
+            debug!(
+                target: PIPELINE_TAG,
+                "Pushing band powers returned {}", result
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Stage for Arc<WebSocketServer> {
+    fn name(&self) -> &str {
+        "websocket"
+    }
+}
+
+impl Sink for Arc<WebSocketServer> {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.send(&timed.sample, timed.host_time);
+        Ok(())
+    }
+}
+
+impl Stage for TcpServer {
+    fn name(&self) -> &str {
+        "tcp"
+    }
+}
+
+impl Sink for TcpServer {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.send(&timed.sample, timed.host_time);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), SinkError> {
+        self.flush();
+        if self.dropped() > 0 {
+            warn!(
+                target: PIPELINE_TAG,
+                "Dropped {} TCP packets for clients that fell behind",
+                self.dropped()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Stage for UdpSender {
+    fn name(&self) -> &str {
+        "udp"
+    }
+}
+
+impl Sink for UdpSender {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.send(&timed.sample, timed.host_time);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), SinkError> {
+        self.flush();
+        if self.errors() > 0 {
+            warn!(
+                target: PIPELINE_TAG,
+                "Failed to send {} UDP packets",
+                self.errors()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Stage for OscSender {
+    fn name(&self) -> &str {
+        "osc"
+    }
+}
+
+impl Sink for OscSender {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.send(&timed.sample);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), SinkError> {
+        self.flush();
+        if self.errors() > 0 {
+            warn!(
+                target: PIPELINE_TAG,
+                "Failed to send {} OSC packets",
+                self.errors()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Stage for PtyBridge {
+    fn name(&self) -> &str {
+        "openbci"
+    }
+}
+
+#[cfg(unix)]
+impl Sink for PtyBridge {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        self.send(&timed.sample)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), SinkError> {
+        if self.dropped() > 0 {
+            warn!(
+                target: PIPELINE_TAG,
+                "Dropped {} OpenBCI packets that no client read in time",
+                self.dropped()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The signal processing of the crate as pipeline transforms
+
+use log::info;
+
+use super::{Stage, TimedSample, Transform, PIPELINE_TAG};
+use crate::artifact::ArtifactDetector;
+use crate::client::sample::Sample;
+use crate::dsp::{self, DspResult, FilterChain, FilterSpec, Resampler};
+use crate::montage::Montage;
+
+impl Stage for FilterChain {
+    fn name(&self) -> &str {
+        "filter"
+    }
+}
+
+impl Transform for FilterChain {
+    fn process(&mut self, mut input: TimedSample, out: &mut Vec<TimedSample>) {
+        self.process_sample(&mut input.sample);
+        out.push(input);
+    }
+}
+
+/// Holds back the whole stream and, at its end, filters it forwards and backwards with
+/// `dsp::zero_phase_samples` so the filters don't shift it in time.  Nothing comes out until the
+/// source ends, so this is only for recordings.
+pub struct ZeroPhaseFilter {
+    specs: Vec<FilterSpec>,
+    sample_rate: u32,
+    description: String,
+    held: Vec<TimedSample>,
+}
+
+impl ZeroPhaseFilter {
+    pub fn new(specs: &[FilterSpec], sample_rate: u32) -> DspResult<Self> {
+        // designed here only to check the specs, so filtering at the end can't fail
+        let chain = FilterChain::new(specs, sample_rate, 1)?;
+        Ok(Self {
+            specs: specs.to_vec(),
+            sample_rate,
+            description: chain.description(),
+            held: Vec::new(),
+        })
+    }
+}
+
+impl Stage for ZeroPhaseFilter {
+    fn name(&self) -> &str {
+        "zero-phase filter"
+    }
+}
+
+impl Transform for ZeroPhaseFilter {
+    fn process(&mut self, input: TimedSample, _out: &mut Vec<TimedSample>) {
+        self.held.push(input);
+    }
+
+    fn flush(&mut self, out: &mut Vec<TimedSample>) {
+        let mut samples: Vec<Sample> = self.held.iter().map(|timed| timed.sample.clone()).collect();
+        info!(
+            target: PIPELINE_TAG,
+            "Filtering {} samples with {} forwards and backwards",
+            samples.len(),
+            self.description
+        );
+        dsp::zero_phase_samples(&self.specs, self.sample_rate, &mut samples)
+            .expect("the filters were checked when the transform was made");
+        for (mut timed, sample) in self.held.drain(..).zip(samples) {
+            timed.sample = sample;
+            out.push(timed);
+        }
+    }
+}
+
+impl Stage for Resampler {
+    fn name(&self) -> &str {
+        "resample"
+    }
+}
+
+impl Transform for Resampler {
+    fn process(&mut self, input: TimedSample, out: &mut Vec<TimedSample>) {
+        let mut samples = Vec::new();
+        self.process_sample(&input.sample, &mut samples);
+        out.extend(
+            samples
+                .into_iter()
+                .map(|sample| TimedSample::new(input.host_time, sample)),
+        );
+    }
+}
+
+impl Stage for Montage {
+    fn name(&self) -> &str {
+        "montage"
+    }
+}
+
+impl Transform for Montage {
+    fn process(&mut self, mut input: TimedSample, out: &mut Vec<TimedSample>) {
+        self.apply(&mut input.sample);
+        out.push(input);
+    }
+}
+
+impl Stage for ArtifactDetector {
+    fn name(&self) -> &str {
+        "artifacts"
+    }
+}
+
+impl Transform for ArtifactDetector {
+    fn process(&mut self, input: TimedSample, out: &mut Vec<TimedSample>) {
+        self.process_sample(input, out);
+    }
+
+    fn flush(&mut self, out: &mut Vec<TimedSample>) {
+        ArtifactDetector::flush(self, out);
+        info!(target: PIPELINE_TAG, "Detected {} artifacts", self.events());
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-channel signal quality, judged over consecutive windows of the raw sample stream.
+//!
+//! Each window a channel is checked for samples at the limits of the ADC's range, for a flat
+//! line, for its share of power at the mains frequency, for its RMS amplitude and for the
+//! board's lead-off flags.  These combine into a score from 0 to 100:
+//!
+//! ```text
+//! score = 100 × (1 − railed) × (1 − lead off) × (1 − line noise) × min(1, noisy µV / RMS µV)
+//! ```
+//!
+//! where railed and lead off are the fractions of the window's samples affected, and a flat
+//! channel scores 0.  Powered-down channels aren't scored.
+
+use std::fmt;
+
+use crate::client::sample::Sample;
+use crate::common::config::{BoardConfig, DIGITAL_MAX, DIGITAL_MIN};
+use crate::common::constants::NUM_CHANNELS;
+use crate::dsp::{Band, DspError, DspResult, Taper, Welch, WelchOptions};
+
+// samples within this many counts of full scale count as railed, about 1% of the range
+const RAIL_MARGIN: i32 = 1 << 16;
+
+// the line-noise ratio compares the power within this many Hz of the mains frequency...
+const LINE_WIDTH: f64 = 1.0;
+// ...with the power over the EEG range, up to Nyquist
+const BROADBAND: (f64, f64) = (1.0, 100.0);
+
+// fractions above which a problem is reported
+const LEAD_OFF_THRESHOLD: f64 = 0.5;
+const LINE_NOISE_THRESHOLD: f64 = 0.5;
+
+#[derive(Clone, Debug)]
+pub struct QualityOptions {
+    /// seconds of signal each report covers
+    pub window: f64,
+    /// the mains frequency, usually 50 or 60 Hz
+    pub line_frequency: f64,
+    /// peak-to-peak microvolts below which a channel is flat
+    pub flat_microvolts: f64,
+    /// RMS microvolts above which a channel is noisy
+    pub noisy_microvolts: f64,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        Self {
+            window: 2.0,
+            line_frequency: 60.0,
+            flat_microvolts: 0.5,
+            noisy_microvolts: 100.0,
+        }
+    }
+}
+
+/// Something wrong with a channel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Issue {
+    /// some samples were at the limits of the ADC's range
+    Railed,
+    /// the board flagged an electrode as disconnected for most of the window
+    LeadOff,
+    Flat,
+    /// most of the channel's power is mains hum
+    LineNoise,
+    /// the RMS amplitude is above `QualityOptions::noisy_microvolts`
+    Noisy,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Issue::Railed => "railed",
+            Issue::LeadOff => "lead off",
+            Issue::Flat => "flat",
+            Issue::LineNoise => "line noise",
+            Issue::Noisy => "noisy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One channel's quality over a window
+#[derive(Clone, Debug)]
+pub struct ChannelQuality {
+    pub label: String,
+    /// false if the channel is powered down, when the rest is meaningless
+    pub enabled: bool,
+    /// from 0, unusable, to 100
+    pub score: f64,
+    /// in microvolts, with the mean removed
+    pub rms: f64,
+    pub peak_to_peak: f64,
+    /// fraction of samples within about 1% of full scale
+    pub railed: f64,
+    /// fraction of the 1-100 Hz power within 1 Hz of the mains frequency
+    pub line_noise: f64,
+    /// fractions of samples with the positive and negative electrodes flagged as off
+    pub lead_off_p: f64,
+    pub lead_off_n: f64,
+    pub issues: Vec<Issue>,
+}
+
+/// Every channel's quality over the latest window
+#[derive(Clone, Debug)]
+pub struct QualityReport {
+    /// the sample number of the window's last sample
+    pub sample_number: u32,
+    /// seconds the window covers
+    pub window: f64,
+    pub channels: Vec<ChannelQuality>,
+}
+
+impl QualityReport {
+    /// The mean score of the enabled channels, or None if there are none
+    pub fn overall(&self) -> Option<f64> {
+        let scores: Vec<f64> = self
+            .channels
+            .iter()
+            .filter(|channel| channel.enabled)
+            .map(|channel| channel.score)
+            .collect();
+        if scores.is_empty() {
+            None
+        } else {
+            Some(scores.iter().sum::<f64>() / scores.len() as f64)
+        }
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let overall = match self.overall() {
+            Some(score) => format!("{:.0}", score),
+            None => "-".to_string(),
+        };
+        writeln!(
+            f,
+            "Signal quality over {} s to sample {} (overall {}):",
+            self.window, self.sample_number, overall
+        )?;
+        writeln!(
+            f,
+            "  {:<10} {:>5} {:>9} {:>9} {:>6} {:>6} {:>8}  issues",
+            "channel", "score", "rms µV", "p-p µV", "line", "railed", "lead-off"
+        )?;
+        for channel in &self.channels {
+            if !channel.enabled {
+                writeln!(
+                    f,
+                    "  {:<10} {:>5} {:>9} {:>9} {:>6} {:>6} {:>8}  off",
+                    channel.label, "-", "-", "-", "-", "-", "-"
+                )?;
+                continue;
+            }
+            let mut lead_off = String::new();
+            if channel.lead_off_p > LEAD_OFF_THRESHOLD {
+                lead_off.push('P');
+            }
+            if channel.lead_off_n > LEAD_OFF_THRESHOLD {
+                lead_off.push('N');
+            }
+            if lead_off.is_empty() {
+                lead_off.push('-');
+            }
+            let issues: Vec<String> = channel.issues.iter().map(Issue::to_string).collect();
+            writeln!(
+                f,
+                "  {:<10} {:>5.0} {:>9.1} {:>9.1} {:>5.0}% {:>5.0}% {:>8}  {}",
+                channel.label,
+                channel.score,
+                channel.rms,
+                channel.peak_to_peak,
+                channel.line_noise * 100.0,
+                channel.railed * 100.0,
+                lead_off,
+                issues.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Running totals for one channel over the current window
+#[derive(Copy, Clone)]
+struct Accumulator {
+    sum: f64,
+    sum_squares: f64,
+    min: i32,
+    max: i32,
+    railed: usize,
+    lead_off_p: usize,
+    lead_off_n: usize,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            sum: 0.0,
+            sum_squares: 0.0,
+            min: i32::MAX,
+            max: i32::MIN,
+            railed: 0,
+            lead_off_p: 0,
+            lead_off_n: 0,
+        }
+    }
+}
+
+/// Judges the channels of a stream, producing a `QualityReport` at the end of each window
+pub struct QualityMonitor {
+    config: BoardConfig,
+    options: QualityOptions,
+    window_len: usize,
+    count: usize,
+    accumulators: [Accumulator; NUM_CHANNELS],
+    // the line-noise ratio comes from a spectrum of each whole window
+    welch: Welch,
+    line: Band,
+    broadband: Band,
+    frame: Vec<f64>,
+    report: Option<QualityReport>,
+}
+
+impl QualityMonitor {
+    /// Monitors the channels of `config`, which gives their labels, scales and which are on
+    pub fn new(config: &BoardConfig, options: &QualityOptions) -> DspResult<Self> {
+        let welch_options = WelchOptions {
+            segment: options.window,
+            overlap: 0.0,
+            taper: Taper::Hann,
+            average: 1,
+        };
+        let welch = Welch::new(config.sample_rate, NUM_CHANNELS, &welch_options)?;
+        let nyquist = config.sample_rate as f64 / 2.0;
+        let line = Band::new(
+            "line",
+            options.line_frequency - LINE_WIDTH,
+            options.line_frequency + LINE_WIDTH,
+        );
+        let broadband = Band::new("broadband", BROADBAND.0, BROADBAND.1.min(nyquist));
+        if line.low < broadband.low || line.high > broadband.high {
+            return Err(DspError::BadSpectrum(format!(
+                "A line frequency of {} Hz is outside {}-{} Hz",
+                options.line_frequency, broadband.low, broadband.high
+            )));
+        }
+        welch.check_bands(&[line.clone(), broadband.clone()])?;
+
+        Ok(Self {
+            config: config.clone(),
+            options: options.clone(),
+            window_len: (options.window * config.sample_rate as f64).round() as usize,
+            count: 0,
+            accumulators: [Accumulator::default(); NUM_CHANNELS],
+            welch,
+            line,
+            broadband,
+            frame: vec![0.0; NUM_CHANNELS],
+            report: None,
+        })
+    }
+
+    /// Takes the next sample, returning a new report when it completes a window
+    pub fn push(&mut self, sample: &Sample) -> Option<&QualityReport> {
+        for (chan_idx, (acc, channel)) in self
+            .accumulators
+            .iter_mut()
+            .zip(&sample.channels)
+            .enumerate()
+        {
+            let value = channel.sample;
+            acc.sum += value as f64;
+            acc.sum_squares += value as f64 * value as f64;
+            acc.min = acc.min.min(value);
+            acc.max = acc.max.max(value);
+            if value >= DIGITAL_MAX - RAIL_MARGIN || value <= DIGITAL_MIN + RAIL_MARGIN {
+                acc.railed += 1;
+            }
+            if sample.loff_statp & (1 << chan_idx) != 0 {
+                acc.lead_off_p += 1;
+            }
+            if sample.loff_statn & (1 << chan_idx) != 0 {
+                acc.lead_off_n += 1;
+            }
+            self.frame[chan_idx] = value as f64;
+        }
+        // the spectrum's segments are the windows, so it's ready when they end
+        self.welch.push(&self.frame);
+        self.count += 1;
+        if self.count < self.window_len {
+            return None;
+        }
+
+        let channels = (0..NUM_CHANNELS)
+            .map(|chan_idx| self.judge(chan_idx))
+            .collect();
+        self.report = Some(QualityReport {
+            sample_number: sample.sample_number,
+            window: self.options.window,
+            channels,
+        });
+        self.count = 0;
+        self.accumulators = [Accumulator::default(); NUM_CHANNELS];
+        self.report.as_ref()
+    }
+
+    /// The latest report, once a window has completed
+    pub fn report(&self) -> Option<&QualityReport> {
+        self.report.as_ref()
+    }
+
+    fn judge(&self, chan_idx: usize) -> ChannelQuality {
+        let config = &self.config.channels[chan_idx];
+        let acc = &self.accumulators[chan_idx];
+        let n = self.count as f64;
+        let scale = config.microvolts_per_count();
+
+        let mean = acc.sum / n;
+        let rms = (acc.sum_squares / n - mean * mean).max(0.0).sqrt() * scale;
+        let peak_to_peak = (acc.max as f64 - acc.min as f64) * scale;
+        let railed = acc.railed as f64 / n;
+        let lead_off_p = acc.lead_off_p as f64 / n;
+        let lead_off_n = acc.lead_off_n as f64 / n;
+        let total = self.welch.band_power(chan_idx, &self.broadband);
+        let line_noise = if total > 0.0 {
+            (self.welch.band_power(chan_idx, &self.line) / total).min(1.0)
+        } else {
+            0.0
+        };
+
+        let flat = peak_to_peak < self.options.flat_microvolts;
+        let mut issues = Vec::new();
+        if railed > 0.0 {
+            issues.push(Issue::Railed);
+        }
+        if lead_off_p.max(lead_off_n) > LEAD_OFF_THRESHOLD {
+            issues.push(Issue::LeadOff);
+        }
+        if flat {
+            issues.push(Issue::Flat);
+        }
+        if line_noise > LINE_NOISE_THRESHOLD {
+            issues.push(Issue::LineNoise);
+        }
+        if rms > self.options.noisy_microvolts {
+            issues.push(Issue::Noisy);
+        }
+
+        let score = if !config.enabled || flat {
+            0.0
+        } else {
+            let amplitude = if rms > self.options.noisy_microvolts {
+                self.options.noisy_microvolts / rms
+            } else {
+                1.0
+            };
+            100.0
+                * (1.0 - railed)
+                * (1.0 - lead_off_p.max(lead_off_n))
+                * (1.0 - line_noise)
+                * amplitude
+        };
+
+        ChannelQuality {
+            label: config.label.clone(),
+            enabled: config.enabled,
+            score,
+            rms,
+            peak_to_peak,
+            railed,
+            line_noise,
+            lead_off_p,
+            lead_off_n,
+            issues: if config.enabled { issues } else { Vec::new() },
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::client::err::ClientError;
+
+#[derive(Debug)]
+pub enum RecordError {
+    IOError(std::io::Error),
+    NotARecording,
+    UnsupportedVersion(u16),
+    BadHeader(serde_json::error::Error),
+    DecodeError(ClientError),
+}
+
+impl From<std::io::Error> for RecordError {
+    fn from(e: std::io::Error) -> Self {
+        RecordError::IOError(e)
+    }
+}
+
+impl From<serde_json::error::Error> for RecordError {
+    fn from(e: serde_json::error::Error) -> Self {
+        RecordError::BadHeader(e)
+    }
+}
+
+impl From<ClientError> for RecordError {
+    fn from(e: ClientError) -> Self {
+        RecordError::DecodeError(e)
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            RecordError::IOError(e) => write!(f, "I/O error: {}", e),
+            RecordError::NotARecording => write!(f, "Not a HackEEG recording"),
+            RecordError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported recording format version {}", version)
+            }
+            RecordError::BadHeader(e) => write!(f, "Invalid recording header: {}", e),
+            RecordError::DecodeError(e) => write!(f, "Couldn't decode frame: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Native session recordings.
+//!
+//! A recording stores the frames exactly as they came off the serial port, so replaying one goes
+//! through the same decoding as a live session.  The layout, with all integers little-endian:
+//!
+//! ```text
+//! magic        8 bytes   "HACKEEG\0"
+//! version      u16       FORMAT_VERSION
+//! header_len   u32
+//! header       header_len bytes of JSON (RecordingHeader)
+//! frames, until end of file:
+//!   host_time  f64       seconds, from clock::local_clock() when the frame was received
+//!   frame_len  u32
+//!   frame      frame_len bytes, a MessagePack message or JSON line depending on header.mode
+//! ```
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+mod err;
+
+use crate::client::decode_rdatac_frame;
+use crate::client::modes::Mode;
+use crate::client::sample::Sample;
+pub use err::RecordError;
+
+const RECORD_TAG: &str = "record";
+
+pub const MAGIC: &[u8; 8] = b"HACKEEG\0";
+pub const FORMAT_VERSION: u16 = 1;
+// bytes; an rdatac frame is a few hundred at most, so anything longer is a corrupt length
+const MAX_FRAME_LEN: u32 = 4096;
+
+pub type RecordResult<T> = Result<T, RecordError>;
+
+/// What was connected when the recording was made
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DeviceInfo {
+    pub port_name: String,
+    pub firmware_version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordingHeader {
+    /// Local time the recording started, RFC 3339
+    pub created: String,
+    /// Format of the frames, written as `"jsonlines"` or `"msgpack"`
+    pub mode: Mode,
+    pub sample_rate: u32,
+    /// ADS1299 register values from ID (0x00) through CONFIG4 (0x17), as read at the start
+    pub registers: Vec<u8>,
+    pub device: DeviceInfo,
+}
+
+impl RecordingHeader {
+    pub fn new(mode: Mode, sample_rate: u32, registers: Vec<u8>, device: DeviceInfo) -> Self {
+        Self {
+            created: chrono::Local::now().to_rfc3339(),
+            mode,
+            sample_rate,
+            registers,
+            device,
+        }
+    }
+}
+
+/// A raw frame along with the host time it was received at
+pub struct Frame {
+    pub host_time: f64,
+    pub data: Vec<u8>,
+}
+
+pub struct Recorder<W: Write> {
+    writer: W,
+    frames: u64,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, header: &RecordingHeader) -> RecordResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W, header: &RecordingHeader) -> RecordResult<Self> {
+        let header_json = serde_json::to_vec(header)?;
+        writer.write_all(MAGIC)?;
+        writer.write_u16::<LittleEndian>(FORMAT_VERSION)?;
+        writer.write_u32::<LittleEndian>(header_json.len() as u32)?;
+        writer.write_all(&header_json)?;
+        Ok(Self { writer, frames: 0 })
+    }
+
+    pub fn write_frame(&mut self, host_time: f64, frame: &[u8]) -> RecordResult<()> {
+        self.writer.write_f64::<LittleEndian>(host_time)?;
+        self.writer.write_u32::<LittleEndian>(frame.len() as u32)?;
+        self.writer.write_all(frame)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Flushes the recording and returns the underlying writer
+    pub fn finish(mut self) -> RecordResult<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads a recording back, optionally pacing frames at the rate they were recorded.
+pub struct Replayer<R: Read> {
+    reader: R,
+    header: RecordingHeader,
+    realtime: bool,
+    // wall clock and recorded host time of the first frame, when pacing
+    started: Option<(Instant, f64)>,
+}
+
+impl Replayer<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> RecordResult<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Replayer<R> {
+    pub fn new(mut reader: R) -> RecordResult<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(RecordError::NotARecording);
+        }
+
+        let version = reader.read_u16::<LittleEndian>()?;
+        if version != FORMAT_VERSION {
+            return Err(RecordError::UnsupportedVersion(version));
+        }
+
+        let header_len = reader.read_u32::<LittleEndian>()?;
+        let mut header_json = vec![0; header_len as usize];
+        reader.read_exact(&mut header_json)?;
+        let header = serde_json::from_slice(&header_json)?;
+
+        Ok(Self {
+            reader,
+            header,
+            realtime: false,
+            started: None,
+        })
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// When enabled, `next_frame` sleeps so frames come out with the same spacing they were
+    /// recorded with
+    pub fn set_realtime(&mut self, realtime: bool) {
+        self.realtime = realtime;
+        self.started = None;
+    }
+
+    /// Returns the next frame, or `None` at the end of the recording.  A frame cut short by the
+    /// end of the file, e.g. because the recording process was killed, also ends the recording.
+    pub fn next_frame(&mut self) -> RecordResult<Option<Frame>> {
+        let host_time = match self.reader.read_f64::<LittleEndian>() {
+            Ok(host_time) => host_time,
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let data = match self.read_frame_data() {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                warn!(target: RECORD_TAG, "Recording ends with a truncated frame");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if self.realtime {
+            let (started_at, first_host_time) =
+                *self.started.get_or_insert((Instant::now(), host_time));
+            let due = Duration::from_secs_f64((host_time - first_host_time).max(0.0));
+            let elapsed = started_at.elapsed();
+            if due > elapsed {
+                std::thread::sleep(due - elapsed);
+            }
+        }
+
+        Ok(Some(Frame { host_time, data }))
+    }
+
+    fn read_frame_data(&mut self) -> std::io::Result<Vec<u8>> {
+        let frame_len = self.reader.read_u32::<LittleEndian>()?;
+        if frame_len > MAX_FRAME_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("frame length {} is more than {}", frame_len, MAX_FRAME_LEN),
+            ));
+        }
+        let mut data = vec![0; frame_len as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Returns the next frame decoded into a `Sample`, along with its host receive time
+    pub fn next_sample(&mut self) -> RecordResult<Option<(f64, Sample)>> {
+        match self.next_frame()? {
+            Some(frame) => {
+                let sample = decode_rdatac_frame(self.header.mode, &frame.data)?;
+                Ok(Some((frame.host_time, sample)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Replayer<R> {
+    type Item = RecordResult<(f64, Sample)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_sample().transpose()
+    }
+}
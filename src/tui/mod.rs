@@ -0,0 +1,636 @@
+// Copyright © 2020 Starcat LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A live view of a stream in the terminal: a scrolling, autoscaled trace per channel, the
+//! board's lead-off flags, and how acquisition is keeping up.
+//!
+//! The screen is drawn on its own thread from what a `TuiSink` collects, so a slow terminal
+//! never holds up the pipeline.  Keys that act on the board become `TuiCommand`s, which
+//! whatever owns the client picks up with `Tui::poll_command`; it reports back with
+//! `set_acquiring`, `set_gain`, `count_sample` and `add_error`.
+//!
+//! While the screen is up, log messages can't go to stdout, so a program showing it should set
+//! up logging with `log_message` as the output, which keeps the latest for a panel at the bottom.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::client::sample::Sample;
+use crate::common::config::{BoardConfig, ChannelConfig};
+use crate::common::constants::ads1299::Gain;
+use crate::pipeline::{Sink, SinkError, Stage, TimedSample};
+
+const TUI_TAG: &str = "tui";
+
+// the gains the + and - keys step through
+const GAINS: [Gain; 7] = [
+    Gain::X1,
+    Gain::X2,
+    Gain::X4,
+    Gain::X6,
+    Gain::X8,
+    Gain::X12,
+    Gain::X24,
+];
+
+const REFRESH: Duration = Duration::from_millis(50);
+// how often the measured sample rate is updated
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+const LABEL_WIDTH: u16 = 14;
+const LOG_LINES: usize = 4;
+// the smallest half-range a trace is scaled to, so a flat channel doesn't show noise in the LSBs
+const MIN_SCALE_MICROVOLTS: f64 = 1.0;
+
+// log messages waiting for the log panel, and whether it's showing
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static SHOWING: AtomicBool = AtomicBool::new(false);
+
+/// A log output for programs that show the TUI, for `common::log::setup_logger_with_output`:
+/// messages go to the log panel while the screen is up, and to stdout otherwise
+pub fn log_message(message: &str) {
+    if SHOWING.load(Ordering::Relaxed) {
+        let mut log = LOG.lock().unwrap();
+        if log.len() == LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(message.to_string());
+    } else {
+        println!("{}", message);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TuiOptions {
+    /// seconds of signal each trace shows
+    pub seconds: f64,
+    /// refuse gain changes, e.g. because files or network clients were told the gain at the start
+    pub gain_locked: bool,
+}
+
+impl Default for TuiOptions {
+    fn default() -> Self {
+        Self {
+            seconds: 5.0,
+            gain_locked: false,
+        }
+    }
+}
+
+/// What the keys ask of the board
+#[derive(Clone, Debug)]
+pub enum TuiCommand {
+    Start,
+    Stop,
+    /// set every enabled channel to this gain
+    SetGain(Gain),
+    /// mark the stream at the next sample with this text
+    Marker(String),
+}
+
+// what the source reports about acquisition
+struct Acquisition {
+    acquiring: bool,
+    last_sample_number: Option<u32>,
+    missed: u64,
+    // samples counted since `rate_start`
+    rate_start: Option<Instant>,
+    rate_samples: u64,
+    rate: Option<f64>,
+}
+
+struct Traces {
+    channels: Vec<ChannelConfig>,
+    values: Vec<VecDeque<f64>>,
+    capacity: usize,
+    lead_off_p: u8,
+    lead_off_n: u8,
+}
+
+struct Shared {
+    traces: Mutex<Traces>,
+    acquisition: Mutex<Acquisition>,
+    errors: AtomicU64,
+    // set once the stream has ended, to close the screen
+    finished: AtomicBool,
+}
+
+/// The screen, and the handle for whatever controls acquisition
+pub struct Tui {
+    shared: Arc<Shared>,
+    commands: Receiver<TuiCommand>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Tui {
+    /// Takes over the terminal and starts drawing `config`'s channels from what the returned
+    /// sink is given.  The q key sets `quit`.
+    pub fn start(
+        config: &BoardConfig,
+        options: &TuiOptions,
+        quit: Arc<AtomicBool>,
+    ) -> io::Result<(Self, TuiSink)> {
+        let capacity = ((config.sample_rate as f64 * options.seconds).ceil() as usize).max(2);
+        let shared = Arc::new(Shared {
+            traces: Mutex::new(Traces {
+                channels: config.channels.clone(),
+                values: vec![VecDeque::with_capacity(capacity); config.channels.len()],
+                capacity,
+                lead_off_p: 0,
+                lead_off_n: 0,
+            }),
+            acquisition: Mutex::new(Acquisition {
+                acquiring: true,
+                last_sample_number: None,
+                missed: 0,
+                rate_start: None,
+                rate_samples: 0,
+                rate: None,
+            }),
+            errors: AtomicU64::new(0),
+            finished: AtomicBool::new(false),
+        });
+
+        let terminal = ratatui::try_init()?;
+        SHOWING.store(true, Ordering::Relaxed);
+        let (sender, commands) = mpsc::channel();
+        let screen = Screen {
+            shared: shared.clone(),
+            commands: sender,
+            quit,
+            sample_rate: config.sample_rate,
+            seconds: options.seconds,
+            gain_locked: options.gain_locked,
+            shown: vec![true; config.channels.len()],
+            markers: 0,
+        };
+        let thread = std::thread::Builder::new()
+            .name("tui".to_string())
+            .spawn(move || screen.run(terminal))?;
+
+        let sink = TuiSink {
+            shared: shared.clone(),
+        };
+        Ok((
+            Self {
+                shared,
+                commands,
+                thread: Some(thread),
+            },
+            sink,
+        ))
+    }
+
+    /// The next command from the keyboard, if any
+    pub fn poll_command(&self) -> Option<TuiCommand> {
+        self.commands.try_recv().ok()
+    }
+
+    pub fn set_acquiring(&self, acquiring: bool) {
+        let mut acquisition = self.shared.acquisition.lock().unwrap();
+        acquisition.acquiring = acquiring;
+        // neither the rate nor the missed samples should count the pause
+        acquisition.rate_start = None;
+        acquisition.rate_samples = 0;
+        acquisition.last_sample_number = None;
+    }
+
+    /// Rescales the traces for samples taken at `gain`
+    pub fn set_gain(&self, gain: Gain) {
+        let mut traces = self.shared.traces.lock().unwrap();
+        for channel in traces.channels.iter_mut() {
+            channel.gain = gain.multiplier();
+        }
+        for values in traces.values.iter_mut() {
+            values.clear();
+        }
+    }
+
+    /// Notes a sample as it comes from the board, before anything can drop it, to measure the
+    /// sample rate and count the samples the board missed
+    pub fn count_sample(&self, sample: &Sample) {
+        let mut acquisition = self.shared.acquisition.lock().unwrap();
+        if let Some(last) = acquisition.last_sample_number {
+            let missing = sample.sample_number.wrapping_sub(last).wrapping_sub(1);
+            if missing > 0 && missing < u32::MAX / 2 {
+                acquisition.missed += missing as u64;
+            }
+        }
+        acquisition.last_sample_number = Some(sample.sample_number);
+
+        let now = Instant::now();
+        match acquisition.rate_start {
+            None => {
+                acquisition.rate_start = Some(now);
+                acquisition.rate_samples = 0;
+            }
+            Some(start) => {
+                acquisition.rate_samples += 1;
+                let elapsed = now - start;
+                if elapsed >= RATE_INTERVAL {
+                    acquisition.rate =
+                        Some(acquisition.rate_samples as f64 / elapsed.as_secs_f64());
+                    acquisition.rate_start = Some(now);
+                    acquisition.rate_samples = 0;
+                }
+            }
+        }
+    }
+
+    /// Counts a frame that couldn't be read or decoded
+    pub fn add_error(&self) {
+        self.shared.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Closes the screen and gives the terminal back
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.shared.finished.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("the TUI panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        // e.g. returning early with an error; the terminal has to be restored before it's shown
+        let _ = self.close();
+    }
+}
+
+/// Collects samples for the traces
+pub struct TuiSink {
+    shared: Arc<Shared>,
+}
+
+impl Stage for TuiSink {
+    fn name(&self) -> &str {
+        "tui"
+    }
+}
+
+impl Sink for TuiSink {
+    fn consume(&mut self, timed: &TimedSample) -> Result<(), SinkError> {
+        let sample = &timed.sample;
+        let mut traces = self.shared.traces.lock().unwrap();
+        let traces = &mut *traces;
+        for ((channel, values), counts) in traces
+            .channels
+            .iter()
+            .zip(traces.values.iter_mut())
+            .zip(sample.channels.iter())
+        {
+            if values.len() == traces.capacity {
+                values.pop_front();
+            }
+            values.push_back(channel.to_microvolts(counts.sample));
+        }
+        traces.lead_off_p = sample.loff_statp;
+        traces.lead_off_n = sample.loff_statn;
+        Ok(())
+    }
+}
+
+// the drawing thread's state
+struct Screen {
+    shared: Arc<Shared>,
+    commands: Sender<TuiCommand>,
+    quit: Arc<AtomicBool>,
+    sample_rate: u32,
+    seconds: f64,
+    gain_locked: bool,
+    // channels the user hasn't hidden
+    shown: Vec<bool>,
+    markers: u32,
+}
+
+impl Screen {
+    fn run(mut self, mut terminal: DefaultTerminal) -> io::Result<()> {
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        SHOWING.store(false, Ordering::Relaxed);
+        // what was logged last may explain why the screen closed
+        for message in LOG.lock().unwrap().drain(..) {
+            println!("{}", message);
+        }
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.shared.finished.load(Ordering::Relaxed) && !self.quit.load(Ordering::Relaxed) {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(REFRESH)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit.store(true, Ordering::Relaxed),
+            // the terminal is raw, so ^C is a key rather than SIGINT
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.quit.store(true, Ordering::Relaxed)
+            }
+            KeyCode::Char(' ') => {
+                let acquiring = self.shared.acquisition.lock().unwrap().acquiring;
+                self.send(if acquiring {
+                    TuiCommand::Stop
+                } else {
+                    TuiCommand::Start
+                });
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => self.step_gain(1),
+            KeyCode::Char('-') => self.step_gain(-1),
+            KeyCode::Char('m') => {
+                self.markers += 1;
+                self.send(TuiCommand::Marker(format!("Marker {}", self.markers)));
+            }
+            KeyCode::Char(c @ '1'..='9') => {
+                let chan_idx = c as usize - '1' as usize;
+                if let Some(shown) = self.shown.get_mut(chan_idx) {
+                    *shown = !*shown;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn step_gain(&self, step: isize) {
+        if self.gain_locked {
+            warn!(
+                target: TUI_TAG,
+                "The gain can't change while recording or streaming, which assume the gain they started with"
+            );
+            return;
+        }
+        let current = match self.gain() {
+            Some(gain) => gain,
+            None => return,
+        };
+        let index = GAINS
+            .iter()
+            .position(|gain| gain.multiplier() == current)
+            .unwrap_or(0) as isize;
+        let next = (index + step).clamp(0, GAINS.len() as isize - 1) as usize;
+        if next as isize != index {
+            self.send(TuiCommand::SetGain(GAINS[next]));
+        }
+    }
+
+    // the gain of the first enabled channel, which the keys set every channel to
+    fn gain(&self) -> Option<u32> {
+        let traces = self.shared.traces.lock().unwrap();
+        traces
+            .channels
+            .iter()
+            .find(|channel| channel.enabled)
+            .map(|channel| channel.gain)
+    }
+
+    fn send(&self, command: TuiCommand) {
+        if self.commands.send(command).is_err() {
+            info!(target: TUI_TAG, "Nothing is taking commands any more");
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [traces_area, log_area, status_area] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(LOG_LINES as u16 + 1),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+        self.draw_traces(frame, traces_area);
+        self.draw_log(frame, log_area);
+        self.draw_status(frame, status_area);
+    }
+
+    fn draw_traces(&self, frame: &mut Frame, area: Rect) {
+        let traces = self.shared.traces.lock().unwrap();
+        let visible: Vec<usize> = (0..traces.channels.len())
+            .filter(|&chan_idx| traces.channels[chan_idx].enabled && self.shown[chan_idx])
+            .collect();
+        if visible.is_empty() {
+            frame.render_widget(
+                Paragraph::new("No channels shown; press 1-8 to show them"),
+                area,
+            );
+            return;
+        }
+
+        let rows = Layout::vertical(vec![
+            Constraint::Ratio(1, visible.len() as u32);
+            visible.len()
+        ])
+        .split(area);
+        for (&chan_idx, row) in visible.iter().zip(rows.iter()) {
+            let [label_area, chart_area] =
+                Layout::horizontal([Constraint::Length(LABEL_WIDTH), Constraint::Min(1)])
+                    .areas(*row);
+            let values = &traces.values[chan_idx];
+            // braille cells are two dots wide
+            let points = envelope(values, traces.capacity, chart_area.width as usize * 2);
+            let (center, scale) = autoscale(values);
+
+            let lead_off_p = traces.lead_off_p & (1 << chan_idx) != 0;
+            let lead_off_n = traces.lead_off_n & (1 << chan_idx) != 0;
+            let mut title = vec![Span::styled(
+                format!("{:<6}", traces.channels[chan_idx].label),
+                Style::default().add_modifier(Modifier::BOLD),
+            )];
+            if lead_off_p || lead_off_n {
+                let flags = match (lead_off_p, lead_off_n) {
+                    (true, true) => "off P N",
+                    (true, false) => "off P",
+                    _ => "off N",
+                };
+                title.push(Span::styled(flags, Style::default().fg(Color::Red)));
+            }
+            let label = Paragraph::new(vec![
+                Line::from(title),
+                Line::from(format!("±{}", format_scale(scale))),
+            ]);
+            frame.render_widget(label, label_area);
+
+            let color = if lead_off_p || lead_off_n {
+                Color::Red
+            } else {
+                Color::Cyan
+            };
+            let dataset = Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(&points);
+            let chart = Chart::new(vec![dataset])
+                .x_axis(Axis::default().bounds([0.0, traces.capacity as f64]))
+                .y_axis(Axis::default().bounds([center - scale, center + scale]));
+            frame.render_widget(chart, chart_area);
+        }
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = LOG
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|message| Line::from(message.clone()))
+            .collect();
+        let log = Paragraph::new(lines)
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::TOP).title("Log"));
+        frame.render_widget(log, area);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let (acquiring, rate, missed) = {
+            let acquisition = self.shared.acquisition.lock().unwrap();
+            (acquisition.acquiring, acquisition.rate, acquisition.missed)
+        };
+        let errors = self.shared.errors.load(Ordering::Relaxed);
+
+        let state = if acquiring {
+            Span::styled("● acquiring", Style::default().fg(Color::Green))
+        } else {
+            Span::styled("■ paused", Style::default().fg(Color::Yellow))
+        };
+        let rate = match rate {
+            Some(rate) if acquiring => format!("{:.1}", rate),
+            _ => "-".to_string(),
+        };
+        let problem = |count: u64| {
+            if count > 0 {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            }
+        };
+        let mut status = vec![
+            state,
+            Span::raw(format!(
+                "  {} SPS ({} measured)  {} s shown",
+                self.sample_rate, rate, self.seconds
+            )),
+        ];
+        if let Some(gain) = self.gain() {
+            status.push(Span::raw(format!("  gain ×{}", gain)));
+            if self.gain_locked {
+                status.push(Span::raw(" (fixed)"));
+            }
+        }
+        status.push(Span::styled(
+            format!("  missed {}", missed),
+            problem(missed),
+        ));
+        status.push(Span::styled(
+            format!("  errors {}", errors),
+            problem(errors),
+        ));
+        status.push(Span::raw(format!("  markers {}", self.markers)));
+
+        let help = Line::styled(
+            "space start/stop  +/- gain  1-8 show/hide channel  m marker  q quit",
+            Style::default().fg(Color::DarkGray),
+        );
+        frame.render_widget(Paragraph::new(vec![Line::from(status), help]), area);
+    }
+}
+
+/// Points for a trace `width` dots wide, placed so the newest sample is at the right edge of
+/// `capacity` samples.  When there are more samples than dots, each dot gets the minimum and
+/// maximum of its samples, so spikes aren't lost.
+fn envelope(values: &VecDeque<f64>, capacity: usize, width: usize) -> Vec<(f64, f64)> {
+    let offset = (capacity - values.len()) as f64;
+    if values.len() <= width || width == 0 {
+        return values
+            .iter()
+            .enumerate()
+            .map(|(index, &value)| (offset + index as f64, value))
+            .collect();
+    }
+
+    let per_dot = values.len() as f64 / width as f64;
+    let mut points = Vec::with_capacity(width * 2);
+    for dot in 0..width {
+        let start = (dot as f64 * per_dot) as usize;
+        let end = (((dot + 1) as f64 * per_dot) as usize).min(values.len());
+        let (min, max) = values
+            .range(start..end)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &value| {
+                (min.min(value), max.max(value))
+            });
+        if min <= max {
+            let x = offset + start as f64;
+            points.push((x, min));
+            points.push((x, max));
+        }
+    }
+    points
+}
+
+/// The center and half-range of the vertical axis for a trace: its mean, and the furthest any
+/// sample gets from it, rounded up to 1, 2 or 5 times a power of ten so the scale doesn't jitter
+fn autoscale(values: &VecDeque<f64>) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, MIN_SCALE_MICROVOLTS);
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let furthest = values
+        .iter()
+        .map(|value| (value - mean).abs())
+        .fold(0.0, f64::max)
+        .max(MIN_SCALE_MICROVOLTS);
+
+    let magnitude = 10f64.powi(furthest.log10().floor() as i32);
+    let scale = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|step| step * magnitude)
+        .find(|&scale| scale >= furthest)
+        .unwrap_or(10.0 * magnitude);
+    (mean, scale)
+}
+
+fn format_scale(scale: f64) -> String {
+    if scale >= 1000.0 {
+        format!("{} mV", scale / 1000.0)
+    } else {
+        format!("{} µV", scale)
+    }
+}